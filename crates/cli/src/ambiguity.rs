@@ -0,0 +1,149 @@
+use anyhow::Result;
+use icfpc2025_solver::{canonical_hash, explore_until, StopCondition};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Write};
+
+use crate::trials::run_trials;
+use crate::StrategyName;
+
+/// Width of the longest bar in the histogram.
+const BAR_WIDTH: usize = 40;
+
+/// Outcome of `ambiguity`.
+#[derive(Debug, Serialize)]
+pub struct AmbiguitySummary {
+    pub rooms: usize,
+    pub strategy: StrategyName,
+    pub trials: usize,
+    /// How many trials ended with each number of distinct candidates.
+    pub histogram: BTreeMap<usize, usize>,
+    /// Trials that ended with a single candidate from a complete search.
+    pub unique: usize,
+    /// Trials whose reconstruction was cut short, so their count is only a lower bound.
+    pub inexhaustive: usize,
+    /// Trials that errored out and are left out of the histogram.
+    pub errors: usize,
+}
+
+impl AmbiguitySummary {
+    /// Fraction of the trials that ended with exactly one candidate from a complete search.
+    pub fn unique_fraction(&self) -> f64 {
+        if self.trials == 0 {
+            return 0.0;
+        }
+        self.unique as f64 / self.trials as f64
+    }
+}
+
+impl fmt::Display for AmbiguitySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{} rooms, {}: {}/{} uniquely determined ({:.1}%)",
+            self.rooms,
+            self.strategy,
+            self.unique,
+            self.trials,
+            100.0 * self.unique_fraction()
+        );
+        let _ = writeln!(out, "{:>10}{:>8}", "candidates", "trials");
+        let most = self.histogram.values().copied().max().unwrap_or(0).max(1);
+        for (&candidates, &trials) in &self.histogram {
+            let bar = (trials * BAR_WIDTH).div_ceil(most);
+            let _ = writeln!(out, "{:>10}{:>8}  {}", candidates, trials, "#".repeat(bar));
+        }
+        if self.inexhaustive > 0 {
+            let _ = writeln!(
+                out,
+                "{} trials stopped searching early and may have more candidates",
+                self.inexhaustive
+            );
+        }
+        if self.errors > 0 {
+            let _ = writeln!(out, "{} trials failed", self.errors);
+        }
+        f.write_str(out.trim_end())
+    }
+}
+
+/// Explores `trials` regular libraries generated from consecutive seeds starting at
+/// `first_seed` with `strategy` until the reconstruction is unique or the budget runs out, and
+/// counts the candidates left, up to renumbering of the rooms. Unlike `solve`, no budget goes
+/// into telling the candidates apart, so this shows how often that is needed.
+pub async fn ambiguity(
+    rooms: usize,
+    trials: usize,
+    first_seed: u64,
+    budget: usize,
+    strategy: StrategyName,
+) -> Result<AmbiguitySummary> {
+    let config = strategy.config(rooms, budget);
+    let mut summary = AmbiguitySummary {
+        rooms,
+        strategy,
+        trials,
+        histogram: BTreeMap::new(),
+        unique: 0,
+        inexhaustive: 0,
+        errors: 0,
+    };
+    let outcomes = run_trials(rooms, trials, first_seed, async |simulator, rng| {
+        let mut explorer = strategy.strategy(rooms);
+        let stop = StopCondition::UniqueReconstruction;
+        explore_until(simulator, &mut *explorer, stop, &config, rng).await
+    })
+    .await?;
+    for (_, outcome) in outcomes {
+        let Some(outcome) = outcome else {
+            summary.errors += 1;
+            continue;
+        };
+        let (candidates, exhaustive) = match outcome.reconstruction {
+            Some(r) => {
+                let distinct = r
+                    .candidates
+                    .iter()
+                    .map(|map| Ok(canonical_hash(&map.canonicalize()?)))
+                    .collect::<Result<HashSet<u64>>>()?;
+                (distinct.len(), r.exhaustive)
+            }
+            None => (0, true),
+        };
+        *summary.histogram.entry(candidates).or_default() += 1;
+        if !exhaustive {
+            summary.inexhaustive += 1;
+        } else if candidates == 1 {
+            summary.unique += 1;
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_ambiguity_smoke() {
+        // Seed 0 leaves two libraries that explain everything observed, seed 1 only one.
+        let summary = ambiguity(3, 2, 0, 54, StrategyName::Frontier)
+            .await
+            .unwrap();
+        assert_eq!(summary.histogram, BTreeMap::from([(1, 1), (2, 1)]));
+        assert_eq!((summary.unique, summary.inexhaustive), (1, 0));
+        assert_eq!(summary.errors, 0);
+
+        let table = summary.to_string();
+        assert!(table.starts_with("3 rooms, frontier: "), "{}", table);
+        assert_eq!(table.lines().count(), 4, "{}", table);
+
+        // Without a budget nothing is observed, which nothing pins down.
+        let summary = ambiguity(2, 2, 0, 0, StrategyName::RandomWalk)
+            .await
+            .unwrap();
+        assert_eq!(summary.histogram, BTreeMap::from([(0, 2)]));
+        assert_eq!(summary.unique_fraction(), 0.0);
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::Result;
+use serde::Serialize;
+use std::fmt::{self, Write};
+use std::time::Instant;
+
+use crate::trials::run_trials;
+use crate::{solve_with, StrategyName};
+
+/// Mean and median of one measurement over the solves that finished.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct Stats {
+    pub mean: f64,
+    pub median: f64,
+}
+
+impl Stats {
+    fn of(mut values: Vec<f64>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_by(f64::total_cmp);
+        let mid = values.len() / 2;
+        let median = if values.len().is_multiple_of(2) {
+            (values[mid - 1] + values[mid]) / 2.0
+        } else {
+            values[mid]
+        };
+        Self {
+            mean: values.iter().sum::<f64>() / values.len() as f64,
+            median,
+        }
+    }
+}
+
+/// Outcome of `bench`.
+#[derive(Debug, Serialize)]
+pub struct BenchSummary {
+    pub rooms: usize,
+    pub strategy: StrategyName,
+    pub trials: usize,
+    pub solved: usize,
+    pub explores: Stats,
+    pub doorways: Stats,
+    pub seconds: Stats,
+}
+
+impl BenchSummary {
+    pub fn solve_rate(&self) -> f64 {
+        if self.trials == 0 {
+            return 0.0;
+        }
+        self.solved as f64 / self.trials as f64
+    }
+}
+
+impl fmt::Display for BenchSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{} rooms, {}: {}/{} solved ({:.1}%)",
+            self.rooms,
+            self.strategy,
+            self.solved,
+            self.trials,
+            100.0 * self.solve_rate()
+        );
+        let _ = writeln!(out, "{:<10}{:>10}{:>10}", "", "mean", "median");
+        for (name, stats, precision) in [
+            ("explores", self.explores, 1),
+            ("doorways", self.doorways, 1),
+            ("seconds", self.seconds, 3),
+        ] {
+            let _ = writeln!(
+                out,
+                "{:<10}{:>10.*}{:>10.*}",
+                name, precision, stats.mean, precision, stats.median
+            );
+        }
+        f.write_str(out.trim_end())
+    }
+}
+
+/// Solves `trials` regular libraries generated from consecutive seeds starting at `first_seed`
+/// with `strategy`, timing each solve. Solves that error out count as unsolved and are left
+/// out of the statistics.
+pub async fn bench(
+    rooms: usize,
+    trials: usize,
+    first_seed: u64,
+    budget: usize,
+    strategy: StrategyName,
+) -> Result<BenchSummary> {
+    let config = strategy.config(rooms, budget);
+    let mut solved = 0;
+    let (mut explores, mut doorways, mut seconds) = (Vec::new(), Vec::new(), Vec::new());
+    let outcomes = run_trials(rooms, trials, first_seed, async |simulator, rng| {
+        let started = Instant::now();
+        let report = solve_with(simulator, strategy, &config, rng).await?;
+        Ok((report, started.elapsed()))
+    })
+    .await?;
+    for (report, elapsed) in outcomes.into_iter().filter_map(|(_, outcome)| outcome) {
+        seconds.push(elapsed.as_secs_f64());
+        explores.push(report.explores as f64);
+        doorways.push(report.doorways as f64);
+        solved += usize::from(report.correct);
+    }
+    Ok(BenchSummary {
+        rooms,
+        strategy,
+        trials,
+        solved,
+        explores: Stats::of(explores),
+        doorways: Stats::of(doorways),
+        seconds: Stats::of(seconds),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(
+            Stats::of(vec![3.0, 1.0, 2.0, 10.0]),
+            Stats {
+                mean: 4.0,
+                median: 2.5
+            }
+        );
+        assert_eq!(Stats::of(vec![]), Stats::default());
+    }
+
+    #[tokio::test]
+    async fn test_bench_smoke() {
+        for strategy in [StrategyName::Frontier, StrategyName::RandomWalk] {
+            let summary = bench(2, 2, 0, 36, strategy).await.unwrap();
+            assert_eq!(summary.trials, 2);
+            assert!(summary.solved > 0, "{:?}", summary);
+            assert!(summary.explores.mean >= 1.0);
+
+            let table = summary.to_string();
+            assert_eq!(table.lines().count(), 5, "{}", table);
+            assert!(table.starts_with(&format!("2 rooms, {}: ", strategy)));
+        }
+    }
+}
@@ -1,21 +1,96 @@
 use anyhow::Result;
-use clap::{Parser, Subcommand};
-use icfpc2025_client::AedificiumRemoteClient;
-use icfpc2025_common::{AedificiumClient, Map};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use icfpc2025_client::{AedificiumRemoteClient, ClientBuilder};
+use icfpc2025_common::{doorway_budget, AedificiumClient, Map, ProblemInfo, DOORS, RESET};
+use icfpc2025_simulator::{GenerationMode, Simulator};
+use icfpc2025_solver::{
+    driver, reconstruct, ExploreConfig, ExploreStrategy, Frontier, RandomWalk, ReconstructOptions,
+    RunLog, Shutdown, SolveReport,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::env;
+use std::fmt;
+use std::fs;
 use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+mod ambiguity;
+mod bench;
+mod minimize;
+mod replay;
+mod trials;
 
 #[derive(Parser)]
 #[command(name = "aedificium")]
 #[command(about = "ICFPC 2025 Aedificium contest CLI tool")]
 #[command(version = "0.1.0")]
 struct Cli {
+    #[command(flatten)]
+    options: ClientOptions,
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Args)]
+struct ClientOptions {
+    #[arg(
+        long,
+        global = true,
+        help = "Send select/explore/guess to an in-process simulator instead of the API"
+    )]
+    offline: bool,
+    #[arg(long, global = true, help = "Number of rooms in the library")]
+    rooms: Option<usize>,
+    #[arg(
+        long,
+        global = true,
+        help = "Seed for the offline library (0 by default) and for generation"
+    )]
+    seed: Option<u64>,
+    #[arg(
+        long,
+        global = true,
+        help = "Print API requests to stderr instead of sending them, and answer with stub responses"
+    )]
+    dry_run: bool,
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = FileFormat::Json,
+        help = "Format of the map and session files written and read"
+    )]
+    format: FileFormat,
+    #[cfg(feature = "keyring")]
+    #[arg(
+        long,
+        global = true,
+        help = "Read the team id from the OS secret store (see `login`), falling back to ICFPC_TEAM_ID"
+    )]
+    from_keyring: bool,
+}
+
+/// How maps and sessions are stored on disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FileFormat {
+    Json,
+    /// The compact encoding of `icfpc2025_common::binary`.
+    Binary,
+}
+
 #[derive(Subcommand)]
 enum Commands {
+    #[cfg(feature = "keyring")]
+    #[command(about = "Store the team id in the OS secret store, for --from-keyring")]
+    Login {
+        #[arg(help = "Team id, or read from stdin if not provided")]
+        team_id: Option<String>,
+    },
+    #[cfg(feature = "keyring")]
+    #[command(about = "Remove the team id from the OS secret store")]
+    Logout,
     #[command(about = "Select a problem")]
     Select {
         #[arg(help = "Problem name, or read from stdin if not provided")]
@@ -23,14 +98,143 @@ enum Commands {
     },
     #[command(about = "Explore with plans")]
     Explore {
-        #[arg(help = "Plans (comma-separated), or read from stdin if not provided")]
+        #[arg(help = "Plans as a JSON array, or read from stdin if not provided")]
         plans: Option<String>,
+        #[arg(long, help = "Plans are comma-separated instead of a JSON array")]
+        raw: bool,
     },
     #[command(about = "Submit a guess")]
     Guess {
         #[arg(help = "Map data as JSON string, or read from stdin if not provided")]
         map: Option<String>,
     },
+    #[command(
+        about = "Reconstruct a map from explore results read from stdin, without network access"
+    )]
+    Reconstruct {
+        #[arg(
+            long,
+            help = "Plans as a JSON array; required unless the stdin JSON has a `plans` field"
+        )]
+        plans: Option<String>,
+        #[arg(
+            long,
+            help = "Collapse rooms that look alike up to this many doors ahead into one"
+        )]
+        refine_depth: Option<usize>,
+    },
+    #[command(about = "Generate a random library and save its map for offline solving")]
+    Gen {
+        #[arg(long, help = "File to write the library's map JSON to")]
+        out: PathBuf,
+    },
+    #[command(about = "Explore a library until its map is determined, then guess it")]
+    Solve {
+        #[arg(
+            long,
+            help = "Problem to select; required unless --simulator or --offline is given"
+        )]
+        problem: Option<String>,
+        #[arg(
+            long,
+            help = "Solve the map saved by `gen` in a local simulator instead of the API"
+        )]
+        simulator: Option<PathBuf>,
+        #[arg(long, help = "Doorways to spend at most; defaults to 18 per room")]
+        budget: Option<usize>,
+        #[arg(
+            long,
+            help = "Keep the best candidate map so far in this file, even if the solve fails"
+        )]
+        checkpoint: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Write a JSON report of the run, with every explore and the guess, to a new file in this directory"
+        )]
+        json_logs: Option<PathBuf>,
+        #[arg(
+            long,
+            help = "Tune the number of plans per explore call to the measured latency"
+        )]
+        auto_batch: bool,
+        #[arg(long, value_enum, default_value_t = StrategyName::Frontier)]
+        strategy: StrategyName,
+        #[arg(
+            long,
+            help = "On Ctrl-C, exit without guessing instead of guessing the best candidate so far"
+        )]
+        no_guess_on_exit: bool,
+    },
+    #[command(about = "Solve offline libraries and print explore, doorway and time statistics")]
+    Bench {
+        #[arg(long, default_value_t = 20, help = "Number of libraries to solve")]
+        trials: usize,
+        #[arg(
+            long,
+            help = "Doorways to spend at most per trial; defaults to 18 per room"
+        )]
+        budget: Option<usize>,
+        #[arg(long, value_enum, default_value_t = StrategyName::Frontier)]
+        strategy: StrategyName,
+        #[arg(
+            long,
+            default_value_t = 0.9,
+            help = "Fail if fewer than this fraction of the trials are solved"
+        )]
+        min_solve_rate: f64,
+    },
+    #[command(
+        about = "Explore offline libraries and show how many candidate maps the observations leave"
+    )]
+    Ambiguity {
+        #[arg(long, default_value_t = 20, help = "Number of libraries to explore")]
+        trials: usize,
+        #[arg(
+            long,
+            help = "Doorways to spend at most per trial; defaults to 18 per room"
+        )]
+        budget: Option<usize>,
+        #[arg(long, value_enum, default_value_t = StrategyName::Frontier)]
+        strategy: StrategyName,
+    },
+    #[command(
+        about = "Shrink a plan set that reconstructs the offline library wrongly or ambiguously"
+    )]
+    Minimize {
+        #[arg(long, help = "File holding the plans as a JSON array")]
+        plans: PathBuf,
+    },
+    #[command(
+        about = "Walk the plans of a saved session over a map and show where it disagrees with the observations"
+    )]
+    ReplayVerify {
+        #[arg(
+            long,
+            help = "Plans and results as JSON, a run report from --json-logs, or an observation log (.jsonl)"
+        )]
+        session: PathBuf,
+        #[arg(long, help = "File holding the map JSON to check")]
+        map: PathBuf,
+    },
+    #[command(
+        about = "Solve freshly generated libraries offline and fail unless every guess is correct"
+    )]
+    Selftest {
+        #[arg(long, default_value_t = 10, help = "Number of libraries to solve")]
+        trials: usize,
+        #[arg(
+            long,
+            help = "Doorways to spend at most per trial; defaults to 18 per room"
+        )]
+        budget: Option<usize>,
+    },
+}
+
+/// Stdin format of `reconstruct`: an explore response, optionally with the plans that produced it.
+#[derive(Deserialize)]
+struct ReconstructInput {
+    plans: Option<Vec<String>>,
+    results: Vec<Vec<usize>>,
 }
 
 fn get_input_or_stdin(arg: Option<String>, field_name: &str) -> Result<String> {
@@ -57,47 +261,683 @@ fn get_input_or_stdin(arg: Option<String>, field_name: &str) -> Result<String> {
     }
 }
 
+/// Parses a JSON array of plans, cleaning up each with [`normalize_plan`].
+fn parse_plans(input: &str) -> Result<Vec<String>> {
+    let plans: Vec<String> = serde_json::from_str(input)
+        .map_err(|e| anyhow::anyhow!("Invalid JSON format for plans: {}", e))?;
+    plans
+        .iter()
+        .enumerate()
+        .map(|(i, plan)| normalize_plan(i, plan))
+        .collect()
+}
+
+/// Parses comma-separated plans, as pasted from anywhere. A trailing comma is ignored.
+fn parse_raw_plans(input: &str) -> Result<Vec<String>> {
+    let mut tokens: Vec<&str> = input.split(',').collect();
+    if tokens.len() > 1 && tokens.last().is_some_and(|last| last.trim().is_empty()) {
+        tokens.pop();
+    }
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, plan)| normalize_plan(i, plan))
+        .collect()
+}
+
+/// `plan` without any whitespace in it, which copy-pasting tends to leave behind. Fails on
+/// anything else that isn't a door or a reset, naming the plan and the character's offset.
+fn normalize_plan(index: usize, plan: &str) -> Result<String> {
+    plan.chars()
+        .enumerate()
+        .filter(|(_, c)| !c.is_whitespace())
+        .map(|(offset, c)| match c {
+            RESET => Ok(c),
+            _ if c.to_digit(10).is_some_and(|door| (door as usize) < DOORS) => Ok(c),
+            _ => Err(anyhow::anyhow!(
+                "Plan {} has {:?} at offset {}; plans may only contain doors 0-{} and {}",
+                index,
+                c,
+                offset,
+                DOORS - 1,
+                RESET
+            )),
+        })
+        .collect()
+}
+
+fn reconstruct_from_input(
+    input: &str,
+    plans: Option<Vec<String>>,
+    rooms: usize,
+    options: &ReconstructOptions,
+    rng: &mut impl Rng,
+) -> Result<Map> {
+    let input: ReconstructInput = serde_json::from_str(input)
+        .map_err(|e| anyhow::anyhow!("Invalid JSON format for explore results: {}", e))?;
+    let plans = plans.or(input.plans).ok_or_else(|| {
+        anyhow::anyhow!("Plans are required. Pass --plans or include `plans` in the input")
+    })?;
+
+    let reconstruction = reconstruct(&plans, &input.results, rooms, options, rng)?;
+    if reconstruction.candidates.len() > 1 {
+        eprintln!(
+            "Found {}{} candidate maps, printing the first",
+            reconstruction.candidates.len(),
+            if reconstruction.exhaustive { "" } else { "+" }
+        );
+    }
+
+    reconstruction
+        .candidates
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("No map with {} rooms matches the observations", rooms))
+}
+
+/// Writes the map of a freshly generated library to `out`.
+fn generate_library(rooms: usize, seed: u64, out: &PathBuf, format: FileFormat) -> Result<Map> {
+    let simulator = Simulator::with_mode_seed(rooms, GenerationMode::Regular, seed)?;
+    let map = simulator.get_actual_map();
+    let bytes = match format {
+        FileFormat::Json => serde_json::to_string_pretty(&map)?.into_bytes(),
+        FileFormat::Binary => map.to_binary(),
+    };
+    fs::write(out, bytes)
+        .map_err(|e| anyhow::anyhow!("Failed to write {}: {}", out.display(), e))?;
+    Ok(map)
+}
+
+fn load_map(path: &PathBuf, format: FileFormat) -> Result<Map> {
+    let bytes =
+        fs::read(path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+    match format {
+        FileFormat::Json => serde_json::from_slice(&bytes)
+            .map_err(|e| anyhow::anyhow!("Invalid JSON format for map: {}", e)),
+        FileFormat::Binary => Map::from_binary(&bytes)
+            .map_err(|e| anyhow::anyhow!("Invalid binary map in {}: {}", path.display(), e)),
+    }
+}
+
+fn load_simulator(path: &PathBuf, format: FileFormat) -> Result<Simulator> {
+    Simulator::from_map(&load_map(path, format)?)
+}
+
+/// Exploration strategies that can be picked on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum StrategyName {
+    /// Head for doors not yet walked through.
+    Frontier,
+    /// Independent random walks.
+    RandomWalk,
+    /// Frontier walks, reconstructed by whichever backend suits the room count.
+    Auto,
+}
+
+impl StrategyName {
+    /// The strategy, with walks of two doors per room.
+    fn strategy(self, rooms: usize) -> Box<dyn ExploreStrategy> {
+        let plan_len = 2 * rooms;
+        match self {
+            StrategyName::Frontier | StrategyName::Auto => Box::new(Frontier {
+                plan_len,
+                plans_per_call: 1,
+            }),
+            StrategyName::RandomWalk => Box::new(RandomWalk {
+                plan_len,
+                plans_per_call: 1,
+            }),
+        }
+    }
+
+    /// Explore config for a library of `rooms` rooms, with the backend picked by size for
+    /// `auto`.
+    fn config(self, rooms: usize, budget: usize) -> ExploreConfig {
+        match self {
+            StrategyName::Auto => ExploreConfig::auto(rooms, budget),
+            _ => ExploreConfig::new(rooms, budget),
+        }
+    }
+}
+
+impl fmt::Display for StrategyName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no variant is skipped");
+        f.write_str(value.get_name())
+    }
+}
+
+/// Solves with the frontier strategy and a doorway budget.
+async fn solve<C: AedificiumClient + ?Sized>(
+    client: &mut C,
+    rooms: usize,
+    budget: usize,
+    checkpoint: Option<PathBuf>,
+    rng: &mut impl Rng,
+) -> Result<SolveReport> {
+    let config = ExploreConfig {
+        checkpoint,
+        ..ExploreConfig::new(rooms, budget)
+    };
+    solve_with(client, StrategyName::Frontier, &config, rng).await
+}
+
+/// Solves with the given strategy and configuration.
+async fn solve_with<C: AedificiumClient + ?Sized>(
+    client: &mut C,
+    strategy: StrategyName,
+    config: &ExploreConfig,
+    rng: &mut impl Rng,
+) -> Result<SolveReport> {
+    let mut strategy = strategy.strategy(config.room_count);
+    driver::solve(client, &mut *strategy, config, rng).await
+}
+
+/// `<dir>/<problem>-<unix milliseconds>.json`, a fresh report file for every run.
+fn run_log_path(dir: &Path, problem_name: &str) -> PathBuf {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis());
+    dir.join(format!("{}-{}.json", problem_name, millis))
+}
+
+/// Outcome of `selftest`.
+#[derive(Debug, Serialize)]
+struct SelftestSummary {
+    rooms: usize,
+    passed: usize,
+    failed: usize,
+    /// Seeds of the libraries that were not solved, to replay with `solve --offline --seed`.
+    failed_seeds: Vec<u64>,
+    average_explores: f64,
+}
+
+/// Solves `trials` regular libraries generated from consecutive seeds starting at `first_seed`.
+///
+/// A trial passes if the simulator accepts the guess; solver errors count as failures.
+async fn selftest(
+    rooms: usize,
+    trials: usize,
+    first_seed: u64,
+    budget: usize,
+) -> Result<SelftestSummary> {
+    let mut summary = SelftestSummary {
+        rooms,
+        passed: 0,
+        failed: 0,
+        failed_seeds: Vec::new(),
+        average_explores: 0.0,
+    };
+    let mut explores = 0;
+    let outcomes = trials::run_trials(rooms, trials, first_seed, async |simulator, rng| {
+        solve(simulator, rooms, budget, None, rng).await
+    })
+    .await?;
+    for (seed, report) in outcomes {
+        let passed = report.is_some_and(|report| {
+            explores += report.explores;
+            if !report.correct {
+                eprintln!(
+                    "seed {}: wrong guess after {} explores",
+                    seed, report.explores
+                );
+            }
+            report.correct
+        });
+        if passed {
+            summary.passed += 1;
+        } else {
+            summary.failed += 1;
+            summary.failed_seeds.push(seed);
+        }
+    }
+    if trials > 0 {
+        summary.average_explores = explores as f64 / trials as f64;
+    }
+    Ok(summary)
+}
+
+fn remote_client(team_id: String, dry_run: bool) -> Result<AedificiumRemoteClient> {
+    ClientBuilder::from_env(team_id).dry_run(dry_run).build()
+}
+
+impl ClientOptions {
+    /// The team id from the secret store with `--from-keyring`, if one is stored there, or else
+    /// from `ICFPC_TEAM_ID`.
+    fn team_id(&self) -> Result<String> {
+        #[cfg(feature = "keyring")]
+        if self.from_keyring {
+            if let Some(team_id) = icfpc2025_client::keyring::load_team_id()? {
+                return Ok(team_id);
+            }
+        }
+        env::var("ICFPC_TEAM_ID").map_err(|_| {
+            anyhow::anyhow!(
+                "Team ID is required. Set via ICFPC_TEAM_ID environment variable or .env file"
+            )
+        })
+    }
+
+    fn rooms(&self) -> Result<usize> {
+        self.rooms
+            .ok_or_else(|| anyhow::anyhow!("--rooms is required for this command"))
+    }
+
+    /// The API client, or with `--offline` a simulator of a regular library generated from
+    /// `--seed`. The seed defaults to 0 so that separate invocations see the same library.
+    fn client(&self) -> Result<Box<dyn AedificiumClient>> {
+        if self.offline && self.dry_run {
+            return Err(anyhow::anyhow!(
+                "--dry-run only applies to the API, not to --offline"
+            ));
+        }
+        if self.offline {
+            let seed = self.seed.unwrap_or(0);
+            Ok(Box::new(Simulator::with_mode_seed(
+                self.rooms()?,
+                GenerationMode::Regular,
+                seed,
+            )?))
+        } else {
+            Ok(Box::new(remote_client(self.team_id()?, self.dry_run)?))
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenvy::dotenv().ok();
 
     let cli = Cli::parse();
 
-    let team_id = env::var("ICFPC_TEAM_ID").map_err(|_| {
-        anyhow::anyhow!(
-            "Team ID is required. Set via ICFPC_TEAM_ID environment variable or .env file"
-        )
-    })?;
-
-    let mut client = AedificiumRemoteClient::new(team_id);
-
+    let options = cli.options;
     match cli.command {
+        #[cfg(feature = "keyring")]
+        Commands::Login { team_id } => {
+            let team_id = get_input_or_stdin(team_id, "Team id")?;
+            icfpc2025_client::keyring::store_team_id(&team_id)?;
+            eprintln!("Stored the team id in the OS secret store");
+        }
+        #[cfg(feature = "keyring")]
+        Commands::Logout => {
+            if icfpc2025_client::keyring::delete_team_id()? {
+                eprintln!("Removed the team id from the OS secret store");
+            } else {
+                eprintln!("No team id was stored");
+            }
+        }
         Commands::Select { problem } => {
             let problem_input = get_input_or_stdin(problem, "Problem name")?;
-            let response = client.select(problem_input).await?;
+            let response = options.client()?.select(problem_input).await?;
             println!("{}", serde_json::to_string_pretty(&response)?);
         }
-        Commands::Explore { plans } => {
+        Commands::Explore { plans, raw } => {
             let plans_input = get_input_or_stdin(plans, "Plans")?;
 
-            let plans_vec: Vec<String> = serde_json::from_str(&plans_input)
-                .map_err(|e| anyhow::anyhow!("Invalid JSON format for plans: {}", e))?;
+            let plans_vec = if raw {
+                parse_raw_plans(&plans_input)?
+            } else {
+                parse_plans(&plans_input)?
+            };
 
             if plans_vec.is_empty() {
                 return Err(anyhow::anyhow!("No valid plans found after parsing"));
             }
 
-            let response = client.explore(plans_vec).await?;
+            let response = options.client()?.explore(plans_vec).await?;
             println!("{}", serde_json::to_string_pretty(&response)?);
         }
         Commands::Guess { map } => {
             let map_input = get_input_or_stdin(map, "Map JSON")?;
             let map_data: Map = serde_json::from_str(&map_input)
                 .map_err(|e| anyhow::anyhow!("Invalid JSON format for map: {}", e))?;
-            let response = client.guess(map_data).await?;
+            let response = options.client()?.guess(map_data).await?;
             println!("{}", serde_json::to_string_pretty(&response)?);
         }
+        Commands::Reconstruct {
+            plans,
+            refine_depth,
+        } => {
+            let plans = plans.as_deref().map(parse_plans).transpose()?;
+            let input = get_input_or_stdin(None, "Explore results")?;
+            let reconstruct_options = ReconstructOptions {
+                refine_depth,
+                ..ReconstructOptions::default()
+            };
+            let map = reconstruct_from_input(
+                &input,
+                plans,
+                options.rooms()?,
+                &reconstruct_options,
+                &mut rand::rng(),
+            )?;
+            println!("{}", serde_json::to_string_pretty(&map)?);
+        }
+        Commands::Gen { out } => {
+            let rooms = options.rooms()?;
+            let seed = options.seed.unwrap_or_else(rand::random);
+            generate_library(rooms, seed, &out, options.format)?;
+            eprintln!(
+                "Wrote a {}-room library (seed {}) to {}",
+                rooms,
+                seed,
+                out.display()
+            );
+        }
+        Commands::Solve {
+            problem,
+            simulator,
+            budget,
+            checkpoint,
+            json_logs,
+            auto_batch,
+            strategy,
+            no_guess_on_exit,
+        } => {
+            let mut client: Box<dyn AedificiumClient> = match &simulator {
+                Some(path) => Box::new(load_simulator(path, options.format)?),
+                None => options.client()?,
+            };
+            let selected = match &problem {
+                Some(problem) if simulator.is_none() => {
+                    let response = client.select(problem.clone()).await?;
+                    Some(ProblemInfo::from_select(problem, &response))
+                }
+                None if simulator.is_none() && !options.offline => {
+                    return Err(anyhow::anyhow!(
+                        "--problem is required unless --simulator or --offline is given"
+                    ));
+                }
+                _ => None,
+            };
+            let rooms = match (options.rooms, selected.and_then(|info| info.room_count)) {
+                (Some(rooms), _) | (None, Some(rooms)) => rooms,
+                (None, None) => options.rooms()?,
+            };
+            let budget = budget.unwrap_or(doorway_budget(rooms));
+            let mut rng = match options.seed {
+                Some(seed) => StdRng::seed_from_u64(seed),
+                None => StdRng::from_rng(&mut rand::rng()),
+            };
+            let run_log = match &json_logs {
+                Some(dir) => {
+                    fs::create_dir_all(dir).map_err(|e| {
+                        anyhow::anyhow!("Failed to create {}: {}", dir.display(), e)
+                    })?;
+                    let problem_name = match (&problem, &simulator) {
+                        (Some(problem), _) => problem.clone(),
+                        (None, Some(path)) => {
+                            path.file_stem().map_or("simulator".to_string(), |stem| {
+                                stem.to_string_lossy().into_owned()
+                            })
+                        }
+                        (None, None) => "offline".to_string(),
+                    };
+                    Some(RunLog {
+                        path: run_log_path(dir, &problem_name),
+                        problem_name,
+                        seed: options.offline.then(|| options.seed.unwrap_or(0)),
+                    })
+                }
+                None => None,
+            };
+            let config = ExploreConfig {
+                checkpoint,
+                run_log,
+                auto_batch,
+                shutdown: Shutdown::on_ctrl_c(),
+                guess_on_shutdown: !no_guess_on_exit,
+                ..strategy.config(rooms, budget)
+            };
+            eprintln!("Reconstructing with {:?}", config.backend());
+            let report = solve_with(&mut *client, strategy, &config, &mut rng).await?;
+            if report.interrupted {
+                eprintln!("Interrupted; guessed the best candidate so far");
+            }
+            let verdict = if report.correct { "Correct" } else { "Wrong" };
+            eprintln!("{} guess: {}", verdict, report.map);
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        }
+        Commands::Bench {
+            trials,
+            budget,
+            strategy,
+            min_solve_rate,
+        } => {
+            let rooms = options.rooms()?;
+            let budget = budget.unwrap_or(doorway_budget(rooms));
+            let summary =
+                bench::bench(rooms, trials, options.seed.unwrap_or(0), budget, strategy).await?;
+            println!("{}", summary);
+            if summary.solve_rate() < min_solve_rate {
+                return Err(anyhow::anyhow!(
+                    "Solved {:.1}% of trials, below --min-solve-rate {}",
+                    100.0 * summary.solve_rate(),
+                    min_solve_rate
+                ));
+            }
+        }
+        Commands::Ambiguity {
+            trials,
+            budget,
+            strategy,
+        } => {
+            let rooms = options.rooms()?;
+            let budget = budget.unwrap_or(doorway_budget(rooms));
+            let summary =
+                ambiguity::ambiguity(rooms, trials, options.seed.unwrap_or(0), budget, strategy)
+                    .await?;
+            println!("{}", summary);
+        }
+        Commands::Minimize { plans } => {
+            let input = fs::read_to_string(&plans)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", plans.display(), e))?;
+            let plans = parse_plans(&input)?;
+            let count = plans.len();
+            let set =
+                minimize::PlanSet::explore(options.rooms()?, options.seed.unwrap_or(0), plans)
+                    .await?;
+            let (minimal, outcome) = set.minimize().await?;
+            eprintln!(
+                "{} of {} plans still give {:?}",
+                minimal.len(),
+                count,
+                outcome
+            );
+            println!("{}", serde_json::to_string_pretty(&minimal)?);
+        }
+        Commands::ReplayVerify { session, map } => {
+            let (plans, results) =
+                replay::load_session(&session, options.format == FileFormat::Binary)?;
+            let map = load_map(&map, options.format)?;
+            let checks = replay::replay_verify(&map, &plans, &results)?;
+            for (i, check) in checks.iter().enumerate() {
+                println!("plan {} {:?}: {}", i, plans[i], check);
+            }
+            let diverging = checks
+                .iter()
+                .filter(|check| **check != replay::PlanCheck::Matches)
+                .count();
+            if diverging > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} of {} plans disagree with the map",
+                    diverging,
+                    checks.len()
+                ));
+            }
+        }
+        Commands::Selftest { trials, budget } => {
+            let rooms = options.rooms()?;
+            let budget = budget.unwrap_or(doorway_budget(rooms));
+            let summary = selftest(rooms, trials, options.seed.unwrap_or(0), budget).await?;
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+            if summary.failed > 0 {
+                return Err(anyhow::anyhow!(
+                    "{} of {} trials failed",
+                    summary.failed,
+                    trials
+                ));
+            }
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use icfpc2025_solver::verify_against_observations;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_messy_plans() {
+        let json = "[\"01 2\", \"3\\n4\\t5\", \" R0\\u00a0\"]";
+        assert_eq!(parse_plans(json).unwrap(), vec!["012", "345", "R0"]);
+        assert_eq!(
+            parse_raw_plans(" 012 ,\n 3 4\r\n,R5,\n").unwrap(),
+            vec!["012", "34", "R5"]
+        );
+        // Only a trailing comma is dropped; an empty plan elsewhere is still a plan.
+        assert_eq!(parse_raw_plans("0,,1").unwrap(), vec!["0", "", "1"]);
+
+        let error = parse_raw_plans("012,34x5").unwrap_err().to_string();
+        assert!(
+            error.contains("Plan 1") && error.contains("offset 2"),
+            "{}",
+            error
+        );
+        let error = parse_plans("[\"0\", \"\", \"1６\"]")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("Plan 2") && error.contains("offset 1"),
+            "{}",
+            error
+        );
+        assert!(parse_plans("[\"0\",]").is_err());
+    }
+
+    /// Random walk that only takes connected doors, since the simulator leaves some doors open.
+    fn connected_walk(map: &Map, len: usize, rng: &mut impl Rng) -> String {
+        let mut current = map.starting_room;
+        let mut plan = String::new();
+        for _ in 0..len {
+            let exits: Vec<(usize, usize)> = map
+                .connections
+                .iter()
+                .flat_map(|c| [(&c.from, &c.to), (&c.to, &c.from)])
+                .filter(|(from, _)| from.room == current)
+                .map(|(from, to)| (from.door, to.room))
+                .collect();
+            let (door, next) = exits[rng.random_range(0..exits.len())];
+            plan.push_str(&door.to_string());
+            current = next;
+        }
+        plan
+    }
+
+    #[tokio::test]
+    async fn test_reconstruct_piped_simulator_response() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut simulator = Simulator::with_seed(3, 7).unwrap();
+        let plans = vec![connected_walk(&simulator.get_actual_map(), 40, &mut rng)];
+        let response = simulator.explore(plans.clone()).await.unwrap();
+
+        // What `aedificium explore` prints, fed into `aedificium reconstruct --plans ...`.
+        let options = ReconstructOptions::default();
+        let piped = serde_json::to_string_pretty(&response).unwrap();
+        let map =
+            reconstruct_from_input(&piped, Some(plans.clone()), 3, &options, &mut rng).unwrap();
+        assert_eq!(map.rooms.len(), 3);
+        assert!(verify_against_observations(&map, &plans, &response.results));
+
+        // The plans can also be embedded in the input itself.
+        let embedded = serde_json::json!({ "plans": plans, "results": response.results });
+        let map =
+            reconstruct_from_input(&embedded.to_string(), None, 3, &options, &mut rng).unwrap();
+        assert!(verify_against_observations(&map, &plans, &response.results));
+
+        assert!(reconstruct_from_input(&piped, None, 3, &options, &mut rng).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_gen_then_solve() {
+        let out = env::temp_dir().join(format!("aedificium-gen-{}.json", std::process::id()));
+        for (rooms, seed, format) in [
+            (3, 1, FileFormat::Json),
+            (4, 2, FileFormat::Binary),
+            (6, 3, FileFormat::Json),
+        ] {
+            let generated = generate_library(rooms, seed, &out, format).unwrap();
+            let mut simulator = load_simulator(&out, format).unwrap();
+            assert_eq!(simulator.get_actual_map().rooms, generated.rooms);
+
+            let mut rng = StdRng::seed_from_u64(seed);
+            let report = solve(&mut simulator, rooms, 18 * rooms, None, &mut rng)
+                .await
+                .unwrap();
+            assert!(report.correct, "{} rooms: {:?}", rooms, report);
+        }
+        fs::remove_file(&out).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_selftest() {
+        let summary = selftest(3, 4, 0, 18 * 3).await.unwrap();
+        assert_eq!(summary.passed + summary.failed, 4);
+        assert_eq!(summary.failed_seeds.len(), summary.failed);
+        assert!(summary.passed > 0, "{:?}", summary);
+        assert!(summary.average_explores >= 1.0);
+
+        // A budget too small to observe anything fails every trial instead of erroring out.
+        let summary = selftest(3, 2, 0, 0).await.unwrap();
+        assert_eq!((summary.passed, summary.failed), (0, 2));
+        assert_eq!(summary.failed_seeds, vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_offline_solve() {
+        // Without --problem nothing is selected, so the library is the one generated from the
+        // seed.
+        let cli = Cli::try_parse_from([
+            "aedificium",
+            "solve",
+            "--offline",
+            "--rooms",
+            "4",
+            "--seed",
+            "5",
+        ])
+        .unwrap();
+        assert!(matches!(cli.command, Commands::Solve { problem: None, .. }));
+
+        let mut client = cli.options.client().unwrap();
+        let mut rng = StdRng::seed_from_u64(5);
+        let report = solve(&mut *client, 4, 18 * 4, None, &mut rng)
+            .await
+            .unwrap();
+        assert!(report.correct);
+
+        // Offline invocations without a seed agree on the library.
+        let options = ClientOptions {
+            offline: true,
+            rooms: Some(4),
+            seed: None,
+            dry_run: false,
+            format: FileFormat::Json,
+            #[cfg(feature = "keyring")]
+            from_keyring: false,
+        };
+        let map = {
+            let mut rng = StdRng::seed_from_u64(5);
+            solve(&mut *options.client().unwrap(), 4, 18 * 4, None, &mut rng)
+                .await
+                .unwrap()
+                .map
+        };
+        let response = options.client().unwrap().guess(map).await.unwrap();
+        assert!(response.correct);
+    }
+}
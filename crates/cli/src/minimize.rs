@@ -0,0 +1,155 @@
+use anyhow::{bail, Result};
+use icfpc2025_common::AedificiumClient;
+use icfpc2025_simulator::{GenerationMode, Simulator};
+use icfpc2025_solver::{reconstruct, ReconstructOptions};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::Serialize;
+
+/// How reconstructing from a set of plans went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Outcome {
+    /// A single candidate, and it is the library.
+    Solved,
+    /// A single candidate, but not the library.
+    Wrong,
+    /// This many candidates, up to the search's limit.
+    Ambiguous(usize),
+}
+
+/// Offline reconstructions of the regular library generated from `seed`, each from a subset
+/// of `plans`. Every plan is explored once up front, from the same doorway count, so plan sets
+/// longer than one budget still work.
+pub struct PlanSet {
+    rooms: usize,
+    simulator: Simulator,
+    plans: Vec<String>,
+    results: Vec<Vec<usize>>,
+}
+
+impl PlanSet {
+    pub async fn explore(rooms: usize, seed: u64, plans: Vec<String>) -> Result<Self> {
+        let mut simulator = Simulator::with_mode_seed(rooms, GenerationMode::Regular, seed)?;
+        let start = simulator.snapshot();
+        let mut results = Vec::with_capacity(plans.len());
+        for plan in &plans {
+            let response = simulator.explore(vec![plan.clone()]).await?;
+            results.extend(response.results);
+            simulator.restore(start.clone());
+        }
+        Ok(Self {
+            rooms,
+            simulator,
+            plans,
+            results,
+        })
+    }
+
+    /// Reconstructs from the plans at `indices` and checks the candidates against the library.
+    pub async fn outcome(&self, indices: &[usize]) -> Result<Outcome> {
+        let plans: Vec<String> = indices.iter().map(|&i| self.plans[i].clone()).collect();
+        let results: Vec<Vec<usize>> = indices.iter().map(|&i| self.results[i].clone()).collect();
+        let reconstruction = reconstruct(
+            &plans,
+            &results,
+            self.rooms,
+            &ReconstructOptions::default(),
+            &mut StdRng::seed_from_u64(0),
+        )?;
+        Ok(match reconstruction.candidates.as_slice() {
+            [map] if reconstruction.exhaustive => {
+                if self.simulator.guess(map.clone()).await?.correct {
+                    Outcome::Solved
+                } else {
+                    Outcome::Wrong
+                }
+            }
+            candidates => Outcome::Ambiguous(candidates.len()),
+        })
+    }
+
+    /// The smallest subset of the plans found by delta debugging that fails the same way as
+    /// all of them, in their original order.
+    pub async fn minimize(&self) -> Result<(Vec<String>, Outcome)> {
+        let all: Vec<usize> = (0..self.plans.len()).collect();
+        let failure = self.outcome(&all).await?;
+        if failure == Outcome::Solved {
+            bail!("These plans reconstruct the library; there is no failure to minimize");
+        }
+
+        let mut current = all;
+        let mut granularity = 2;
+        while current.len() >= 2 {
+            let chunk_len = current.len().div_ceil(granularity);
+            let chunks: Vec<Vec<usize>> = current.chunks(chunk_len).map(<[_]>::to_vec).collect();
+
+            let mut reduced = None;
+            for chunk in &chunks {
+                if self.outcome(chunk).await? == failure {
+                    reduced = Some((chunk.clone(), 2));
+                    break;
+                }
+            }
+            if reduced.is_none() && chunks.len() > 2 {
+                for skip in 0..chunks.len() {
+                    let complement: Vec<usize> = chunks
+                        .iter()
+                        .enumerate()
+                        .filter(|&(i, _)| i != skip)
+                        .flat_map(|(_, chunk)| chunk.iter().copied())
+                        .collect();
+                    if self.outcome(&complement).await? == failure {
+                        reduced = Some((complement, (granularity - 1).max(2)));
+                        break;
+                    }
+                }
+            }
+
+            match reduced {
+                Some((subset, next_granularity)) => {
+                    current = subset;
+                    granularity = next_granularity;
+                }
+                None if granularity >= current.len() => break,
+                None => granularity = (granularity * 2).min(current.len()),
+            }
+        }
+
+        let plans = current.iter().map(|&i| self.plans[i].clone()).collect();
+        Ok((plans, failure))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_minimize_drops_redundant_plans() {
+        // Short walks leave doors unobserved, so the reconstruction fails; repeating them adds
+        // nothing but work.
+        let plans: Vec<String> = ["01", "23", "4", "01", "23", "4", "01", "5"]
+            .map(String::from)
+            .to_vec();
+        let set = PlanSet::explore(3, 2, plans.clone()).await.unwrap();
+        let all: Vec<usize> = (0..plans.len()).collect();
+        let failure = set.outcome(&all).await.unwrap();
+
+        let (minimal, outcome) = set.minimize().await.unwrap();
+        assert_eq!(outcome, failure);
+        assert!(minimal.len() < plans.len(), "{:?}", minimal);
+
+        // Still fails the same way, and no single plan can be left out.
+        let indices: Vec<usize> = minimal
+            .iter()
+            .map(|plan| plans.iter().position(|p| p == plan).unwrap())
+            .collect();
+        assert_eq!(set.outcome(&indices).await.unwrap(), failure);
+        for skip in 0..indices.len() {
+            let mut fewer = indices.clone();
+            fewer.remove(skip);
+            if !fewer.is_empty() {
+                assert_ne!(set.outcome(&fewer).await.unwrap(), failure);
+            }
+        }
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::{bail, Context, Result};
+use icfpc2025_common::{validate_map, Map, Observation, ObservationSet};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A saved session: either `{"plans": [...], "results": [...]}` as `reconstruct` reads it, or
+/// a run report written by `solve --json-logs`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SessionFile {
+    Explore {
+        plans: Vec<String>,
+        results: Vec<Vec<usize>>,
+    },
+    Report {
+        observations: Vec<Observation>,
+    },
+}
+
+/// Reads the plans and results of a saved session. Files ending in `.jsonl` are read as an
+/// observation log, one [`Observation`] per line, and `binary` ones as an encoded
+/// [`ObservationSet`].
+pub fn load_session(path: &Path, binary: bool) -> Result<(Vec<String>, Vec<Vec<usize>>)> {
+    if binary {
+        let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+        let set = ObservationSet::from_binary(&bytes)
+            .with_context(|| format!("{} is not a binary observation set", path.display()))?;
+        return Ok((set.plans(), set.results()));
+    }
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "jsonl")
+    {
+        let set = ObservationSet::load(path)?;
+        return Ok((set.plans(), set.results()));
+    }
+    let json =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let session = serde_json::from_str(&json).with_context(|| {
+        format!(
+            "{} holds neither plans and results nor a run report",
+            path.display()
+        )
+    })?;
+    Ok(match session {
+        SessionFile::Explore { plans, results } => {
+            if plans.len() != results.len() {
+                bail!("{} plans but {} results", plans.len(), results.len());
+            }
+            (plans, results)
+        }
+        SessionFile::Report { observations } => {
+            let set = ObservationSet { observations };
+            (set.plans(), set.results())
+        }
+    })
+}
+
+/// How walking one plan over a map compares with what was observed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlanCheck {
+    Matches,
+    /// The first label the map gets wrong. `predicted` or `observed` is `None` past the end of
+    /// the walk or of the recorded labels, if they differ in length.
+    Diverges {
+        position: usize,
+        predicted: Option<usize>,
+        observed: Option<usize>,
+    },
+    /// The plan takes a door the map leaves unconnected.
+    Unwalkable(String),
+}
+
+/// Walks every plan over `map` and compares the labels with `results`, plan by plan. Fails on a
+/// map [`validate_map`] rejects, whose walks would be meaningless or out of bounds.
+pub fn replay_verify(
+    map: &Map,
+    plans: &[String],
+    results: &[Vec<usize>],
+) -> Result<Vec<PlanCheck>> {
+    validate_map(map).context("the map to verify is invalid")?;
+    Ok(plans
+        .iter()
+        .zip(results)
+        .map(|(plan, observed)| {
+            let predicted = match map.walk(plan) {
+                Ok(predicted) => predicted,
+                Err(e) => return PlanCheck::Unwalkable(e.to_string()),
+            };
+            let position = (0..predicted.len().max(observed.len()))
+                .find(|&i| predicted.get(i) != observed.get(i));
+            match position {
+                None => PlanCheck::Matches,
+                Some(position) => PlanCheck::Diverges {
+                    position,
+                    predicted: predicted.get(position).copied(),
+                    observed: observed.get(position).copied(),
+                },
+            }
+        })
+        .collect())
+}
+
+impl fmt::Display for PlanCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = |label: &Option<usize>| label.map_or("nothing".to_string(), |l| l.to_string());
+        match self {
+            PlanCheck::Matches => write!(f, "matches"),
+            PlanCheck::Diverges {
+                position,
+                predicted,
+                observed,
+            } => write!(
+                f,
+                "diverges at position {}: the map predicts {}, observed {}",
+                position,
+                label(predicted),
+                label(observed)
+            ),
+            PlanCheck::Unwalkable(reason) => write!(f, "can't be walked: {}", reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use icfpc2025_common::MapBuilder;
+
+    use super::*;
+
+    fn chain(last_label: usize) -> Map {
+        MapBuilder::new()
+            .room(0, 0)
+            .room(1, 1)
+            .room(2, last_label)
+            .connect(0, 0, 1, 0)
+            .connect(1, 1, 2, 0)
+            .connect(0, 1, 0, 1)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_wrong_map_is_pinpointed() {
+        let truth = chain(2);
+        let plans: Vec<String> = ["001", "01", "010", "2"].map(String::from).to_vec();
+        let results: Vec<Vec<usize>> = plans
+            .iter()
+            .take(3)
+            .map(|p| truth.walk(p).unwrap())
+            .collect();
+        assert!(replay_verify(&truth, &plans[..3], &results)
+            .unwrap()
+            .iter()
+            .all(|check| *check == PlanCheck::Matches));
+
+        // Room 2 is first entered by the second door of "01" and "010".
+        let checks = replay_verify(
+            &chain(3),
+            &plans,
+            &[results.clone(), vec![vec![0, 0]]].concat(),
+        )
+        .unwrap();
+        assert_eq!(checks[0], PlanCheck::Matches);
+        for check in &checks[1..3] {
+            assert_eq!(
+                *check,
+                PlanCheck::Diverges {
+                    position: 2,
+                    predicted: Some(3),
+                    observed: Some(2),
+                }
+            );
+        }
+        assert!(
+            matches!(checks[3], PlanCheck::Unwalkable(_)),
+            "{:?}",
+            checks[3]
+        );
+
+        let short = replay_verify(&truth, &plans[..1], &[vec![0, 1]]).unwrap();
+        assert_eq!(
+            short,
+            vec![PlanCheck::Diverges {
+                position: 2,
+                predicted: Some(0),
+                observed: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_invalid_map_is_rejected() {
+        let mut map = chain(2);
+        map.connections[0].to.room = 7;
+        let error = replay_verify(&map, &["0".to_string()], &[vec![0, 1]]).unwrap_err();
+        assert!(format!("{:#}", error).contains("invalid"), "{:#}", error);
+    }
+}
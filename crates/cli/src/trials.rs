@@ -0,0 +1,29 @@
+use anyhow::Result;
+use icfpc2025_simulator::{GenerationMode, Simulator};
+use rand::{rngs::StdRng, SeedableRng};
+
+/// Runs `trial` against `trials` regular libraries generated from consecutive seeds starting
+/// at `first_seed`, each with an rng seeded the same, and returns every seed with what its
+/// trial returned. A trial that errors out is reported and returns `None`; a library that
+/// can't be generated fails the whole run.
+pub async fn run_trials<T>(
+    rooms: usize,
+    trials: usize,
+    first_seed: u64,
+    mut trial: impl AsyncFnMut(&mut Simulator, &mut StdRng) -> Result<T>,
+) -> Result<Vec<(u64, Option<T>)>> {
+    let mut outcomes = Vec::with_capacity(trials);
+    for seed in (first_seed..).take(trials) {
+        let mut simulator = Simulator::with_mode_seed(rooms, GenerationMode::Regular, seed)?;
+        let mut rng = StdRng::seed_from_u64(seed);
+        let outcome = match trial(&mut simulator, &mut rng).await {
+            Ok(outcome) => Some(outcome),
+            Err(e) => {
+                eprintln!("seed {}: {}", seed, e);
+                None
+            }
+        };
+        outcomes.push((seed, outcome));
+    }
+    Ok(outcomes)
+}
@@ -1,10 +1,10 @@
 use anyhow::Result;
 use icfpc2025_client::AedificiumRemoteClient;
-use icfpc2025_common::{AedificiumClient, Map, MapConnection, RoomDoor};
+use icfpc2025_common::{AedificiumClient, MapBuilder};
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let client = AedificiumRemoteClient::new("example-id".to_string());
+    let mut client = AedificiumRemoteClient::new("example-id".to_string());
 
     // Select a problem
     let _select_response = client.select("example-problem".to_string()).await?;
@@ -22,19 +22,17 @@ async fn main() -> Result<()> {
         .await?;
 
     println!("Explore results: {:?}", explore_response.results);
-    println!("Query count: {}", explore_response.query_count);
+    println!("Query count: {}", explore_response.total_query_count());
 
     // Submit a guess
-    let guess_response = client
-        .guess(Map {
-            rooms: vec![1, 2, 3],
-            starting_room: 1,
-            connections: vec![MapConnection {
-                from: RoomDoor { room: 1, door: 0 },
-                to: RoomDoor { room: 2, door: 1 },
-            }],
-        })
-        .await?;
+    let map = MapBuilder::new()
+        .room(0, 0)
+        .room(1, 1)
+        .room(2, 2)
+        .starting(0)
+        .connect(0, 0, 1, 1)
+        .build()?;
+    let guess_response = client.guess(map).await?;
 
     println!("Guess correct: {}", guess_response.correct);
 
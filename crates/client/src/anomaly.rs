@@ -0,0 +1,163 @@
+use icfpc2025_common::{ExploreResponse, ObservationConvention, SelectResponse, LABELS};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::observer::ClientObserver;
+
+/// Labels to see for a problem before judging how they are spread.
+const MIN_LABELS: u64 = 32;
+/// The largest share of all labels one label may have before it looks suspicious. Every
+/// label is shared by about a quarter of the rooms, so a walk rarely sees one this often.
+const MAX_LABEL_SHARE: f64 = 0.75;
+
+/// An observer that warns on stderr when explore responses look wrong: results whose length
+/// doesn't fit their plan, labels out of range, or one label seen far more often than the
+/// others, which usually means the plans aren't what was meant to be sent.
+///
+/// The label histogram is per problem and starts over on every `select`. Warnings are also
+/// kept, for [`AnomalyDetector::warnings`].
+#[derive(Debug, Default)]
+pub struct AnomalyDetector {
+    convention: ObservationConvention,
+    state: Mutex<AnomalyState>,
+}
+
+#[derive(Debug, Default)]
+struct AnomalyState {
+    problem_name: Option<String>,
+    histogram: [u64; LABELS],
+    skew_reported: bool,
+    warnings: Vec<String>,
+}
+
+impl AnomalyDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Checks result lengths against `convention` instead of the latest API's.
+    pub fn with_convention(convention: ObservationConvention) -> Self {
+        Self {
+            convention,
+            ..Self::default()
+        }
+    }
+
+    /// Every warning so far, oldest first.
+    pub fn warnings(&self) -> Vec<String> {
+        self.state.lock().unwrap().warnings.clone()
+    }
+
+    /// How often each label has been seen for the current problem.
+    pub fn histogram(&self) -> [u64; LABELS] {
+        self.state.lock().unwrap().histogram
+    }
+}
+
+impl AnomalyState {
+    fn warn(&mut self, message: String) {
+        let message = match &self.problem_name {
+            Some(name) => format!("{}: {}", name, message),
+            None => message,
+        };
+        eprintln!("[anomaly] {}", message);
+        self.warnings.push(message);
+    }
+}
+
+impl ClientObserver for AnomalyDetector {
+    fn on_select(&self, problem_name: &str, _response: &SelectResponse) {
+        let mut state = self.state.lock().unwrap();
+        state.problem_name = Some(problem_name.to_string());
+        state.histogram = [0; LABELS];
+        state.skew_reported = false;
+    }
+
+    fn on_explore(&self, plans: &[String], response: &ExploreResponse, _elapsed: Duration) {
+        let mut state = self.state.lock().unwrap();
+        for (i, (plan, labels)) in plans.iter().zip(&response.results).enumerate() {
+            if let Err(e) = self.convention.check(plan.chars().count(), labels) {
+                state.warn(format!("result {}: {}", i, e));
+            }
+            let out_of_range = labels.iter().filter(|&&label| label >= LABELS).count();
+            if out_of_range > 0 {
+                state.warn(format!(
+                    "result {} has {} labels outside 0..{}",
+                    i, out_of_range, LABELS
+                ));
+            }
+            for &label in labels.iter().filter(|&&label| label < LABELS) {
+                state.histogram[label] += 1;
+            }
+        }
+
+        let total: u64 = state.histogram.iter().sum();
+        let (label, &most) = state
+            .histogram
+            .iter()
+            .enumerate()
+            .max_by_key(|&(_, count)| count)
+            .unwrap();
+        if !state.skew_reported
+            && total >= MIN_LABELS
+            && most as f64 > MAX_LABEL_SHARE * total as f64
+        {
+            state.skew_reported = true;
+            state.warn(format!(
+                "label {} is {} of {} labels seen; are the plans malformed?",
+                label, most, total
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn explore(detector: &AnomalyDetector, plan: &str, results: Vec<usize>) {
+        let response = ExploreResponse {
+            results: vec![results],
+            query_count: 1.into(),
+            truncated: false,
+        };
+        detector.on_explore(&[plan.to_string()], &response, Duration::ZERO);
+    }
+
+    #[test]
+    fn test_warns_on_all_one_label() {
+        let detector = AnomalyDetector::new();
+        let plan = "0".repeat(40);
+        explore(&detector, &plan, vec![0; 41]);
+        let warnings = detector.warnings();
+        assert_eq!(warnings.len(), 1, "{:?}", warnings);
+        assert!(
+            warnings[0].contains("label 0 is 41 of 41"),
+            "{:?}",
+            warnings
+        );
+
+        // Reported once per problem.
+        explore(&detector, &plan, vec![0; 41]);
+        assert_eq!(detector.warnings().len(), 1);
+
+        explore(&detector, "01", vec![0, 1]);
+        explore(&detector, "0", vec![0, 7]);
+        assert_eq!(detector.warnings().len(), 3);
+    }
+
+    #[test]
+    fn test_quiet_on_balanced_labels() {
+        let detector = AnomalyDetector::new();
+        detector.on_select(
+            "probatio",
+            &SelectResponse {
+                data: serde_json::json!({ "problemName": "probatio" }),
+            },
+        );
+        let plan = "012345".repeat(10);
+        explore(&detector, &plan, (0..=60).map(|i| i % LABELS).collect());
+        assert!(detector.warnings().is_empty(), "{:?}", detector.warnings());
+        assert_eq!(detector.histogram(), [16, 15, 15, 15]);
+    }
+}
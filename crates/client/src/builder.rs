@@ -0,0 +1,181 @@
+use anyhow::Result;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::client::{AedificiumRemoteClient, DEFAULT_BASE_URL, DEFAULT_MAX_PLAN_LEN};
+use crate::config::{RateLimit, RetryConfig, SharedRateLimiter};
+use crate::observer::{ClientObserver, Observers};
+use crate::version::ApiVersion;
+
+/// Builds an [`AedificiumRemoteClient`] from explicit settings only.
+///
+/// Unlike [`AedificiumRemoteClient::new`], nothing is read from the environment unless
+/// [`ClientBuilder::from_env`] is used.
+#[derive(Debug, Clone)]
+pub struct ClientBuilder {
+    pub(crate) id: String,
+    pub(crate) base_url: String,
+    pub(crate) debug: bool,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) retry: RetryConfig,
+    pub(crate) rate_limit: Option<RateLimit>,
+    pub(crate) shared_rate_limiter: Option<SharedRateLimiter>,
+    pub(crate) observers: Observers,
+    pub(crate) cache_explores: bool,
+    pub(crate) dedup_plans: bool,
+    pub(crate) max_plan_len: usize,
+    pub(crate) dry_run: bool,
+    pub(crate) api_version: ApiVersion,
+}
+
+impl ClientBuilder {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self {
+            id: id.into(),
+            base_url: DEFAULT_BASE_URL.to_string(),
+            debug: false,
+            timeout: None,
+            retry: RetryConfig::default(),
+            rate_limit: None,
+            shared_rate_limiter: None,
+            observers: Observers::default(),
+            cache_explores: false,
+            dedup_plans: false,
+            max_plan_len: DEFAULT_MAX_PLAN_LEN,
+            dry_run: false,
+            api_version: ApiVersion::LATEST,
+        }
+    }
+
+    /// Like [`ClientBuilder::new`], with `debug` taken from `AEDIFICIUM_CLIENT_DEBUG`.
+    pub fn from_env(id: impl Into<String>) -> Self {
+        let debug =
+            env::var("AEDIFICIUM_CLIENT_DEBUG").is_ok_and(|value| value.to_lowercase() == "true");
+        Self::new(id).debug(debug)
+    }
+
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryConfig) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn rate_limit(mut self, rate_limit: RateLimit) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Draws from `limiter` instead of a limiter of the client's own. Takes precedence over
+    /// [`ClientBuilder::rate_limit`].
+    pub fn shared_rate_limiter(mut self, limiter: SharedRateLimiter) -> Self {
+        self.shared_rate_limiter = Some(limiter);
+        self
+    }
+
+    /// Longest plan `explore` sends. Longer plans are rejected before any request is made;
+    /// see [`icfpc2025_common::split_plan`] for chopping them up.
+    pub fn max_plan_len(mut self, max_plan_len: usize) -> Self {
+        self.max_plan_len = max_plan_len;
+        self
+    }
+
+    /// Remembers the labels observed for each plan until the next `select` or
+    /// [`AedificiumRemoteClient::reset`], and only sends plans that haven't been explored yet.
+    /// A call whose plans were all explored before sends no request and reports the last known
+    /// query count.
+    pub fn cache_explores(mut self, cache: bool) -> Self {
+        self.cache_explores = cache;
+        self
+    }
+
+    /// Sends each distinct plan of an `explore` call once and copies its result to every
+    /// position it appeared at. Off by default, since a repeated plan usually points at a bug in
+    /// the caller. [`ClientBuilder::cache_explores`] implies this.
+    pub fn dedup_plans(mut self, dedup: bool) -> Self {
+        self.dedup_plans = dedup;
+        self
+    }
+
+    /// Prints every request to stderr instead of sending it, and answers with a stub response:
+    /// `select` echoes the problem name, `explore` reports label 0 everywhere and `guess` is
+    /// never correct. Plans are still checked against the length limit.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// The tier of the API to speak, [`ApiVersion::LATEST`] by default. Plans it can't send are
+    /// rejected before any request is made, and explore responses of another shape are errors.
+    pub fn api_version(mut self, version: ApiVersion) -> Self {
+        self.api_version = version;
+        self
+    }
+
+    /// Adds an observer notified after every successful request.
+    pub fn observer(mut self, observer: Arc<dyn ClientObserver>) -> Self {
+        self.observers.0.push(observer);
+        self
+    }
+
+    pub fn build(self) -> Result<AedificiumRemoteClient> {
+        AedificiumRemoteClient::from_builder(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_defaults() {
+        let builder = ClientBuilder::new("team");
+        assert_eq!(builder.id, "team");
+        assert_eq!(builder.base_url, DEFAULT_BASE_URL);
+        assert!(!builder.debug);
+        assert_eq!(builder.timeout, None);
+        assert_eq!(builder.retry, RetryConfig::disabled());
+        assert_eq!(builder.rate_limit, None);
+        assert_eq!(builder.observers.0.len(), 0);
+        assert!(!builder.cache_explores);
+        assert_eq!(builder.max_plan_len, DEFAULT_MAX_PLAN_LEN);
+        assert_eq!(builder.api_version, ApiVersion::LATEST);
+    }
+
+    #[test]
+    fn test_builder_setters() {
+        let builder = ClientBuilder::new("team")
+            .debug(true)
+            .base_url("http://localhost:8080/")
+            .timeout(Duration::from_secs(5))
+            .retry(RetryConfig::new(3, Duration::from_millis(10)))
+            .rate_limit(RateLimit::new(4, Duration::from_secs(1)));
+
+        assert!(builder.debug);
+        assert_eq!(builder.base_url, "http://localhost:8080");
+        assert_eq!(builder.timeout, Some(Duration::from_secs(5)));
+        assert_eq!(builder.retry.max_retries, 3);
+        assert_eq!(
+            builder.rate_limit,
+            Some(RateLimit::new(4, Duration::from_secs(1)))
+        );
+
+        let client = builder.build().unwrap();
+        assert!(client.debug());
+        assert_eq!(client.base_url(), "http://localhost:8080");
+    }
+}
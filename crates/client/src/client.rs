@@ -1,36 +1,407 @@
 use anyhow::Result;
 use async_trait::async_trait;
 use icfpc2025_common::{
-    AedificiumClient, ExploreRequest, ExploreResponse, GuessRequest, GuessResponse, Map,
-    SelectRequest, SelectResponse,
+    max_plan_len, AedificiumClient, ExploreRequest, ExploreResponse, GuessRequest, GuessResponse,
+    Map, ProblemInfo, SelectRequest, SelectResponse,
 };
 use reqwest::Client;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use std::env;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
+use crate::builder::ClientBuilder;
+use crate::config::{RetryConfig, SharedRateLimiter};
+use crate::observer::Observers;
+use crate::version::ApiVersion;
+
+/// Header carrying the key that identifies a guess across retries.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// The API's plan length limit for the largest standard problem.
+pub const DEFAULT_MAX_PLAN_LEN: usize = max_plan_len(30);
+
+pub(crate) const DEFAULT_BASE_URL: &str = "https://31pwr5t6ij.execute-api.eu-west-2.amazonaws.com";
+
+/// A client for the contest API.
+///
+/// Clones share everything the client remembers about the current problem, as well as the
+/// rate limiter and the HTTP connection pool, so one client can be cloned into concurrent
+/// tasks working on the same problem.
+///
+/// Successful requests are reported through the `metrics` facade, which costs next to nothing
+/// unless a recorder is installed: `aedificium_explore_calls_total` and
+/// `aedificium_guess_calls_total` count explores and guesses, `aedificium_wrong_guesses_total`
+/// counts guesses the API rejected, and `aedificium_explore_latency_seconds` is a histogram of
+/// explore latency, retries and rate limiting included.
+#[derive(Clone)]
 pub struct AedificiumRemoteClient {
     id: String,
     client: Client,
     base_url: String,
     debug: bool,
+    retry: RetryConfig,
+    rate_limiter: Option<SharedRateLimiter>,
+    observers: Observers,
+    max_plan_len: usize,
+    dedup_plans: bool,
+    cache_explores: bool,
+    problem: Arc<Mutex<ProblemState>>,
+    dry_run: bool,
+    api_version: ApiVersion,
+}
+
+/// What a client remembers about the current problem, shared between its clones. The lock is
+/// never held across a request.
+#[derive(Debug, Default)]
+struct ProblemState {
+    /// Labels per explored plan, if explores are cached.
+    explore_cache: HashMap<String, Vec<usize>>,
+    /// Query count reported by the last explore.
+    query_count: usize,
+    /// Metadata of the problem last selected, until `reset`.
+    current_problem: Option<ProblemInfo>,
+}
+
+/// A response along with the wall time of the call that produced it, from
+/// [`AedificiumRemoteClient::explore_timed`] and [`AedificiumRemoteClient::guess_timed`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timed<R> {
+    pub value: R,
+    /// Time spent in the call, including retries and waiting for the rate limiter.
+    pub elapsed: Duration,
+}
+
+/// A request as it would be POSTed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PreparedRequest {
+    pub url: String,
+    pub body: serde_json::Value,
+}
+
+/// A response with a non-success status, kept whole so callers can look into the body.
+#[derive(Debug)]
+pub(crate) struct HttpError {
+    pub(crate) status: reqwest::StatusCode,
+    pub(crate) body: String,
+}
+
+impl std::fmt::Display for HttpError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP {}: {}", self.status, self.body)
+    }
+}
+
+impl std::error::Error for HttpError {}
+
+/// A successful response whose body doesn't parse, e.g. because it was cut off in transit.
+/// Retried like a server error.
+#[derive(Debug)]
+pub struct MalformedResponse {
+    /// The start of the body, at most [`MalformedResponse::SNIPPET_LEN`] characters.
+    pub body_snippet: String,
+    pub error: serde_json::Error,
+}
+
+impl MalformedResponse {
+    pub const SNIPPET_LEN: usize = 200;
+
+    fn new(body: &str, error: serde_json::Error) -> Self {
+        Self {
+            body_snippet: body.chars().take(Self::SNIPPET_LEN).collect(),
+            error,
+        }
+    }
+}
+
+impl std::fmt::Display for MalformedResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "malformed response ({}): {}",
+            self.error, self.body_snippet
+        )
+    }
 }
 
-fn parse_bool(value: String) -> bool {
-    value.to_lowercase() == "true"
+impl std::error::Error for MalformedResponse {}
+
+/// An explore response with a different number of results than plans sent, which would pair
+/// the results with the wrong plans. Responses salvaged from a rejected explore are marked
+/// `truncated` and may have fewer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResultCountMismatch {
+    pub sent: usize,
+    pub got: usize,
+}
+
+impl std::fmt::Display for ResultCountMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sent {} plans but got {} results", self.sent, self.got)
+    }
+}
+
+impl std::error::Error for ResultCountMismatch {}
+
+/// Random UUID (version 4) used as an idempotency key.
+fn new_idempotency_key() -> String {
+    let bits =
+        rand::random::<u128>() & !(0xf000 << 64) & !(0xc << 60) | (0x4000 << 64) | (0x8 << 60);
+    let hex = format!("{:032x}", bits);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+/// Results of the plans completed before an explore was rejected, if the error response
+/// carries any.
+fn salvage_explore(error: &anyhow::Error) -> Option<ExploreResponse> {
+    let error = error.downcast_ref::<HttpError>()?;
+    if !error.status.is_client_error() {
+        return None;
+    }
+    let mut response: ExploreResponse = serde_json::from_str(&error.body).ok()?;
+    if response.results.is_empty() {
+        return None;
+    }
+    response.truncated = true;
+    Some(response)
 }
 
 impl AedificiumRemoteClient {
+    /// Creates a client with default settings, reading `AEDIFICIUM_CLIENT_DEBUG`
+    /// from the environment. Use [`ClientBuilder`] for environment-free construction.
     pub fn new(id: String) -> Self {
-        Self {
-            client: Client::new(),
-            base_url: "https://31pwr5t6ij.execute-api.eu-west-2.amazonaws.com".to_string(),
-            id,
-            debug: parse_bool(env::var("AEDIFICIUM_CLIENT_DEBUG").unwrap_or("false".to_string())),
+        ClientBuilder::from_env(id)
+            .build()
+            .expect("failed to build HTTP client")
+    }
+
+    pub fn builder(id: impl Into<String>) -> ClientBuilder {
+        ClientBuilder::new(id)
+    }
+
+    pub(crate) fn from_builder(builder: ClientBuilder) -> Result<Self> {
+        let mut client = Client::builder();
+        if let Some(timeout) = builder.timeout {
+            client = client.timeout(timeout);
+        }
+
+        Ok(Self {
+            id: builder.id,
+            client: client.build()?,
+            base_url: builder.base_url,
+            debug: builder.debug,
+            retry: builder.retry,
+            rate_limiter: builder
+                .shared_rate_limiter
+                .or_else(|| builder.rate_limit.map(SharedRateLimiter::new)),
+            observers: builder.observers,
+            max_plan_len: builder.max_plan_len,
+            dedup_plans: builder.dedup_plans,
+            cache_explores: builder.cache_explores,
+            problem: Arc::default(),
+            dry_run: builder.dry_run,
+            api_version: builder.api_version,
+        })
+    }
+
+    /// Team id the client's requests are made as.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn debug(&self) -> bool {
+        self.debug
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Forgets everything remembered about the current problem. `select` does this
+    /// implicitly. The rate limiter is kept, since the server limits requests per team rather
+    /// than per problem.
+    pub fn reset(&mut self) {
+        if self.debug {
+            println!("[DEBUG] Resetting per-problem client state");
+        }
+        *self.problem() = ProblemState::default();
+    }
+
+    fn problem(&self) -> MutexGuard<'_, ProblemState> {
+        self.problem.lock().unwrap()
+    }
+
+    /// The tier of the API this client speaks, as set by [`ClientBuilder::api_version`].
+    pub fn api_version(&self) -> ApiVersion {
+        self.api_version
+    }
+
+    /// The problem selected last, parsed from its `select` response. `None` before the first
+    /// `select` and after [`AedificiumRemoteClient::reset`].
+    pub fn current_problem(&self) -> Option<ProblemInfo> {
+        self.problem().current_problem.clone()
+    }
+
+    fn select_request(&self, problem_name: String) -> SelectRequest {
+        SelectRequest {
+            id: self.id.clone(),
+            problem_name,
+        }
+    }
+
+    fn explore_request(&self, plans: Vec<String>) -> ExploreRequest {
+        ExploreRequest {
+            id: self.id.clone(),
+            plans,
+        }
+    }
+
+    fn guess_request(&self, map: Map) -> GuessRequest {
+        GuessRequest {
+            id: self.id.clone(),
+            map,
+        }
+    }
+
+    fn prepare<T: Serialize>(&self, endpoint: &str, data: &T) -> Result<PreparedRequest> {
+        Ok(PreparedRequest {
+            url: format!("{}{}", self.base_url, endpoint),
+            body: serde_json::to_value(data)?,
+        })
+    }
+
+    /// The request `select` would send, without sending it.
+    pub fn prepare_select(&self, problem_name: &str) -> Result<PreparedRequest> {
+        self.prepare("/select", &self.select_request(problem_name.to_string()))
+    }
+
+    /// The request `explore` would send for `plans` if none of them were cached.
+    pub fn prepare_explore(&self, plans: &[String]) -> Result<PreparedRequest> {
+        self.prepare("/explore", &self.explore_request(plans.to_vec()))
+    }
+
+    /// The request `guess` would send, without sending it.
+    pub fn prepare_guess(&self, map: &Map) -> Result<PreparedRequest> {
+        self.prepare("/guess", &self.guess_request(map.clone()))
+    }
+
+    /// [`AedificiumClient::explore`], timed.
+    pub async fn explore_timed(&mut self, plans: Vec<String>) -> Result<Timed<ExploreResponse>> {
+        let started = Instant::now();
+        let value = self.explore(plans).await?;
+        Ok(Timed {
+            value,
+            elapsed: started.elapsed(),
+        })
+    }
+
+    /// [`AedificiumClient::guess`], timed.
+    pub async fn guess_timed(&self, map: Map) -> Result<Timed<GuessResponse>> {
+        let started = Instant::now();
+        let value = self.guess(map).await?;
+        Ok(Timed {
+            value,
+            elapsed: started.elapsed(),
+        })
+    }
+
+    fn print_dry_run(request: &PreparedRequest) -> Result<()> {
+        eprintln!("[DRY RUN] POST {}", request.url);
+        eprintln!("{}", serde_json::to_string_pretty(&request.body)?);
+        Ok(())
+    }
+
+    async fn explore_uncached(&mut self, plans: Vec<String>) -> Result<ExploreResponse> {
+        let data = self.explore_request(plans);
+        let response = match self.request::<_, ExploreResponse>("/explore", &data).await {
+            Ok(response) => response,
+            Err(e) => match salvage_explore(&e) {
+                Some(response) => {
+                    if self.debug {
+                        println!(
+                            "[DEBUG] Explore failed after {} of {} plans, keeping their results",
+                            response.results.len(),
+                            data.plans.len()
+                        );
+                    }
+                    response
+                }
+                None => return Err(e),
+            },
+        };
+        self.api_version.check_explore(&data.plans, &response)?;
+        self.problem().query_count = response.total_query_count();
+        Ok(response)
+    }
+
+    /// Explores the distinct plans of `plans` and spreads the results back over `plans`.
+    async fn explore_deduped(&mut self, plans: &[String]) -> Result<ExploreResponse> {
+        let mut unique: Vec<String> = Vec::new();
+        let positions: Vec<usize> = plans
+            .iter()
+            .map(|plan| match unique.iter().position(|p| p == plan) {
+                Some(index) => index,
+                None => {
+                    unique.push(plan.clone());
+                    unique.len() - 1
+                }
+            })
+            .collect();
+        if self.debug && unique.len() < plans.len() {
+            println!(
+                "[DEBUG] Sending {} distinct plans out of {}",
+                unique.len(),
+                plans.len()
+            );
+        }
+
+        let response = self.explore_uncached(unique).await?;
+        // After a truncated response, only the plans up to the first unanswered one are filled.
+        let results: Vec<Vec<usize>> = positions
+            .iter()
+            .map_while(|&index| response.results.get(index).cloned())
+            .collect();
+        Ok(ExploreResponse {
+            truncated: response.truncated || results.len() < plans.len(),
+            results,
+            query_count: response.query_count,
+        })
+    }
+
+    async fn wait_for_rate_limit(&self) {
+        let Some(limiter) = &self.rate_limiter else {
+            return;
+        };
+        let wait = limiter.acquire(Instant::now());
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
         }
     }
 
     async fn request<T, R>(&self, endpoint: &str, data: &T) -> Result<R>
+    where
+        T: Serialize,
+        R: DeserializeOwned,
+    {
+        self.send(endpoint, data, self.retry.max_retries, None)
+            .await
+    }
+
+    async fn send<T, R>(
+        &self,
+        endpoint: &str,
+        data: &T,
+        max_retries: usize,
+        idempotency_key: Option<&str>,
+    ) -> Result<R>
     where
         T: Serialize,
         R: DeserializeOwned,
@@ -43,43 +414,589 @@ impl AedificiumRemoteClient {
             println!("========================================");
         }
 
-        let response = self.client.post(&url).json(data).send().await?;
+        let mut attempt = 0;
+        loop {
+            self.wait_for_rate_limit().await;
 
-        if !response.status().is_success() {
-            return Err(anyhow::anyhow!(
-                "HTTP {}: {}",
-                response.status(),
-                response.text().await?
-            ));
-        }
+            let mut request = self.client.post(&url).json(data);
+            if let Some(key) = idempotency_key {
+                request = request.header(IDEMPOTENCY_KEY_HEADER, key);
+            }
+
+            let retriable_error = match request.send().await {
+                Ok(response) if response.status().is_success() => match response.text().await {
+                    Ok(body) => match serde_json::from_str::<R>(&body) {
+                        Ok(result) => return Ok(result),
+                        Err(e) => {
+                            if self.debug {
+                                println!("[DEBUG] Unparsable {} response: {}", endpoint, body);
+                            }
+                            MalformedResponse::new(&body, e).into()
+                        }
+                    },
+                    Err(e) => e.into(),
+                },
+                Ok(response) if response.status().is_server_error() => HttpError {
+                    status: response.status(),
+                    body: response.text().await?,
+                }
+                .into(),
+                Ok(response) => {
+                    return Err(HttpError {
+                        status: response.status(),
+                        body: response.text().await?,
+                    }
+                    .into());
+                }
+                Err(e) => e.into(),
+            };
 
-        let result = response.json::<R>().await?;
-        Ok(result)
+            if attempt >= max_retries {
+                return Err(retriable_error);
+            }
+            if self.debug {
+                println!(
+                    "[DEBUG] Retrying {} after error: {}",
+                    endpoint, retriable_error
+                );
+            }
+            tokio::time::sleep(self.retry.delay_for(attempt)).await;
+            attempt += 1;
+        }
     }
 }
 #[async_trait]
 impl AedificiumClient for AedificiumRemoteClient {
-    async fn select(&self, problem_name: String) -> Result<SelectResponse> {
-        let data = SelectRequest {
-            id: self.id.clone(),
-            problem_name,
-        };
-        self.request("/select", &data).await
+    async fn select(&mut self, problem_name: String) -> Result<SelectResponse> {
+        self.reset();
+        if self.dry_run {
+            Self::print_dry_run(&self.prepare_select(&problem_name)?)?;
+            return Ok(SelectResponse {
+                data: serde_json::json!({ "problemName": problem_name }),
+            });
+        }
+        let data = self.select_request(problem_name);
+        let response: SelectResponse = self.request("/select", &data).await?;
+        for observer in self.observers.iter() {
+            observer.on_select(&data.problem_name, &response);
+        }
+        self.problem().current_problem =
+            Some(ProblemInfo::from_select(&data.problem_name, &response));
+        Ok(response)
     }
 
     async fn explore(&mut self, plans: Vec<String>) -> Result<ExploreResponse> {
-        let data = ExploreRequest {
-            id: self.id.clone(),
-            plans,
+        let started = Instant::now();
+        if let Some((i, plan)) = plans
+            .iter()
+            .enumerate()
+            .find(|(_, plan)| plan.len() > self.max_plan_len)
+        {
+            return Err(anyhow::anyhow!(
+                "Plan {} has {} doors, more than the limit of {}; shorten it or use split_plan",
+                i,
+                plan.len(),
+                self.max_plan_len
+            ));
+        }
+        for plan in &plans {
+            self.api_version.check_plan(plan)?;
+        }
+        if self.dry_run {
+            Self::print_dry_run(&self.prepare_explore(&plans)?)?;
+            let convention = self.api_version.convention();
+            return Ok(ExploreResponse {
+                results: plans
+                    .iter()
+                    .map(|plan| vec![0; convention.expected_len(plan.chars().count())])
+                    .collect(),
+                query_count: self.problem().query_count.into(),
+                truncated: false,
+            });
+        }
+        let response = match self.cache_explores {
+            false if self.dedup_plans => self.explore_deduped(&plans).await?,
+            false => self.explore_uncached(plans.clone()).await?,
+            true => {
+                let mut missing: Vec<String> = Vec::new();
+                {
+                    let cache = &self.problem().explore_cache;
+                    for plan in &plans {
+                        if !cache.contains_key(plan) && !missing.contains(plan) {
+                            missing.push(plan.clone());
+                        }
+                    }
+                }
+                if !missing.is_empty() {
+                    let fetched = self.explore_uncached(missing.clone()).await?;
+                    if fetched.results.len() != missing.len() && !fetched.truncated {
+                        return Err(ResultCountMismatch {
+                            sent: missing.len(),
+                            got: fetched.results.len(),
+                        }
+                        .into());
+                    }
+                    self.problem()
+                        .explore_cache
+                        .extend(missing.into_iter().zip(fetched.results));
+                }
+                let problem = self.problem();
+                // After a truncated fetch, answer the plans up to the first one still missing.
+                let results: Vec<Vec<usize>> = plans
+                    .iter()
+                    .map_while(|plan| problem.explore_cache.get(plan).cloned())
+                    .collect();
+                ExploreResponse {
+                    truncated: results.len() < plans.len(),
+                    results,
+                    query_count: problem.query_count.into(),
+                }
+            }
         };
-        self.request("/explore", &data).await
+        let elapsed = started.elapsed();
+        metrics::counter!("aedificium_explore_calls_total").increment(1);
+        metrics::histogram!("aedificium_explore_latency_seconds").record(elapsed.as_secs_f64());
+        for observer in self.observers.iter() {
+            observer.on_explore(&plans, &response, elapsed);
+        }
+        Ok(response)
     }
 
     async fn guess(&self, data: Map) -> Result<GuessResponse> {
-        let data = GuessRequest {
-            id: self.id.clone(),
-            map: data,
+        if self.dry_run {
+            Self::print_dry_run(&self.prepare_guess(&data)?)?;
+            return Ok(GuessResponse { correct: false });
+        }
+        if self.debug {
+            println!(
+                "[DEBUG] Guessing a map of {} rooms and {} edges, {} bytes",
+                data.rooms.len(),
+                data.edge_count(),
+                serde_json::to_vec(&data)?.len()
+            );
+        }
+        let data = self.guess_request(data);
+        let max_retries = if self.retry.retry_guesses {
+            self.retry.max_retries
+        } else {
+            0
         };
-        self.request("/guess", &data).await
+        let key = new_idempotency_key();
+        let response: GuessResponse = self.send("/guess", &data, max_retries, Some(&key)).await?;
+        metrics::counter!("aedificium_guess_calls_total").increment(1);
+        if !response.correct {
+            metrics::counter!("aedificium_wrong_guesses_total").increment(1);
+        }
+        for observer in self.observers.iter() {
+            observer.on_guess(&data.map, &response);
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use icfpc2025_common::{MapBuilder, DOORS};
+
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use crate::observer::ClientObserver;
+
+    fn map() -> Map {
+        MapBuilder::new().room(0, 0).build().unwrap()
+    }
+
+    #[test]
+    fn test_idempotency_key_format() {
+        let key = new_idempotency_key();
+        assert_eq!(key.len(), 36);
+        assert_eq!(&key[14..15], "4");
+        assert!(matches!(&key[19..20], "8" | "9" | "a" | "b"));
+        assert_ne!(key, new_idempotency_key());
+    }
+
+    #[tokio::test]
+    async fn test_explore_is_retried() {
+        let server = MockServer::start(vec![
+            MockResponse::status(500, "oops"),
+            MockResponse::ok(r#"{"results":[[0]],"queryCount":1}"#),
+        ])
+        .await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .retry(RetryConfig::new(2, Duration::from_millis(1)))
+            .build()
+            .unwrap();
+
+        let response = client.explore(vec!["".to_string()]).await.unwrap();
+        assert_eq!(response.total_query_count(), 1);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_malformed_response_is_retried() {
+        let server = MockServer::start(vec![
+            MockResponse::ok(r#"{"results":[[0,1],[0"#),
+            MockResponse::ok(r#"{"results":[[0]],"queryCount":1}"#),
+            MockResponse::ok(r#"{"results":"#),
+        ])
+        .await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .retry(RetryConfig::new(1, Duration::from_millis(1)))
+            .build()
+            .unwrap();
+
+        let response = client.explore(vec!["".to_string()]).await.unwrap();
+        assert_eq!(response.results, vec![vec![0]]);
+
+        let error = client.guess(map()).await.unwrap_err();
+        let malformed = error.downcast_ref::<MalformedResponse>().unwrap();
+        assert_eq!(malformed.body_snippet, r#"{"results":"#);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_guess_is_not_retried_by_default() {
+        let server = MockServer::start(vec![MockResponse::status(500, "oops")]).await;
+        let client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .retry(RetryConfig::new(3, Duration::from_millis(1)))
+            .build()
+            .unwrap();
+
+        assert!(client.guess(map()).await.is_err());
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/guess");
+        assert!(requests[0].body.contains(r#""map""#));
+        assert!(requests[0].header(IDEMPOTENCY_KEY_HEADER).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_guess_retries_reuse_idempotency_key() {
+        let server = MockServer::start(vec![
+            MockResponse::status(503, "busy"),
+            MockResponse::status(503, "busy"),
+            MockResponse::ok(r#"{"correct":true}"#),
+        ])
+        .await;
+        let client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .retry(RetryConfig::new(3, Duration::from_millis(1)).with_guess_retries(true))
+            .build()
+            .unwrap();
+
+        assert!(client.guess(map()).await.unwrap().correct);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 3);
+        let key = requests[0].header(IDEMPOTENCY_KEY_HEADER).unwrap();
+        assert!(requests
+            .iter()
+            .all(|r| r.header(IDEMPOTENCY_KEY_HEADER) == Some(key)));
+
+        // A new guess gets a new key.
+        client.guess(map()).await.unwrap();
+        assert_ne!(
+            server.requests()[3].header(IDEMPOTENCY_KEY_HEADER),
+            Some(key)
+        );
+    }
+
+    #[derive(Default)]
+    struct CountingObserver {
+        explored_plans: Mutex<Vec<String>>,
+        guesses: Mutex<Vec<bool>>,
+    }
+
+    impl ClientObserver for CountingObserver {
+        fn on_explore(&self, plans: &[String], _response: &ExploreResponse, _elapsed: Duration) {
+            self.explored_plans.lock().unwrap().extend_from_slice(plans);
+        }
+
+        fn on_guess(&self, _map: &Map, response: &GuessResponse) {
+            self.guesses.lock().unwrap().push(response.correct);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_observer_sees_successful_requests() {
+        let server = MockServer::start(vec![
+            MockResponse::ok(r#"{"results":[[0,1]],"queryCount":2}"#),
+            MockResponse::status(400, "bad"),
+        ])
+        .await;
+        let observer = Arc::new(CountingObserver::default());
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .observer(observer.clone())
+            .build()
+            .unwrap();
+
+        client.explore(vec!["0".to_string()]).await.unwrap();
+        assert!(client.guess(map()).await.is_err());
+        assert_eq!(*observer.explored_plans.lock().unwrap(), vec!["0"]);
+        assert!(observer.guesses.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_select_clears_explore_cache() {
+        let server = MockServer::start(vec![
+            MockResponse::ok(r#"{"problemName":"a"}"#),
+            MockResponse::ok(r#"{"results":[[0,1]],"queryCount":2}"#),
+            MockResponse::ok(r#"{"problemName":"b"}"#),
+            MockResponse::ok(r#"{"results":[[3,2]],"queryCount":2}"#),
+        ])
+        .await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .cache_explores(true)
+            .build()
+            .unwrap();
+
+        client.select("a".to_string()).await.unwrap();
+        let first = client.explore(vec!["0".to_string()]).await.unwrap();
+        let again = client
+            .explore(vec!["0".to_string(), "0".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(again.results, vec![vec![0, 1], vec![0, 1]]);
+        assert_eq!(again.query_count, first.query_count);
+        assert_eq!(server.requests().len(), 2);
+
+        client.select("b".to_string()).await.unwrap();
+        let other = client.explore(vec!["0".to_string()]).await.unwrap();
+        assert_eq!(other.results, vec![vec![3, 2]]);
+        let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(paths, ["/select", "/explore", "/select", "/explore"]);
+    }
+
+    #[tokio::test]
+    async fn test_timed_calls_measure_the_request() {
+        let delay = Duration::from_millis(50);
+        let server = MockServer::start(vec![
+            MockResponse {
+                delay,
+                ..MockResponse::ok(r#"{"results":[[0,1]],"queryCount":2}"#)
+            },
+            MockResponse {
+                delay,
+                ..MockResponse::ok(r#"{"correct":true}"#)
+            },
+        ])
+        .await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .build()
+            .unwrap();
+
+        let explored = client.explore_timed(vec!["0".to_string()]).await.unwrap();
+        assert_eq!(explored.value.results, vec![vec![0, 1]]);
+        assert!(explored.elapsed >= delay, "{:?}", explored.elapsed);
+        let guessed = client.guess_timed(map()).await.unwrap();
+        assert!(guessed.value.correct);
+        assert!(guessed.elapsed >= delay, "{:?}", guessed.elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_clones_share_one_problem_across_tasks() {
+        fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+        assert_shareable::<AedificiumRemoteClient>();
+
+        let server = MockServer::start(vec![
+            MockResponse::ok(r#"{"problemName":"probatio"}"#),
+            MockResponse::ok(r#"{"results":[[0,1]],"queryCount":2}"#),
+        ])
+        .await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .cache_explores(true)
+            .build()
+            .unwrap();
+        client.select("probatio".to_string()).await.unwrap();
+
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let mut client = client.clone();
+                tokio::spawn(async move { client.explore(vec!["0".to_string()]).await })
+            })
+            .collect();
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap().results, vec![vec![0, 1]]);
+        }
+
+        // Every clone sees the problem selected, and what any of them explored.
+        assert_eq!(client.clone().current_problem().unwrap().name, "probatio");
+        let requests = server.requests().len();
+        client.clone().explore(vec!["0".to_string()]).await.unwrap();
+        assert_eq!(server.requests().len(), requests);
+
+        client.clone().reset();
+        assert_eq!(client.current_problem(), None);
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_sends_nothing() {
+        let server = MockServer::start(vec![]).await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .dry_run(true)
+            .build()
+            .unwrap();
+
+        let plans = vec!["012".to_string(), "5".to_string()];
+        assert_eq!(
+            client.prepare_explore(&plans).unwrap(),
+            PreparedRequest {
+                url: format!("{}/explore", server.base_url),
+                body: serde_json::json!({ "id": "team", "plans": ["012", "5"] }),
+            }
+        );
+        client.select("probatio".to_string()).await.unwrap();
+        let response = client.explore(plans).await.unwrap();
+        assert_eq!(response.results, vec![vec![0; 4], vec![0; 2]]);
+        assert!(!client.guess(map()).await.unwrap().correct);
+        assert!(client.explore(vec!["0".repeat(1000)]).await.is_err());
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_current_problem() {
+        let server = MockServer::start(vec![MockResponse::ok(
+            r#"{"problemName":"secundus","remainingBudget":100}"#,
+        )])
+        .await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .build()
+            .unwrap();
+        assert_eq!(client.current_problem(), None);
+
+        client.select("secundus".to_string()).await.unwrap();
+        assert_eq!(
+            client.current_problem(),
+            Some(ProblemInfo {
+                name: "secundus".to_string(),
+                room_count: Some(12),
+                budget: Some(100),
+            })
+        );
+
+        client.reset();
+        assert_eq!(client.current_problem(), None);
+    }
+
+    #[tokio::test]
+    async fn test_explore_rejects_out_of_range_doors_before_sending() {
+        let server = MockServer::start(vec![MockResponse::ok(
+            r#"{"results":[[0,1]],"queryCount":2}"#,
+        )])
+        .await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .build()
+            .unwrap();
+
+        let plan = format!("01{}", DOORS);
+        let error = client.explore(vec![plan]).await.unwrap_err();
+        assert!(error.to_string().contains(&DOORS.to_string()), "{}", error);
+        assert!(server.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_explore_rejects_missing_results() {
+        let server = MockServer::start(vec![MockResponse::ok(
+            r#"{"results":[[0,1]],"queryCount":3}"#,
+        )])
+        .await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .build()
+            .unwrap();
+
+        let error = client
+            .explore(vec!["0".to_string(), "1".to_string()])
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ResultCountMismatch>(),
+            Some(&ResultCountMismatch { sent: 2, got: 1 })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_explore_keeps_partial_results_of_rejected_batch() {
+        let server = MockServer::start(vec![
+            MockResponse::status(400, r#"{"results":[[0,1]],"queryCount":3}"#),
+            MockResponse::status(400, "out of budget"),
+        ])
+        .await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .cache_explores(true)
+            .build()
+            .unwrap();
+
+        let response = client
+            .explore(vec!["0".to_string(), "1".to_string(), "0".to_string()])
+            .await
+            .unwrap();
+        assert!(response.truncated);
+        assert_eq!(response.results, vec![vec![0, 1]]);
+        assert_eq!(response.total_query_count(), 3);
+
+        // An error without results is still an error.
+        assert!(client.explore(vec!["1".to_string()]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dedup_plans_sends_each_plan_once() {
+        let server = MockServer::start(vec![MockResponse::ok(
+            r#"{"results":[[0,1],[0,2]],"queryCount":2}"#,
+        )])
+        .await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .dedup_plans(true)
+            .build()
+            .unwrap();
+
+        let plans = ["0", "1", "0", "0"].map(String::from).to_vec();
+        let response = client.explore(plans).await.unwrap();
+        assert_eq!(
+            response.results,
+            vec![vec![0, 1], vec![0, 2], vec![0, 1], vec![0, 1]]
+        );
+        assert!(!response.truncated);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        let body: serde_json::Value = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(body["plans"], serde_json::json!(["0", "1"]));
+    }
+
+    #[tokio::test]
+    async fn test_explore_rejects_long_plans() {
+        let server = MockServer::start(vec![MockResponse::ok(
+            r#"{"results":[[0,1,2,3]],"queryCount":4}"#,
+        )])
+        .await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .max_plan_len(3)
+            .build()
+            .unwrap();
+
+        let err = client
+            .explore(vec!["0".to_string(), "0123".to_string()])
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Plan 1 has 4 doors"));
+        assert!(server.requests().is_empty());
+
+        client.explore(vec!["012".to_string()]).await.unwrap();
+        assert_eq!(server.requests().len(), 1);
     }
 }
@@ -0,0 +1,155 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How failed requests are retried.
+///
+/// Only transport errors and `5xx` responses are retried; a `4xx` response is
+/// returned to the caller immediately.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryConfig {
+    pub max_retries: usize,
+    /// Delay before the first retry. Doubled on every subsequent retry.
+    pub backoff: Duration,
+    /// Whether `guess` is retried too. Off by default: a guess that timed out may still have
+    /// reached the server, and retrying it would spend a second attempt on the same map.
+    /// Retries reuse the request's idempotency key so the server can spot the duplicate.
+    pub retry_guesses: bool,
+}
+
+impl RetryConfig {
+    pub fn new(max_retries: usize, backoff: Duration) -> Self {
+        Self {
+            max_retries,
+            backoff,
+            retry_guesses: false,
+        }
+    }
+
+    pub fn with_guess_retries(mut self, retry_guesses: bool) -> Self {
+        self.retry_guesses = retry_guesses;
+        self
+    }
+
+    pub fn disabled() -> Self {
+        Self::new(0, Duration::ZERO)
+    }
+
+    pub(crate) fn delay_for(&self, attempt: usize) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(attempt.min(16) as u32))
+    }
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+/// Allows at most `max_requests` requests per `per`, with bursts up to `max_requests`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub max_requests: usize,
+    pub per: Duration,
+}
+
+impl RateLimit {
+    pub fn new(max_requests: usize, per: Duration) -> Self {
+        Self { max_requests, per }
+    }
+}
+
+/// One [`RateLimit`] shared by several clients, e.g. clients of different sessions that solve
+/// problems concurrently but count against the same server-side limit.
+#[derive(Debug, Clone)]
+pub struct SharedRateLimiter(pub(crate) Arc<Mutex<RateLimiter>>);
+
+impl SharedRateLimiter {
+    pub fn new(limit: RateLimit) -> Self {
+        Self(Arc::new(Mutex::new(RateLimiter::new(limit))))
+    }
+
+    /// Takes one token and returns how long the caller has to wait before sending.
+    pub(crate) fn acquire(&self, now: Instant) -> Duration {
+        self.0.lock().unwrap().acquire(now)
+    }
+}
+
+/// Token bucket backing [`RateLimit`].
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    limit: RateLimit,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            tokens: limit.max_requests as f64,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn rate(&self) -> f64 {
+        self.limit.max_requests as f64 / self.limit.per.as_secs_f64()
+    }
+
+    /// Takes one token and returns how long the caller has to wait before sending.
+    pub(crate) fn acquire(&mut self, now: Instant) -> Duration {
+        if self.limit.max_requests == 0 || self.limit.per.is_zero() {
+            return Duration::ZERO;
+        }
+
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.last_refill = now;
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * self.rate()).min(self.limit.max_requests as f64);
+        self.tokens -= 1.0;
+
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_backoff_doubles() {
+        let retry = RetryConfig::new(3, Duration::from_millis(100));
+        assert_eq!(retry.delay_for(0), Duration::from_millis(100));
+        assert_eq!(retry.delay_for(1), Duration::from_millis(200));
+        assert_eq!(retry.delay_for(2), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_rate_limiter_allows_burst_then_waits() {
+        let mut limiter = RateLimiter::new(RateLimit::new(2, Duration::from_secs(1)));
+        let now = limiter.last_refill;
+
+        assert_eq!(limiter.acquire(now), Duration::ZERO);
+        assert_eq!(limiter.acquire(now), Duration::ZERO);
+        assert_eq!(limiter.acquire(now), Duration::from_millis(500));
+
+        // Half a second later the bucket has refilled the token we borrowed.
+        let later = now + Duration::from_millis(500);
+        assert_eq!(limiter.acquire(later), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_shared_rate_limiter_pools_tokens() {
+        let shared = SharedRateLimiter::new(RateLimit::new(2, Duration::from_secs(1)));
+        let other = shared.clone();
+        let now = shared.0.lock().unwrap().last_refill;
+
+        assert_eq!(shared.acquire(now), Duration::ZERO);
+        assert_eq!(other.acquire(now), Duration::ZERO);
+        assert_eq!(shared.acquire(now), Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,101 @@
+use anyhow::Result;
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A point in time by which a request has to be answered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Deadline(Instant);
+
+impl Deadline {
+    pub fn at(instant: Instant) -> Self {
+        Self(instant)
+    }
+
+    pub fn after(duration: Duration) -> Self {
+        Self(Instant::now() + duration)
+    }
+
+    pub fn instant(&self) -> Instant {
+        self.0
+    }
+
+    /// Time left until the deadline, zero once it has passed.
+    pub fn remaining(&self) -> Duration {
+        self.0.saturating_duration_since(Instant::now())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.remaining().is_zero()
+    }
+}
+
+/// Returned by [`with_deadline`] when the deadline passes first. Recover it from the
+/// `anyhow::Error` with `downcast_ref::<Timeout>()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timeout;
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "deadline exceeded")
+    }
+}
+
+impl std::error::Error for Timeout {}
+
+/// Runs `request` until `deadline`, failing with [`Timeout`] if it hasn't finished by then.
+///
+/// On timeout the request future is dropped, which aborts an in-flight HTTP request; the
+/// server may still have processed it, so an explore that timed out may have been charged.
+pub async fn with_deadline<T>(
+    deadline: Deadline,
+    request: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::select! {
+        result = request => result,
+        _ = tokio::time::sleep_until(deadline.0) => Err(Timeout.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use icfpc2025_common::AedificiumClient;
+
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use crate::ClientBuilder;
+
+    #[tokio::test]
+    async fn test_slow_explore_times_out() {
+        let slow = MockResponse {
+            delay: Duration::from_secs(5),
+            ..MockResponse::ok(r#"{"results":[[0]],"queryCount":1}"#)
+        };
+        let server = MockServer::start(vec![
+            slow,
+            MockResponse::ok(r#"{"results":[[0]],"queryCount":2}"#),
+        ])
+        .await;
+        let mut client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .build()
+            .unwrap();
+
+        let started = std::time::Instant::now();
+        let deadline = Deadline::after(Duration::from_millis(100));
+        let error = with_deadline(deadline, client.explore(vec![String::new()]))
+            .await
+            .unwrap_err();
+        assert_eq!(error.downcast_ref::<Timeout>(), Some(&Timeout));
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert!(deadline.is_expired());
+
+        // The client is still usable after the cancelled request.
+        let deadline = Deadline::after(Duration::from_secs(5));
+        let response = with_deadline(deadline, client.explore(vec![String::new()]))
+            .await
+            .unwrap();
+        assert_eq!(response.total_query_count(), 2);
+    }
+}
@@ -0,0 +1,233 @@
+//! Keeps the team id in the OS secret store instead of a `.env` file that might get committed.
+//!
+//! The store is driven through its command-line tool: `security` for the macOS keychain and
+//! `secret-tool` for the Secret Service (GNOME Keyring, KWallet) elsewhere on Unix. Other
+//! platforms have no backend.
+
+use anyhow::{bail, Context, Result};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+/// Service name the team id is stored under.
+pub const SERVICE: &str = "icfpc2025-aedificium";
+/// Account name the team id is stored under.
+pub const ACCOUNT: &str = "ICFPC_TEAM_ID";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    MacKeychain,
+    SecretService,
+}
+
+impl Backend {
+    fn current() -> Result<Self> {
+        if cfg!(target_os = "macos") {
+            Ok(Backend::MacKeychain)
+        } else if cfg!(unix) {
+            Ok(Backend::SecretService)
+        } else {
+            bail!("No OS secret store is supported on this platform")
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Backend::MacKeychain => "security",
+            Backend::SecretService => "secret-tool",
+        }
+    }
+
+    /// The command storing `team_id`, and what to write to its stdin. The secret is always
+    /// passed on stdin, so it never shows up in the process list: `secret-tool` reads it from
+    /// there, and `security` only takes it as an argument, so it gets the whole command on stdin
+    /// in interactive mode.
+    fn store(self, team_id: &str) -> (Command, Option<String>) {
+        let mut command = Command::new(self.program());
+        match self {
+            Backend::MacKeychain => {
+                command.arg("-i");
+                let line = format!(
+                    "add-generic-password -U -s {} -a {} -w {}\n",
+                    quote(SERVICE),
+                    quote(ACCOUNT),
+                    quote(team_id)
+                );
+                (command, Some(line))
+            }
+            Backend::SecretService => {
+                command.args([
+                    "store",
+                    "--label=ICFPC 2025 team id",
+                    "service",
+                    SERVICE,
+                    "account",
+                    ACCOUNT,
+                ]);
+                (command, Some(team_id.to_string()))
+            }
+        }
+    }
+
+    /// Whether a failed lookup or delete failed because nothing was stored. `security` exits
+    /// with `errSecItemNotFound` (44); `secret-tool lookup` exits with 1 and says nothing.
+    fn is_not_found(self, output: &Output) -> bool {
+        match self {
+            Backend::MacKeychain => output.status.code() == Some(44),
+            Backend::SecretService => output.status.code() == Some(1) && output.stderr.is_empty(),
+        }
+    }
+
+    fn lookup(self) -> Command {
+        let mut command = Command::new(self.program());
+        match self {
+            Backend::MacKeychain => {
+                command.args(["find-generic-password", "-s", SERVICE, "-a", ACCOUNT, "-w"])
+            }
+            Backend::SecretService => {
+                command.args(["lookup", "service", SERVICE, "account", ACCOUNT])
+            }
+        };
+        command
+    }
+
+    fn delete(self) -> Command {
+        let mut command = Command::new(self.program());
+        match self {
+            Backend::MacKeychain => {
+                command.args(["delete-generic-password", "-s", SERVICE, "-a", ACCOUNT])
+            }
+            Backend::SecretService => {
+                command.args(["clear", "service", SERVICE, "account", ACCOUNT])
+            }
+        };
+        command
+    }
+}
+
+/// `arg` as one word for `security -i`, which splits its input lines like a shell.
+fn quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn run(mut command: Command, stdin: Option<String>) -> Result<Output> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(if stdin.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {}; is it installed?", program))?;
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        pipe.write_all(input.as_bytes())?;
+    }
+    Ok(child.wait_with_output()?)
+}
+
+/// Fails with the tool's own message about what went wrong.
+fn failed(what: &str, output: &Output) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Failed to {}: {}",
+        what,
+        String::from_utf8_lossy(&output.stderr).trim()
+    )
+}
+
+/// Stores `team_id` in the OS secret store, replacing any stored before.
+pub fn store_team_id(team_id: &str) -> Result<()> {
+    let (command, stdin) = Backend::current()?.store(team_id);
+    let output = run(command, stdin)?;
+    if !output.status.success() {
+        return Err(failed("store the team id", &output));
+    }
+    Ok(())
+}
+
+/// The team id in the OS secret store, or `None` if none is stored.
+pub fn load_team_id() -> Result<Option<String>> {
+    let backend = Backend::current()?;
+    let output = run(backend.lookup(), None)?;
+    if !output.status.success() {
+        if backend.is_not_found(&output) {
+            return Ok(None);
+        }
+        return Err(failed("look up the team id", &output));
+    }
+    let team_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok((!team_id.is_empty()).then_some(team_id))
+}
+
+/// Removes the team id from the OS secret store. Returns `false` if none was stored.
+pub fn delete_team_id() -> Result<bool> {
+    let backend = Backend::current()?;
+    // `secret-tool clear` succeeds whether or not anything matched, so look first.
+    if backend == Backend::SecretService && load_team_id()?.is_none() {
+        return Ok(false);
+    }
+    let output = run(backend.delete(), None)?;
+    if !output.status.success() {
+        if backend.is_not_found(&output) {
+            return Ok(false);
+        }
+        return Err(failed("remove the team id", &output));
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_commands_name_the_entry() {
+        for backend in [Backend::MacKeychain, Backend::SecretService] {
+            let (store, stdin) = backend.store("secret-id");
+            let stdin = stdin.unwrap();
+            assert_eq!(store.get_program(), backend.program());
+            let store_args = args(&store);
+            assert!(!store_args.iter().any(|arg| arg.contains("secret-id")));
+            assert!(stdin.contains("secret-id"), "{:?}", stdin);
+
+            for command in [&backend.lookup(), &backend.delete()] {
+                assert_eq!(command.get_program(), backend.program());
+                let args = args(command);
+                assert!(args.iter().any(|arg| arg == SERVICE), "{:?}", args);
+                assert!(args.iter().any(|arg| arg == ACCOUNT), "{:?}", args);
+            }
+        }
+        let (_, stdin) = Backend::MacKeychain.store("secret-id");
+        assert!(stdin.unwrap().contains(&quote(SERVICE)));
+    }
+
+    #[test]
+    fn test_quote_escapes_quotes_and_backslashes() {
+        assert_eq!(quote("abc"), "\"abc\"");
+        assert_eq!(quote("a\"b\\c"), "\"a\\\"b\\\\c\"");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_not_found_is_told_apart_from_failures() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let output = |code: i32, stderr: &str| Output {
+            status: std::process::ExitStatus::from_raw(code << 8),
+            stdout: Vec::new(),
+            stderr: stderr.as_bytes().to_vec(),
+        };
+        assert!(Backend::MacKeychain.is_not_found(&output(44, "could not be found")));
+        assert!(!Backend::MacKeychain.is_not_found(&output(51, "user interaction not allowed")));
+        assert!(Backend::SecretService.is_not_found(&output(1, "")));
+        assert!(!Backend::SecretService.is_not_found(&output(1, "Cannot autolaunch D-Bus")));
+    }
+}
@@ -1,3 +1,24 @@
+pub mod anomaly;
+pub mod builder;
 pub mod client;
+pub mod config;
+pub mod deadline;
+#[cfg(feature = "keyring")]
+pub mod keyring;
+#[cfg(test)]
+mod mock;
+pub mod observer;
+pub mod schedule;
+pub mod version;
 
-pub use client::AedificiumRemoteClient;
+pub use anomaly::AnomalyDetector;
+pub use builder::ClientBuilder;
+pub use client::{
+    AedificiumRemoteClient, MalformedResponse, PreparedRequest, ResultCountMismatch, Timed,
+    DEFAULT_MAX_PLAN_LEN,
+};
+pub use config::{RateLimit, RetryConfig, SharedRateLimiter};
+pub use deadline::{with_deadline, Deadline, Timeout};
+pub use observer::ClientObserver;
+pub use schedule::schedule_explores;
+pub use version::ApiVersion;
@@ -0,0 +1,31 @@
+use icfpc2025_common::{ExploreResponse, GuessResponse, Map, SelectResponse};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Hooks called by [`AedificiumRemoteClient`](crate::AedificiumRemoteClient) after each
+/// successful request. Every method does nothing by default.
+pub trait ClientObserver: Send + Sync {
+    fn on_select(&self, _problem_name: &str, _response: &SelectResponse) {}
+
+    /// `elapsed` covers the whole request, including retries and rate limiting.
+    fn on_explore(&self, _plans: &[String], _response: &ExploreResponse, _elapsed: Duration) {}
+
+    fn on_guess(&self, _map: &Map, _response: &GuessResponse) {}
+}
+
+/// Observers registered on a client, called in registration order.
+#[derive(Clone, Default)]
+pub(crate) struct Observers(pub(crate) Vec<Arc<dyn ClientObserver>>);
+
+impl Observers {
+    pub(crate) fn iter(&self) -> impl Iterator<Item = &Arc<dyn ClientObserver>> {
+        self.0.iter()
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
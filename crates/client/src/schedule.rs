@@ -0,0 +1,101 @@
+use anyhow::Result;
+use icfpc2025_common::{AedificiumClient, ExploreResponse};
+use std::time::Instant;
+use tokio::sync::mpsc;
+
+use crate::config::{RateLimit, RateLimiter};
+
+/// Sends every batch of plans as one explore call, from a task of its own, pacing the calls
+/// by `rate` so they go out as fast as the limit allows instead of piling up against it.
+///
+/// Responses arrive on the returned channel in the order of `batches`, each as soon as its call
+/// returns, so the caller can reconstruct from the first while the rest are still on their way.
+/// The channel closes after the last batch, or after the first error, which is sent as well.
+/// Dropping the receiver stops sending further batches.
+///
+/// `client` should not have a rate limit of its own for the same requests, or every call waits
+/// twice.
+pub fn schedule_explores<C>(
+    mut client: C,
+    batches: Vec<Vec<String>>,
+    rate: RateLimit,
+) -> mpsc::Receiver<Result<ExploreResponse>>
+where
+    C: AedificiumClient + Send + 'static,
+{
+    let (sender, receiver) = mpsc::channel(batches.len().max(1));
+    tokio::spawn(async move {
+        let mut limiter = RateLimiter::new(rate);
+        for plans in batches {
+            tokio::time::sleep(limiter.acquire(Instant::now())).await;
+            let response = client.explore(plans).await;
+            let failed = response.is_err();
+            if sender.send(response).await.is_err() || failed {
+                return;
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::mock::{MockResponse, MockServer};
+    use crate::ClientBuilder;
+
+    #[tokio::test]
+    async fn test_explores_are_paced_by_the_rate_limit() {
+        let server = MockServer::start(vec![MockResponse::ok(
+            r#"{"results":[[0,1]],"queryCount":2}"#,
+        )])
+        .await;
+        let client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .build()
+            .unwrap();
+        let batches = vec![vec!["0".to_string()]; 5];
+
+        // Two calls right away, then one every 100ms.
+        let started = Instant::now();
+        let mut responses = schedule_explores(
+            client,
+            batches,
+            RateLimit::new(2, Duration::from_millis(200)),
+        );
+        let mut received = 0;
+        while let Some(response) = responses.recv().await {
+            assert_eq!(response.unwrap().results, vec![vec![0, 1]]);
+            received += 1;
+        }
+        let elapsed = started.elapsed();
+
+        assert_eq!(received, 5);
+        assert_eq!(server.requests().len(), 5);
+        assert!(elapsed >= Duration::from_millis(290), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn test_first_error_closes_the_channel() {
+        let server = MockServer::start(vec![
+            MockResponse::ok(r#"{"results":[[0,1]],"queryCount":2}"#),
+            MockResponse::status(400, "bad plan"),
+        ])
+        .await;
+        let client = ClientBuilder::new("team")
+            .base_url(&server.base_url)
+            .build()
+            .unwrap();
+        let batches = vec![vec!["0".to_string()]; 4];
+
+        let mut responses =
+            schedule_explores(client, batches, RateLimit::new(10, Duration::from_secs(1)));
+        assert!(responses.recv().await.unwrap().is_ok());
+        assert!(responses.recv().await.unwrap().is_err());
+        assert!(responses.recv().await.is_none());
+        assert_eq!(server.requests().len(), 2);
+    }
+}
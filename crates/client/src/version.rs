@@ -0,0 +1,180 @@
+use anyhow::{bail, Context, Result};
+use icfpc2025_common::{
+    parse_plan_steps, ExploreResponse, ObservationConvention, PlanStep, QueryCount,
+};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::client::ResultCountMismatch;
+
+/// The request and response shapes of one tier of the API. Everything that differs between
+/// tiers is decided here, so switching tiers is a matter of
+/// [`crate::ClientBuilder::api_version`].
+///
+/// [`ApiVersion::V3`] is the API as observed; the other tiers are guesses at how it might
+/// differ.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum ApiVersion {
+    /// Results leave out the room behind the last door, `queryCount` has one count per plan,
+    /// and plans are doors only.
+    V1,
+    /// Results include the room behind the last door, `queryCount` is a single number, and
+    /// plans are doors only.
+    V2,
+    /// Like [`ApiVersion::V2`], but plans may also [`icfpc2025_common::RESET`] to the
+    /// starting room.
+    #[default]
+    V3,
+}
+
+impl ApiVersion {
+    pub const LATEST: ApiVersion = ApiVersion::V3;
+    pub const ALL: [ApiVersion; 3] = [ApiVersion::V1, ApiVersion::V2, ApiVersion::V3];
+
+    /// Which labels explore results include.
+    pub fn convention(self) -> ObservationConvention {
+        match self {
+            ApiVersion::V1 => ObservationConvention::ExcludingFinal,
+            ApiVersion::V2 | ApiVersion::V3 => ObservationConvention::IncludingFinal,
+        }
+    }
+
+    /// Whether `queryCount` has one count per plan rather than a single total.
+    pub fn per_plan_query_count(self) -> bool {
+        self == ApiVersion::V1
+    }
+
+    /// Whether plans may contain [`icfpc2025_common::RESET`].
+    pub fn allows_reset(self) -> bool {
+        self == ApiVersion::V3
+    }
+
+    /// Fails on a plan this version can't send. No version takes charcoal marks yet.
+    pub fn check_plan(self, plan: &str) -> Result<()> {
+        let steps = parse_plan_steps(plan)?;
+        if !self.allows_reset() && steps.contains(&PlanStep::Reset) {
+            bail!("{} plans can't reset: {:?}", self, plan);
+        }
+        if steps.iter().any(|step| matches!(step, PlanStep::Mark(_))) {
+            bail!("{} plans can't mark rooms: {:?}", self, plan);
+        }
+        Ok(())
+    }
+
+    /// Fails unless `response` has this version's shape for `plans`, with a
+    /// [`ResultCountMismatch`] unless there is a result for every plan. Only the plans answered
+    /// are checked if the response is truncated.
+    pub fn check_explore(self, plans: &[String], response: &ExploreResponse) -> Result<()> {
+        match &response.query_count {
+            QueryCount::Total(_) if self.per_plan_query_count() => {
+                bail!("{} reports queryCount per plan, got a single number", self)
+            }
+            QueryCount::PerPlan(_) if !self.per_plan_query_count() => {
+                bail!("{} reports queryCount as a single number, got a list", self)
+            }
+            QueryCount::PerPlan(counts) if counts.len() != response.results.len() => bail!(
+                "{} query counts for {} results",
+                counts.len(),
+                response.results.len()
+            ),
+            _ => {}
+        }
+        let got = response.results.len();
+        if got > plans.len() || (got < plans.len() && !response.truncated) {
+            return Err(ResultCountMismatch {
+                sent: plans.len(),
+                got,
+            }
+            .into());
+        }
+        for (i, (plan, labels)) in plans.iter().zip(&response.results).enumerate() {
+            self.convention()
+                .check(plan.chars().count(), labels)
+                .with_context(|| format!("result {} doesn't fit {}", i, self))?;
+        }
+        Ok(())
+    }
+
+    /// Parses an explore response body sent for `plans`, checking it has this version's shape.
+    pub fn decode_explore(self, plans: &[String], body: &str) -> Result<ExploreResponse> {
+        let response = serde_json::from_str(body)?;
+        self.check_explore(plans, &response)?;
+        Ok(response)
+    }
+}
+
+impl fmt::Display for ApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ApiVersion::V1 => "v1",
+            ApiVersion::V2 => "v2",
+            ApiVersion::V3 => "v3",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for ApiVersion {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        ApiVersion::ALL
+            .into_iter()
+            .find(|version| version.to_string().eq_ignore_ascii_case(s))
+            .with_context(|| format!("Unknown API version {:?}; expected v1, v2 or v3", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_sample_responses() {
+        let plans = vec!["01".to_string(), "".to_string()];
+        let v1 = r#"{"results":[[0,1],[]],"queryCount":[3,1]}"#;
+        let v2 = r#"{"results":[[0,1,2],[0]],"queryCount":4}"#;
+
+        let response = ApiVersion::V1.decode_explore(&plans, v1).unwrap();
+        assert_eq!(response.query_count.per_plan(), Some(&[3, 1][..]));
+        assert_eq!(response.results, vec![vec![0, 1], vec![]]);
+        for version in [ApiVersion::V2, ApiVersion::V3] {
+            let response = version.decode_explore(&plans, v2).unwrap();
+            assert_eq!(response.total_query_count(), 4);
+            assert_eq!(response.results, vec![vec![0, 1, 2], vec![0]]);
+        }
+
+        // Each version rejects the other's shape.
+        assert!(ApiVersion::V1.decode_explore(&plans, v2).is_err());
+        assert!(ApiVersion::V3.decode_explore(&plans, v1).is_err());
+        // Right queryCount, wrong number of labels.
+        let mislabelled = r#"{"results":[[0,1],[0]],"queryCount":4}"#;
+        assert!(ApiVersion::V3.decode_explore(&plans, mislabelled).is_err());
+
+        // A dropped result would shift the rest onto the wrong plans.
+        let short = r#"{"results":[[0,1,2]],"queryCount":4}"#;
+        let error = ApiVersion::V3.decode_explore(&plans, short).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ResultCountMismatch>(),
+            Some(&ResultCountMismatch { sent: 2, got: 1 })
+        );
+
+        // A reset observes the starting room again.
+        let reset = vec!["0R1".to_string()];
+        let body = r#"{"results":[[0,1,0,2]],"queryCount":5}"#;
+        assert!(ApiVersion::V3.decode_explore(&reset, body).is_ok());
+        assert!(ApiVersion::V2.check_plan(&reset[0]).is_err());
+        assert!(ApiVersion::V3.check_plan(&reset[0]).is_ok());
+        assert!(ApiVersion::V3.check_plan("0[1]0").is_err());
+    }
+
+    #[test]
+    fn test_parse_version() {
+        assert_eq!(ApiVersion::default(), ApiVersion::LATEST);
+        for version in ApiVersion::ALL {
+            assert_eq!(version.to_string().parse::<ApiVersion>().unwrap(), version);
+        }
+        assert_eq!("V2".parse::<ApiVersion>().unwrap(), ApiVersion::V2);
+        assert!("v4".parse::<ApiVersion>().is_err());
+    }
+}
@@ -0,0 +1,228 @@
+//! A compact binary encoding of [`ObservationSet`] and [`Map`], for sessions too big to save
+//! and load quickly as JSON.
+//!
+//! Every file starts with a four-byte tag naming what it holds and a version byte. Numbers are
+//! LEB128 varints, so small room ids and counts take one byte; plans are stored as their
+//! characters and labels as single bytes.
+
+use anyhow::{bail, Context, Result};
+
+use crate::observation::{Observation, ObservationSet};
+use crate::types::{Map, MapConnection, RoomDoor};
+
+const VERSION: u8 = 1;
+const OBSERVATIONS_TAG: &[u8; 4] = b"AEOB";
+const MAP_TAG: &[u8; 4] = b"AEMP";
+
+fn write_header(out: &mut Vec<u8>, tag: &[u8; 4]) {
+    out.extend_from_slice(tag);
+    out.push(VERSION);
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_varint(out, bytes.len());
+    out.extend_from_slice(bytes);
+}
+
+/// Reads the encoding back, failing on truncated or malformed input.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8], tag: &[u8; 4]) -> Result<Self> {
+        let mut reader = Self { bytes, pos: 0 };
+        if reader.take(4)? != tag {
+            bail!(
+                "Not a binary {}",
+                String::from_utf8_lossy(tag).to_lowercase()
+            );
+        }
+        let version = reader.take(1)?[0];
+        if version != VERSION {
+            bail!("Unsupported binary format version {}", version);
+        }
+        Ok(reader)
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.bytes.len())
+            .context("Binary data ends early")?;
+        let taken = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(taken)
+    }
+
+    fn varint(&mut self) -> Result<usize> {
+        let mut value = 0usize;
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("Varint at offset {} is too long", self.pos)
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.varint()?;
+        self.take(len)
+    }
+
+    fn room_door(&mut self) -> Result<RoomDoor> {
+        Ok(RoomDoor {
+            room: self.varint()?,
+            door: self.varint()?,
+        })
+    }
+
+    fn finish(self) -> Result<()> {
+        if self.pos != self.bytes.len() {
+            bail!("{} bytes left over", self.bytes.len() - self.pos);
+        }
+        Ok(())
+    }
+}
+
+impl ObservationSet {
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_header(&mut out, OBSERVATIONS_TAG);
+        write_varint(&mut out, self.len());
+        for observation in self.iter() {
+            write_bytes(&mut out, observation.plan.as_bytes());
+            write_bytes(&mut out, &observation.labels);
+        }
+        out
+    }
+
+    pub fn from_binary(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes, OBSERVATIONS_TAG)?;
+        let count = reader.varint()?;
+        let mut set = Self::new();
+        for i in 0..count {
+            let plan = String::from_utf8(reader.bytes()?.to_vec())
+                .with_context(|| format!("Plan {} is not UTF-8", i))?;
+            let labels = reader.bytes()?.to_vec();
+            set.push(Observation::new(plan, labels));
+        }
+        reader.finish()?;
+        Ok(set)
+    }
+}
+
+impl Map {
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_header(&mut out, MAP_TAG);
+        write_varint(&mut out, self.rooms.len());
+        for &label in &self.rooms {
+            write_varint(&mut out, label);
+        }
+        write_varint(&mut out, self.starting_room);
+        write_varint(&mut out, self.connections.len());
+        for connection in &self.connections {
+            for end in [&connection.from, &connection.to] {
+                write_varint(&mut out, end.room);
+                write_varint(&mut out, end.door);
+            }
+        }
+        out
+    }
+
+    /// Decodes a map without validating it, like deserializing one from JSON.
+    pub fn from_binary(bytes: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(bytes, MAP_TAG)?;
+        let rooms = (0..reader.varint()?)
+            .map(|_| reader.varint())
+            .collect::<Result<_>>()?;
+        let starting_room = reader.varint()?;
+        let connections = (0..reader.varint()?)
+            .map(|_| {
+                Ok(MapConnection {
+                    from: reader.room_door()?,
+                    to: reader.room_door()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        reader.finish()?;
+        Ok(Map {
+            rooms,
+            starting_room,
+            connections,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::MapBuilder;
+
+    #[test]
+    fn test_binary_and_json_decode_alike() {
+        let observations: ObservationSet = [
+            Observation::new("012345R0", vec![0, 1, 2, 3, 0, 1, 2, 0, 1]),
+            Observation::new("", vec![3]),
+        ]
+        .into_iter()
+        .collect();
+        let binary = observations.to_binary();
+        let json = serde_json::to_string(&observations).unwrap();
+        assert!(binary.len() < json.len());
+        let from_binary = ObservationSet::from_binary(&binary).unwrap();
+        let from_json: ObservationSet = serde_json::from_str(&json).unwrap();
+        assert_eq!(from_binary, from_json);
+        assert_eq!(from_binary, observations);
+
+        let map = MapBuilder::new()
+            .room(0, 0)
+            .room(1, 3)
+            .starting(1)
+            .connect(0, 0, 1, 5)
+            .connect(0, 2, 0, 2)
+            .build()
+            .unwrap();
+        let from_binary = Map::from_binary(&map.to_binary()).unwrap();
+        let from_json: Map = serde_json::from_str(&serde_json::to_string(&map).unwrap()).unwrap();
+        for decoded in [&from_binary, &from_json] {
+            assert_eq!(decoded.rooms, map.rooms);
+            assert_eq!(decoded.starting_room, map.starting_room);
+            assert_eq!(decoded.connections, map.connections);
+        }
+
+        // Large ids take several bytes.
+        let mut big = map.clone();
+        big.starting_room = 300;
+        assert_eq!(
+            Map::from_binary(&big.to_binary()).unwrap().starting_room,
+            300
+        );
+    }
+
+    #[test]
+    fn test_from_binary_rejects_bad_input() {
+        let binary = ObservationSet::new().to_binary();
+        assert!(ObservationSet::from_binary(&binary[..3]).is_err());
+        assert!(Map::from_binary(&binary).is_err());
+        let mut extra = binary.clone();
+        extra.push(0);
+        assert!(ObservationSet::from_binary(&extra).is_err());
+        let mut newer = binary;
+        newer[4] = VERSION + 1;
+        assert!(ObservationSet::from_binary(&newer).is_err());
+    }
+}
@@ -0,0 +1,110 @@
+//! The problems of the contest and their parameters, by name.
+
+use crate::types::ProblemInfo;
+
+/// Doorways a problem allows per room of its library, which is also how many doors a single plan
+/// may walk per room; see [`crate::max_plan_len`].
+pub const DOORWAYS_PER_ROOM: usize = 18;
+
+/// Doorways available for a library of `room_count` rooms.
+pub const fn doorway_budget(room_count: usize) -> usize {
+    DOORWAYS_PER_ROOM * room_count
+}
+
+/// Parameters of one problem of the contest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProblemSpec {
+    pub room_count: usize,
+    pub doorway_budget: usize,
+}
+
+impl ProblemSpec {
+    const fn rooms(room_count: usize) -> Self {
+        Self {
+            room_count,
+            doorway_budget: doorway_budget(room_count),
+        }
+    }
+}
+
+/// Every problem of the contest. The aleph series are libraries made of several copies of a
+/// smaller one, so they have two or three times the rooms of the problem they copy.
+pub struct ProblemCatalog;
+
+impl ProblemCatalog {
+    pub const PROBLEMS: [(&'static str, ProblemSpec); 16] = [
+        ("probatio", ProblemSpec::rooms(3)),
+        ("primus", ProblemSpec::rooms(6)),
+        ("secundus", ProblemSpec::rooms(12)),
+        ("tertius", ProblemSpec::rooms(18)),
+        ("quartus", ProblemSpec::rooms(24)),
+        ("quintus", ProblemSpec::rooms(30)),
+        ("aleph", ProblemSpec::rooms(12)),
+        ("beth", ProblemSpec::rooms(24)),
+        ("gimel", ProblemSpec::rooms(36)),
+        ("daleth", ProblemSpec::rooms(48)),
+        ("he", ProblemSpec::rooms(60)),
+        ("vau", ProblemSpec::rooms(18)),
+        ("zain", ProblemSpec::rooms(36)),
+        ("hhet", ProblemSpec::rooms(54)),
+        ("teth", ProblemSpec::rooms(72)),
+        ("iod", ProblemSpec::rooms(90)),
+    ];
+
+    pub fn get(problem_name: &str) -> Option<ProblemSpec> {
+        Self::PROBLEMS
+            .iter()
+            .find(|(name, _)| *name == problem_name)
+            .map(|&(_, spec)| spec)
+    }
+
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        Self::PROBLEMS.iter().map(|&(name, _)| name)
+    }
+}
+
+/// What the catalog knows about `problem_name`, with the full budget of a fresh selection.
+pub fn info(problem_name: &str) -> Option<ProblemInfo> {
+    let spec = ProblemCatalog::get(problem_name)?;
+    Some(ProblemInfo {
+        name: problem_name.to_string(),
+        room_count: Some(spec.room_count),
+        budget: Some(spec.doorway_budget),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_known_problem() {
+        let expected = [
+            ("probatio", 3),
+            ("primus", 6),
+            ("secundus", 12),
+            ("tertius", 18),
+            ("quartus", 24),
+            ("quintus", 30),
+            ("aleph", 12),
+            ("beth", 24),
+            ("gimel", 36),
+            ("daleth", 48),
+            ("he", 60),
+            ("vau", 18),
+            ("zain", 36),
+            ("hhet", 54),
+            ("teth", 72),
+            ("iod", 90),
+        ];
+        assert_eq!(ProblemCatalog::names().count(), expected.len());
+        for (name, rooms) in expected {
+            let info = info(name).unwrap();
+            assert_eq!(info.name, name);
+            assert_eq!(info.room_count, Some(rooms), "{}", name);
+            assert_eq!(info.budget, Some(18 * rooms), "{}", name);
+        }
+        assert_eq!(info("unknown"), None);
+        assert_eq!(ProblemCatalog::get("Probatio"), None);
+    }
+}
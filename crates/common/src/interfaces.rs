@@ -3,9 +3,45 @@ use async_trait::async_trait;
 
 use crate::types::*;
 
+/// Talks to the Aedificium, remotely or simulated.
+///
+/// Clients are `Send + Sync`, so `Box<dyn AedificiumClient>` and `&mut C` work wherever a
+/// client is expected and can be moved across tasks.
 #[async_trait]
-pub trait AedificiumClient {
-    async fn select(&self, problem_name: String) -> Result<SelectResponse>;
+pub trait AedificiumClient: Send + Sync {
+    /// Takes `&mut self`, like `explore`, so that selecting can reset state such as a
+    /// simulator's library. It used to take `&self`.
+    async fn select(&mut self, problem_name: String) -> Result<SelectResponse>;
     async fn explore(&mut self, plans: Vec<String>) -> Result<ExploreResponse>;
     async fn guess(&self, data: Map) -> Result<GuessResponse>;
 }
+
+#[async_trait]
+impl<C: AedificiumClient + ?Sized> AedificiumClient for Box<C> {
+    async fn select(&mut self, problem_name: String) -> Result<SelectResponse> {
+        (**self).select(problem_name).await
+    }
+
+    async fn explore(&mut self, plans: Vec<String>) -> Result<ExploreResponse> {
+        (**self).explore(plans).await
+    }
+
+    async fn guess(&self, data: Map) -> Result<GuessResponse> {
+        (**self).guess(data).await
+    }
+}
+
+#[async_trait]
+impl<C: AedificiumClient + ?Sized> AedificiumClient for &mut C {
+    async fn select(&mut self, problem_name: String) -> Result<SelectResponse> {
+        (**self).select(problem_name).await
+    }
+
+    async fn explore(&mut self, plans: Vec<String>) -> Result<ExploreResponse> {
+        (**self).explore(plans).await
+    }
+
+    async fn guess(&self, data: Map) -> Result<GuessResponse> {
+        (**self).guess(data).await
+    }
+}
@@ -1,5 +1,17 @@
+#[cfg(feature = "binary")]
+pub mod binary;
+pub mod catalog;
+#[cfg(feature = "client")]
 pub mod interfaces;
+pub mod map;
+pub mod observation;
+pub mod plan;
 pub mod types;
 
+pub use catalog::{doorway_budget, ProblemCatalog, ProblemSpec, DOORWAYS_PER_ROOM};
+#[cfg(feature = "client")]
 pub use interfaces::*;
+pub use map::*;
+pub use observation::*;
+pub use plan::*;
 pub use types::*;
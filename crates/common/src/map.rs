@@ -0,0 +1,647 @@
+use anyhow::{bail, Result};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt;
+
+use crate::plan::{parse_plan_steps, PlanStep};
+use crate::types::{Map, MapConnection, RoomDoor, DOORS};
+
+/// Builds a [`Map`] room by room, checking it on [`MapBuilder::build`].
+///
+/// Room ids must be `0..n`; `Map::rooms[id]` holds the label of room `id`.
+#[derive(Debug, Clone, Default)]
+pub struct MapBuilder {
+    labels: BTreeMap<usize, usize>,
+    starting_room: Option<usize>,
+    connections: Vec<MapConnection>,
+}
+
+impl MapBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn room(mut self, id: usize, label: usize) -> Self {
+        self.labels.insert(id, label);
+        self
+    }
+
+    pub fn starting(mut self, id: usize) -> Self {
+        self.starting_room = Some(id);
+        self
+    }
+
+    /// Connects door `door_a` of `room_a` with door `door_b` of `room_b`, in both directions.
+    pub fn connect(mut self, room_a: usize, door_a: usize, room_b: usize, door_b: usize) -> Self {
+        self.connections.push(MapConnection {
+            from: RoomDoor {
+                room: room_a,
+                door: door_a,
+            },
+            to: RoomDoor {
+                room: room_b,
+                door: door_b,
+            },
+        });
+        self
+    }
+
+    pub fn build(self) -> Result<Map> {
+        let room_count = self.labels.len();
+        if let Some((&id, _)) = self.labels.iter().find(|(&id, _)| id >= room_count) {
+            bail!("Room ids must be 0..{}, got {}", room_count, id);
+        }
+
+        let map = Map {
+            rooms: self.labels.into_values().collect(),
+            starting_room: self.starting_room.unwrap_or(0),
+            connections: self.connections,
+        };
+        validate_map(&map)?;
+        Ok(map)
+    }
+}
+
+/// Checks that `map` describes a library that can be walked both ways: the starting room and
+/// every end of a connection exist, and every door is an end of at most one connection, so
+/// the door behind it leads straight back.
+///
+/// A door connected to itself is both ends of one connection and uses the door once. A door
+/// listed in two connections is an error naming that door, whether the second one is a copy
+/// of the first or leads somewhere else.
+pub fn validate_map(map: &Map) -> Result<()> {
+    let room_count = map.rooms.len();
+    if map.starting_room >= room_count {
+        bail!("Starting room {} does not exist", map.starting_room);
+    }
+
+    let mut other_end: HashMap<&RoomDoor, &RoomDoor> = HashMap::new();
+    for conn in &map.connections {
+        for end in [&conn.from, &conn.to] {
+            if end.room >= room_count {
+                bail!("Connection refers to unknown room {}", end.room);
+            }
+            if end.door >= DOORS {
+                bail!("Invalid door {} of room {}", end.door, end.room);
+            }
+        }
+        let ends = if conn.from == conn.to {
+            vec![(&conn.from, &conn.to)]
+        } else {
+            vec![(&conn.from, &conn.to), (&conn.to, &conn.from)]
+        };
+        for (end, other) in ends {
+            if let Some(previous) = other_end.insert(end, other) {
+                bail!(
+                    "Door {} of room {} is connected to door {} of room {} and to door {} of \
+                     room {}; a door leads back through exactly one door",
+                    end.door,
+                    end.room,
+                    previous.door,
+                    previous.room,
+                    other.door,
+                    other.room
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Map {
+    /// Distinct edges, counting a connection listed in both directions once.
+    pub fn edge_count(&self) -> usize {
+        EdgeSet::from(self).len()
+    }
+
+    /// The same library with room `i` renumbered to `mapping[i]`, in `rooms`, `starting_room`
+    /// and every connection. Fails unless `mapping` is a permutation of the room ids.
+    pub fn relabel(&self, mapping: &[usize]) -> Result<Map> {
+        let room_count = self.rooms.len();
+        if mapping.len() != room_count {
+            bail!(
+                "Got a mapping of {} rooms for {}",
+                mapping.len(),
+                room_count
+            );
+        }
+        let mut rooms = vec![None; room_count];
+        for (&label, &to) in self.rooms.iter().zip(mapping) {
+            match rooms.get_mut(to) {
+                Some(slot @ None) => *slot = Some(label),
+                Some(Some(_)) => bail!("Room {} is mapped to more than once", to),
+                None => bail!("Room ids must be 0..{}, got {}", room_count, to),
+            }
+        }
+
+        let end = |end: &RoomDoor| -> Result<RoomDoor> {
+            match mapping.get(end.room) {
+                Some(&room) => Ok(RoomDoor {
+                    room,
+                    door: end.door,
+                }),
+                None => bail!("Connection refers to unknown room {}", end.room),
+            }
+        };
+        let connections = self
+            .connections
+            .iter()
+            .map(|conn| {
+                Ok(MapConnection {
+                    from: end(&conn.from)?,
+                    to: end(&conn.to)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let Some(&starting_room) = mapping.get(self.starting_room) else {
+            bail!("Starting room {} does not exist", self.starting_room);
+        };
+
+        Ok(Map {
+            rooms: rooms.into_iter().flatten().collect(),
+            starting_room,
+            connections,
+        })
+    }
+
+    /// The same library in a canonical form, so isomorphic maps serialize identically: rooms
+    /// are numbered in the order a breadth-first search from the starting room reaches them
+    /// through doors 0 to 5, which makes the starting room 0, and connections are listed once
+    /// each, smaller endpoint first, in ascending order. Rooms the search can't reach keep their
+    /// relative order after the rest.
+    ///
+    /// Fails if `self` is a map [`validate_map`] rejects.
+    pub fn canonicalize(&self) -> Result<Map> {
+        validate_map(self)?;
+        let doors = self.doors();
+        let mut next_id = 1;
+        let mut mapping = vec![None; self.rooms.len()];
+        mapping[self.starting_room] = Some(0);
+        let mut queue = VecDeque::from([self.starting_room]);
+        while let Some(room) = queue.pop_front() {
+            for &next in doors[room].iter().flatten() {
+                if mapping[next].is_none() {
+                    mapping[next] = Some(next_id);
+                    next_id += 1;
+                    queue.push_back(next);
+                }
+            }
+        }
+        let mapping: Vec<usize> = mapping
+            .into_iter()
+            .map(|id| {
+                id.unwrap_or_else(|| {
+                    next_id += 1;
+                    next_id - 1
+                })
+            })
+            .collect();
+        let relabelled = self.relabel(&mapping)?;
+        Ok(Map {
+            connections: EdgeSet::from(&relabelled).iter().cloned().collect(),
+            ..relabelled
+        })
+    }
+
+    /// The library in Graphviz DOT, for eyeballing a map or comparing two. Rooms are nodes
+    /// labelled `id: label`, the starting room drawn with a double circle; every connection is
+    /// one edge with its door numbers at either end.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("graph library {\n");
+        for (room, label) in self.rooms.iter().enumerate() {
+            let shape = if room == self.starting_room {
+                ", shape=doublecircle"
+            } else {
+                ""
+            };
+            dot.push_str(&format!(
+                "  {} [label=\"{}: {}\"{}];\n",
+                room, room, label, shape
+            ));
+        }
+        for conn in EdgeSet::from(self).iter() {
+            dot.push_str(&format!(
+                "  {} -- {} [taillabel=\"{}\", headlabel=\"{}\"];\n",
+                conn.from.room, conn.to.room, conn.from.door, conn.to.door
+            ));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// `doors()[room][door]` is the room behind `door` of `room`, if it is connected.
+    pub fn doors(&self) -> Vec<[Option<usize>; DOORS]> {
+        let mut doors = vec![[None; DOORS]; self.rooms.len()];
+        for conn in &self.connections {
+            doors[conn.from.room][conn.from.door] = Some(conn.to.room);
+            doors[conn.to.room][conn.to.door] = Some(conn.from.room);
+        }
+        doors
+    }
+
+    /// The labels seen walking `plan` from the starting room, starting with the starting room's
+    /// own: what exploring `plan` in this library reports. Marks rewrite labels until the end
+    /// of the walk. Fails at an unconnected door.
+    pub fn walk(&self, plan: &str) -> Result<Vec<usize>> {
+        let doors = self.doors();
+        let mut rooms = self.rooms.clone();
+        let mut current = self.starting_room;
+        let mut labels = vec![rooms[current]];
+        for step in parse_plan_steps(plan)? {
+            current = match step {
+                PlanStep::Door(door) => match doors[current][door] {
+                    Some(next) => next,
+                    None => bail!("Door {} of room {} is not connected", door, current),
+                },
+                PlanStep::Reset => self.starting_room,
+                PlanStep::Mark(label) => {
+                    rooms[current] = label;
+                    current
+                }
+            };
+            labels.push(rooms[current]);
+        }
+        Ok(labels)
+    }
+}
+
+/// A summary for logs: `Map{rooms: N, start: S, edges: E}`, then one line per room with its
+/// label and the room behind each door, `-` for an unconnected one.
+impl fmt::Display for Map {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Map{{rooms: {}, start: {}, edges: {}}}",
+            self.rooms.len(),
+            self.starting_room,
+            self.edge_count()
+        )?;
+        for (room, doors) in self.doors().iter().enumerate() {
+            write!(f, "\n  room {} label {} doors", room, self.rooms[room])?;
+            for next in doors {
+                match next {
+                    Some(next) => write!(f, " {}", next)?,
+                    None => write!(f, " -")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The connections of a map, independent of their order and direction.
+///
+/// Every connection is stored with its smaller endpoint as `from`, so a connection and its
+/// reverse are the same edge. Iterates in ascending order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct EdgeSet(BTreeSet<MapConnection>);
+
+impl EdgeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `conn` with its smaller endpoint first.
+    pub fn canonical(conn: &MapConnection) -> MapConnection {
+        if conn.to < conn.from {
+            MapConnection {
+                from: conn.to.clone(),
+                to: conn.from.clone(),
+            }
+        } else {
+            conn.clone()
+        }
+    }
+
+    /// Returns `false` if the connection, in either direction, was already present.
+    pub fn insert(&mut self, conn: &MapConnection) -> bool {
+        self.0.insert(Self::canonical(conn))
+    }
+
+    pub fn contains(&self, conn: &MapConnection) -> bool {
+        self.0.contains(&Self::canonical(conn))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MapConnection> {
+        self.0.iter()
+    }
+
+    /// Edges in `self` but not in `other`.
+    pub fn difference<'a>(&'a self, other: &'a EdgeSet) -> impl Iterator<Item = &'a MapConnection> {
+        self.0.difference(&other.0)
+    }
+}
+
+impl From<&Map> for EdgeSet {
+    fn from(map: &Map) -> Self {
+        map.connections.iter().collect()
+    }
+}
+
+impl<'a> FromIterator<&'a MapConnection> for EdgeSet {
+    fn from_iter<I: IntoIterator<Item = &'a MapConnection>>(iter: I) -> Self {
+        let mut set = Self::new();
+        for conn in iter {
+            set.insert(conn);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_map() {
+        let map = MapBuilder::new()
+            .room(1, 3)
+            .room(0, 2)
+            .starting(1)
+            .connect(0, 0, 1, 5)
+            .connect(1, 1, 1, 1)
+            .build()
+            .unwrap();
+
+        assert_eq!(map.rooms, vec![2, 3]);
+        assert_eq!(map.starting_room, 1);
+        assert_eq!(map.connections.len(), 2);
+        assert_eq!(map.connections[0].from, RoomDoor { room: 0, door: 0 });
+        assert_eq!(map.connections[0].to, RoomDoor { room: 1, door: 5 });
+    }
+
+    #[test]
+    fn test_relabel_round_trip() {
+        let map = MapBuilder::new()
+            .room(0, 0)
+            .room(1, 1)
+            .room(2, 2)
+            .connect(0, 0, 1, 5)
+            .connect(1, 1, 2, 3)
+            .connect(2, 4, 2, 4)
+            .build()
+            .unwrap();
+        let permutation = [2, 0, 1];
+        let inverse = [1, 2, 0];
+
+        let relabelled = map.relabel(&permutation).unwrap();
+        assert_eq!(relabelled.rooms, vec![1, 2, 0]);
+        assert_eq!(relabelled.starting_room, 2);
+        assert_eq!(relabelled.connections[0].to, RoomDoor { room: 0, door: 5 });
+        assert_eq!(relabelled.walk("01").unwrap(), map.walk("01").unwrap());
+        let back = relabelled.relabel(&inverse).unwrap();
+        assert_eq!(back.rooms, map.rooms);
+        assert_eq!(back.starting_room, map.starting_room);
+        assert_eq!(back.connections, map.connections);
+
+        assert!(map.relabel(&[0, 1]).is_err());
+        assert!(map.relabel(&[0, 1, 1]).is_err());
+        assert!(map.relabel(&[0, 1, 3]).is_err());
+    }
+
+    #[test]
+    fn test_isomorphic_maps_canonicalize_identically() {
+        let map = MapBuilder::new()
+            .room(0, 0)
+            .room(1, 1)
+            .room(2, 1)
+            .starting(1)
+            .connect(0, 0, 1, 5)
+            .connect(1, 1, 2, 3)
+            .connect(2, 4, 2, 4)
+            .connect(0, 2, 2, 0)
+            .build()
+            .unwrap();
+        let mut shuffled = map.relabel(&[2, 0, 1]).unwrap();
+        shuffled.connections.reverse();
+        for conn in shuffled.connections.iter_mut().step_by(2) {
+            std::mem::swap(&mut conn.from, &mut conn.to);
+        }
+
+        let canonical = map.canonicalize().unwrap();
+        assert_eq!(canonical.starting_room, 0);
+        assert_eq!(canonical.rooms, vec![1, 1, 0]);
+        assert_eq!(canonical.walk("1005").unwrap(), map.walk("1005").unwrap());
+        assert_eq!(
+            serde_json::to_string(&canonical).unwrap(),
+            serde_json::to_string(&shuffled.canonicalize().unwrap()).unwrap()
+        );
+        assert_eq!(
+            serde_json::to_string(&canonical.canonicalize().unwrap()).unwrap(),
+            serde_json::to_string(&canonical).unwrap()
+        );
+
+        // An unreachable room goes last; a starting room that doesn't exist is an error.
+        let island = MapBuilder::new()
+            .room(0, 3)
+            .room(1, 2)
+            .starting(1)
+            .connect(1, 0, 1, 1)
+            .build()
+            .unwrap();
+        assert_eq!(island.canonicalize().unwrap().rooms, vec![2, 3]);
+        let mut missing_start = map.clone();
+        missing_start.starting_room = 3;
+        assert!(missing_start.canonicalize().is_err());
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let map = MapBuilder::new()
+            .room(0, 2)
+            .room(1, 0)
+            .starting(1)
+            .connect(1, 5, 0, 0)
+            .connect(0, 3, 0, 3)
+            .build()
+            .unwrap();
+        assert_eq!(
+            map.to_dot(),
+            "graph library {\n\
+             \x20 0 [label=\"0: 2\"];\n\
+             \x20 1 [label=\"1: 0\", shape=doublecircle];\n\
+             \x20 0 -- 1 [taillabel=\"0\", headlabel=\"5\"];\n\
+             \x20 0 -- 0 [taillabel=\"3\", headlabel=\"3\"];\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let map = MapBuilder::new()
+            .room(0, 2)
+            .room(1, 0)
+            .starting(1)
+            .connect(1, 5, 0, 0)
+            .connect(0, 3, 0, 3)
+            .build()
+            .unwrap();
+        assert_eq!(
+            map.to_string(),
+            "Map{rooms: 2, start: 1, edges: 2}\n\
+             \x20 room 0 label 2 doors 1 - - 0 - -\n\
+             \x20 room 1 label 0 doors - - - - - 0"
+        );
+    }
+
+    #[test]
+    fn test_walk() {
+        let map = MapBuilder::new()
+            .room(0, 0)
+            .room(1, 1)
+            .connect(0, 0, 1, 5)
+            .connect(1, 1, 1, 1)
+            .build()
+            .unwrap();
+        assert_eq!(map.walk("").unwrap(), vec![0]);
+        assert_eq!(map.walk("0115").unwrap(), vec![0, 1, 1, 1, 0]);
+        assert_eq!(map.walk("0R0").unwrap(), vec![0, 1, 0, 1]);
+        // A mark lasts across a reset, but not into the next walk.
+        assert_eq!(map.walk("0[3]5R0").unwrap(), vec![0, 1, 3, 0, 0, 3]);
+        assert_eq!(map.walk("0").unwrap(), vec![0, 1]);
+        assert!(map.walk("01").is_ok());
+        assert!(map.walk("02").is_err());
+        assert!(map.walk("6").is_err());
+    }
+
+    #[test]
+    fn test_edge_set_dedups_reversed_connections() {
+        let map = MapBuilder::new()
+            .room(0, 0)
+            .room(1, 1)
+            .connect(1, 5, 0, 0)
+            .connect(1, 1, 1, 1)
+            .build()
+            .unwrap();
+        let mut edges = EdgeSet::from(&map);
+        assert_eq!(edges.len(), 2);
+
+        let reversed = MapConnection {
+            from: RoomDoor { room: 0, door: 0 },
+            to: RoomDoor { room: 1, door: 5 },
+        };
+        assert!(edges.contains(&reversed));
+        assert!(!edges.insert(&reversed));
+        assert_eq!(edges.len(), 2);
+        assert_eq!(edges.iter().next(), Some(&reversed));
+
+        let other = MapBuilder::new()
+            .room(0, 0)
+            .room(1, 1)
+            .connect(0, 0, 1, 5)
+            .build()
+            .unwrap();
+        let other = EdgeSet::from(&other);
+        let missing: Vec<_> = edges.difference(&other).collect();
+        assert_eq!(missing, vec![&map.connections[1]]);
+    }
+
+    #[test]
+    fn test_edge_count() {
+        // Three rooms in a line, every other door a self-loop: 2 edges between rooms and 14
+        // self-loops.
+        let mut builder = MapBuilder::new()
+            .room(0, 0)
+            .room(1, 1)
+            .room(2, 2)
+            .connect(0, 0, 1, 0)
+            .connect(1, 1, 2, 0);
+        for (room, first_free) in [(0, 1), (1, 2), (2, 1)] {
+            for door in first_free..DOORS {
+                builder = builder.connect(room, door, room, door);
+            }
+        }
+        let mut map = builder.build().unwrap();
+        assert_eq!(map.edge_count(), 16);
+
+        let reversed = MapConnection {
+            from: map.connections[0].to.clone(),
+            to: map.connections[0].from.clone(),
+        };
+        map.connections.push(reversed);
+        assert_eq!(map.edge_count(), 16);
+    }
+
+    #[test]
+    fn test_build_rejects_reused_door() {
+        let result = MapBuilder::new()
+            .room(0, 0)
+            .room(1, 1)
+            .room(2, 2)
+            .connect(0, 0, 1, 0)
+            .connect(2, 0, 1, 0)
+            .build();
+        assert!(result.is_err());
+
+        // The reverse end of a connection counts as used too.
+        let result = MapBuilder::new()
+            .room(0, 0)
+            .room(1, 1)
+            .connect(0, 0, 1, 0)
+            .connect(1, 0, 0, 1)
+            .build();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_validate_names_the_offending_door() {
+        // Door 0 of room 1 leads back to room 0, so it can't also lead to room 2.
+        let missing_reciprocal = MapBuilder::new()
+            .room(0, 0)
+            .room(1, 1)
+            .room(2, 2)
+            .connect(0, 0, 1, 0)
+            .connect(1, 0, 2, 1)
+            .build()
+            .unwrap_err()
+            .to_string();
+        assert!(
+            missing_reciprocal.starts_with("Door 0 of room 1 is connected to door 0 of room 0"),
+            "{}",
+            missing_reciprocal
+        );
+
+        let double_used = Map {
+            rooms: vec![0, 1],
+            starting_room: 0,
+            connections: vec![
+                MapConnection {
+                    from: RoomDoor { room: 0, door: 3 },
+                    to: RoomDoor { room: 1, door: 0 },
+                },
+                MapConnection {
+                    from: RoomDoor { room: 0, door: 3 },
+                    to: RoomDoor { room: 0, door: 3 },
+                },
+            ],
+        };
+        let error = validate_map(&double_used).unwrap_err().to_string();
+        assert!(error.starts_with("Door 3 of room 0 "), "{}", error);
+
+        let self_loop = MapBuilder::new().room(0, 0).connect(0, 3, 0, 3).build();
+        assert!(validate_map(&self_loop.unwrap()).is_ok());
+    }
+
+    #[test]
+    fn test_build_rejects_invalid_rooms_and_doors() {
+        assert!(MapBuilder::new().room(1, 0).build().is_err());
+        assert!(MapBuilder::new().room(0, 0).starting(1).build().is_err());
+        assert!(MapBuilder::new()
+            .room(0, 0)
+            .connect(0, 0, 1, 0)
+            .build()
+            .is_err());
+        assert!(MapBuilder::new()
+            .room(0, 0)
+            .connect(0, 6, 0, 0)
+            .build()
+            .is_err());
+    }
+}
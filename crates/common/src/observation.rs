@@ -0,0 +1,288 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use crate::plan::{parse_plan_steps, MARK_CLOSE, MARK_OPEN};
+use crate::types::ExploreResponse;
+
+/// How many labels an explore reports for a plan. Differs between tiers of the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ObservationConvention {
+    /// `plan.len() + 1` labels: the starting room, then the room behind every door.
+    #[default]
+    IncludingFinal,
+    /// `plan.len()` labels: the room each door is taken from, leaving out the room the last
+    /// door leads to.
+    ExcludingFinal,
+}
+
+impl ObservationConvention {
+    pub fn expected_len(self, plan_len: usize) -> usize {
+        match self {
+            ObservationConvention::IncludingFinal => plan_len + 1,
+            ObservationConvention::ExcludingFinal => plan_len,
+        }
+    }
+
+    /// Fails unless `labels` has the length this convention gives a plan of `plan_len` steps.
+    pub fn check(self, plan_len: usize, labels: &[usize]) -> Result<()> {
+        let expected = self.expected_len(plan_len);
+        if labels.len() != expected {
+            bail!(
+                "A plan of {} doors should have {} labels under {:?}, got {}",
+                plan_len,
+                expected,
+                self,
+                labels.len()
+            );
+        }
+        Ok(())
+    }
+
+    /// Rewrites an observation into [`ObservationConvention::IncludingFinal`] form by dropping
+    /// the last step, whose outcome wasn't observed. `None` if nothing was observed at all.
+    pub fn normalize<'a, 'b>(
+        self,
+        plan: &'a str,
+        labels: &'b [usize],
+    ) -> Result<Option<(&'a str, &'b [usize])>> {
+        self.check(parse_plan_steps(plan)?.len(), labels)?;
+        Ok(match self {
+            ObservationConvention::IncludingFinal => Some((plan, labels)),
+            ObservationConvention::ExcludingFinal if plan.ends_with(MARK_CLOSE) => {
+                plan.rfind(MARK_OPEN).map(|last| (&plan[..last], labels))
+            }
+            ObservationConvention::ExcludingFinal => plan
+                .char_indices()
+                .last()
+                .map(|(last, _)| (&plan[..last], labels)),
+        })
+    }
+}
+
+/// One explored plan and the labels seen along it, starting with the starting room's label.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Observation {
+    pub plan: String,
+    pub labels: Vec<u8>,
+}
+
+impl Observation {
+    pub fn new(plan: impl Into<String>, labels: Vec<u8>) -> Self {
+        Self {
+            plan: plan.into(),
+            labels,
+        }
+    }
+
+    /// Labels widened to the `usize` used by [`ExploreResponse`].
+    pub fn labels_usize(&self) -> Vec<usize> {
+        self.labels.iter().map(|&label| label as usize).collect()
+    }
+}
+
+/// Every observation made against one problem, in exploration order.
+///
+/// Stored on disk as JSONL, one [`Observation`] per line.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ObservationSet {
+    pub observations: Vec<Observation>,
+}
+
+impl ObservationSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pairs the plans sent to `explore` with the labels it returned.
+    pub fn from_explore(plans: &[String], response: &ExploreResponse) -> Result<Self> {
+        let mut set = Self::new();
+        set.extend_from_explore(plans, response)?;
+        Ok(set)
+    }
+
+    pub fn from_results(plans: &[String], results: &[Vec<usize>]) -> Result<Self> {
+        let mut set = Self::new();
+        set.extend_from_results(plans, results)?;
+        Ok(set)
+    }
+
+    pub fn extend_from_explore(
+        &mut self,
+        plans: &[String],
+        response: &ExploreResponse,
+    ) -> Result<()> {
+        self.extend_from_results(plans, &response.results)
+    }
+
+    /// Like [`ObservationSet::extend_from_explore`], for results already taken out of the response.
+    pub fn extend_from_results(&mut self, plans: &[String], results: &[Vec<usize>]) -> Result<()> {
+        if plans.len() != results.len() {
+            bail!("{} plans but {} results", plans.len(), results.len());
+        }
+        for (plan, labels) in plans.iter().zip(results) {
+            let labels = labels
+                .iter()
+                .map(|&label| {
+                    u8::try_from(label).with_context(|| format!("label {} out of range", label))
+                })
+                .collect::<Result<_>>()?;
+            self.observations
+                .push(Observation::new(plan.clone(), labels));
+        }
+        Ok(())
+    }
+
+    pub fn push(&mut self, observation: Observation) {
+        self.observations.push(observation);
+    }
+
+    pub fn len(&self) -> usize {
+        self.observations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observations.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Observation> {
+        self.observations.iter()
+    }
+
+    pub fn plans(&self) -> Vec<String> {
+        self.iter().map(|o| o.plan.clone()).collect()
+    }
+
+    /// Labels in the shape of [`ExploreResponse::results`].
+    pub fn results(&self) -> Vec<Vec<usize>> {
+        self.iter().map(Observation::labels_usize).collect()
+    }
+
+    /// Reads one observation per line, skipping blank lines.
+    pub fn read_jsonl(reader: impl BufRead) -> Result<Self> {
+        let mut set = Self::new();
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let observation = serde_json::from_str(&line)
+                .with_context(|| format!("invalid observation on line {}", i + 1))?;
+            set.push(observation);
+        }
+        Ok(set)
+    }
+
+    pub fn write_jsonl(&self, mut writer: impl Write) -> Result<()> {
+        for observation in &self.observations {
+            serde_json::to_writer(&mut writer, observation)?;
+            writeln!(writer)?;
+        }
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+        Self::read_jsonl(BufReader::new(file))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let file =
+            File::create(path).with_context(|| format!("failed to create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        self.write_jsonl(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl FromIterator<Observation> for ObservationSet {
+    fn from_iter<I: IntoIterator<Item = Observation>>(iter: I) -> Self {
+        Self {
+            observations: iter.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> ObservationSet {
+        ObservationSet::from_explore(
+            &["012".to_string(), "5".to_string()],
+            &ExploreResponse {
+                results: vec![vec![0, 1, 2, 3], vec![0, 0]],
+                query_count: 2.into(),
+                truncated: false,
+            },
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_observation_serde_round_trip() {
+        let observation = Observation::new("012", vec![0, 1, 2, 3]);
+        let json = serde_json::to_string(&observation).unwrap();
+        assert_eq!(json, r#"{"plan":"012","labels":[0,1,2,3]}"#);
+        assert_eq!(
+            serde_json::from_str::<Observation>(&json).unwrap(),
+            observation
+        );
+
+        let set = sample();
+        let json = serde_json::to_string(&set).unwrap();
+        assert_eq!(serde_json::from_str::<ObservationSet>(&json).unwrap(), set);
+    }
+
+    #[test]
+    fn test_jsonl_round_trip() {
+        let set = sample();
+        let mut buf = Vec::new();
+        set.write_jsonl(&mut buf).unwrap();
+        assert_eq!(String::from_utf8(buf.clone()).unwrap().lines().count(), 2);
+
+        let loaded = ObservationSet::read_jsonl(&buf[..]).unwrap();
+        assert_eq!(loaded, set);
+        assert_eq!(loaded.plans(), vec!["012", "5"]);
+        assert_eq!(loaded.results(), vec![vec![0, 1, 2, 3], vec![0, 0]]);
+    }
+
+    #[test]
+    fn test_observation_conventions() {
+        let including = ObservationConvention::IncludingFinal;
+        let excluding = ObservationConvention::ExcludingFinal;
+        assert_eq!(
+            including.normalize("01", &[0, 1, 2]).unwrap(),
+            Some(("01", &[0, 1, 2][..]))
+        );
+        assert_eq!(
+            excluding.normalize("01", &[0, 1]).unwrap(),
+            Some(("0", &[0, 1][..]))
+        );
+        assert_eq!(excluding.normalize("", &[]).unwrap(), None);
+        assert_eq!(
+            excluding.normalize("0[1]", &[0, 2]).unwrap(),
+            Some(("0", &[0, 2][..]))
+        );
+
+        assert!(including.normalize("01", &[0, 1]).is_err());
+        assert!(excluding.normalize("01", &[0, 1, 2]).is_err());
+    }
+
+    #[test]
+    fn test_from_explore_rejects_mismatched_lengths() {
+        let response = ExploreResponse {
+            results: vec![vec![0]],
+            query_count: 1.into(),
+            truncated: false,
+        };
+        assert!(ObservationSet::from_explore(&[], &response).is_err());
+    }
+}
@@ -0,0 +1,306 @@
+use anyhow::{bail, Result};
+
+use crate::catalog::doorway_budget;
+use crate::observation::ObservationConvention;
+use crate::types::{DOORS, LABELS};
+
+/// Longest plan the API accepts for a library with `room_count` rooms: as many doors as the
+/// library's whole doorway budget.
+pub const fn max_plan_len(room_count: usize) -> usize {
+    doorway_budget(room_count)
+}
+
+/// Returns to the starting room in the middle of a plan, on tiers that support it. Observes the
+/// starting room's label again and is charged like the start of a new plan.
+pub const RESET: char = 'R';
+
+/// Opens a charcoal mark, `[x]`, which rewrites the label of the current room to `x` for the
+/// rest of the plan. Observes the new label.
+pub const MARK_OPEN: char = '[';
+/// Closes a charcoal mark.
+pub const MARK_CLOSE: char = ']';
+
+/// One character of a plan.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlanStep {
+    Door(usize),
+    /// [`RESET`].
+    Reset,
+    /// A charcoal mark writing this label, `[x]`.
+    Mark(usize),
+}
+
+/// Parses every step of `plan`: a door below [`DOORS`], [`RESET`], or a mark `[x]` with a
+/// label `x` below [`LABELS`].
+pub fn parse_plan_steps(plan: &str) -> Result<Vec<PlanStep>> {
+    let mut steps = Vec::new();
+    let mut chars = plan.chars();
+    while let Some(c) = chars.next() {
+        steps.push(match c.to_digit(10) {
+            Some(d) if (d as usize) < DOORS => PlanStep::Door(d as usize),
+            _ if c == RESET => PlanStep::Reset,
+            _ if c == MARK_OPEN => {
+                let label = chars.next().and_then(|c| c.to_digit(10));
+                match (label, chars.next()) {
+                    (Some(label), Some(MARK_CLOSE)) if (label as usize) < LABELS => {
+                        PlanStep::Mark(label as usize)
+                    }
+                    _ => bail!(
+                        "Invalid mark in plan {:?}; marks are [0] to [{}]",
+                        plan,
+                        LABELS - 1
+                    ),
+                }
+            }
+            _ => bail!("Invalid door number in plan: {}", c),
+        });
+    }
+    Ok(steps)
+}
+
+/// Chops `plan` into segments of at most `max_len` doors. Only doors count: a reset or a mark
+/// stays in the segment of the door before it, and a mark is never split.
+///
+/// Every plan starts again from the starting room, so the segments are not a continuation of
+/// each other: the labels observed for a later segment are not the ones the full plan would
+/// have observed at that point. Callers have to treat the segments as separate walks.
+///
+/// Fails if `max_len` is zero, which no segment could be cut to, or if [`parse_plan_steps`]
+/// rejects `plan`.
+pub fn split_plan(plan: &str, max_len: usize) -> Result<Vec<String>> {
+    if max_len == 0 {
+        bail!("Can't split a plan into segments of 0 doors");
+    }
+    let mut segments = vec![String::new()];
+    let mut doors = 0;
+    for step in parse_plan_steps(plan)? {
+        let segment = segments.last_mut().expect("there is always a segment");
+        match step {
+            PlanStep::Door(door) => {
+                if doors == max_len {
+                    segments.push(door.to_string());
+                    doors = 1;
+                } else {
+                    segment.push_str(&door.to_string());
+                    doors += 1;
+                }
+            }
+            PlanStep::Reset => segment.push(RESET),
+            PlanStep::Mark(label) => {
+                segment.push_str(&format!("{}{}{}", MARK_OPEN, label, MARK_CLOSE))
+            }
+        }
+    }
+    Ok(segments)
+}
+
+/// Cuts `plan` down to at most `max_len` characters, the way plan lengths are counted, without
+/// splitting a charcoal mark `[x]`: a mark that doesn't fit whole is left out.
+pub fn truncate_plan(plan: &mut String, max_len: usize) {
+    let Some((mut cut, _)) = plan.char_indices().nth(max_len) else {
+        return;
+    };
+    if let Some(open) = plan[..cut].rfind(MARK_OPEN) {
+        if !plan[open..cut].contains(MARK_CLOSE) {
+            cut = open;
+        }
+    }
+    plan.truncate(cut);
+}
+
+/// Plans for a set of target walks in which a walk that is a prefix of another is not sent on
+/// its own: the longer plan observes everything the shorter one would, so its labels are cut
+/// out of the longer plan's. The API has no way to branch off a shared prefix, so only these
+/// exact repeats are saved; walks that merely share a prefix are still sent whole.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixPlans {
+    /// The plans to send: every target that isn't a prefix of another, in the order of the
+    /// first target each one covers.
+    pub plans: Vec<String>,
+    /// For each target, the index of the plan walking it and its number of steps.
+    sources: Vec<(usize, usize)>,
+    /// Steps of each of the plans.
+    plan_steps: Vec<usize>,
+}
+
+impl PrefixPlans {
+    /// Finds the targets covered by a longer one. Sorted, every extension of a walk follows it
+    /// directly, so the first target at or after it that nothing extends is the leaf of the
+    /// prefix tree it is covered by.
+    ///
+    /// Steps are counted as [`parse_plan_steps`] does, so a mark `[x]` is one step; fails on a
+    /// target it rejects.
+    pub fn new(targets: &[String]) -> Result<Self> {
+        let mut sorted: Vec<&str> = targets.iter().map(String::as_str).collect();
+        sorted.sort_unstable();
+        sorted.dedup();
+        let is_leaf: Vec<bool> = (0..sorted.len())
+            .map(|i| {
+                sorted
+                    .get(i + 1)
+                    .is_none_or(|next| !next.starts_with(sorted[i]))
+            })
+            .collect();
+
+        let mut plans: Vec<String> = Vec::new();
+        let mut plan_steps = Vec::new();
+        let sources = targets
+            .iter()
+            .map(|target| {
+                let start = sorted.binary_search(&target.as_str()).unwrap();
+                let leaf = sorted[(start..sorted.len()).find(|&i| is_leaf[i]).unwrap()];
+                let plan = match plans.iter().position(|plan| plan == leaf) {
+                    Some(plan) => plan,
+                    None => {
+                        plans.push(leaf.to_string());
+                        plan_steps.push(parse_plan_steps(leaf)?.len());
+                        plans.len() - 1
+                    }
+                };
+                Ok((plan, parse_plan_steps(target)?.len()))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            plans,
+            sources,
+            plan_steps,
+        })
+    }
+
+    /// Steps of the targets that aren't walked, because a longer plan walks them already.
+    pub fn redundant_steps(&self) -> usize {
+        let target_steps: usize = self.sources.iter().map(|&(_, steps)| steps).sum();
+        target_steps - self.plan_steps.iter().sum::<usize>()
+    }
+
+    /// The labels of every target, cut out of `results`, the labels observed for
+    /// [`PrefixPlans::plans`] under `convention`. If `results` covers only the first plans, as
+    /// after a truncated explore, the labels stop before the first target walked by a plan
+    /// without results.
+    pub fn expand(
+        &self,
+        results: &[Vec<usize>],
+        convention: ObservationConvention,
+    ) -> Result<Vec<Vec<usize>>> {
+        let mut labels = Vec::with_capacity(self.sources.len());
+        for &(plan, steps) in &self.sources {
+            let Some(observed) = results.get(plan) else {
+                break;
+            };
+            convention.check(self.plan_steps[plan], observed)?;
+            labels.push(observed[..convention.expected_len(steps)].to_vec());
+        }
+        Ok(labels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_plan() {
+        assert_eq!(split_plan("0123450", 3).unwrap(), ["012", "345", "0"]);
+        assert_eq!(split_plan("012345", 3).unwrap(), ["012", "345"]);
+        assert_eq!(split_plan("01", 3).unwrap(), ["01"]);
+        assert_eq!(split_plan("", 3).unwrap(), [""]);
+        assert!(split_plan("01", 0).is_err());
+        assert_eq!(
+            split_plan("0[1]12R[3]34", 2).unwrap(),
+            ["0[1]1", "2R[3]3", "4"]
+        );
+        assert!(split_plan("01é2", 2).is_err());
+        assert_eq!(max_plan_len(3), 54);
+    }
+
+    #[test]
+    fn test_truncate_plan_keeps_marks_whole() {
+        let truncated = |plan: &str, max_len: usize| {
+            let mut plan = plan.to_string();
+            truncate_plan(&mut plan, max_len);
+            plan
+        };
+        assert_eq!(truncated("012345", 4), "0123");
+        assert_eq!(truncated("012", 4), "012");
+        assert_eq!(truncated("0[1]23", 3), "0");
+        assert_eq!(truncated("0[1]23", 4), "0[1]");
+        assert_eq!(truncated("0[1]23", 5), "0[1]2");
+        assert_eq!(truncated("[2]", 0), "");
+    }
+
+    #[test]
+    fn test_parse_plan_steps() {
+        assert_eq!(
+            parse_plan_steps("05R1").unwrap(),
+            vec![
+                PlanStep::Door(0),
+                PlanStep::Door(5),
+                PlanStep::Reset,
+                PlanStep::Door(1)
+            ]
+        );
+        assert!(parse_plan_steps("6").is_err());
+        assert!(parse_plan_steps("0r").is_err());
+
+        assert_eq!(
+            parse_plan_steps("[3]0").unwrap(),
+            vec![PlanStep::Mark(3), PlanStep::Door(0)]
+        );
+        for bad in ["[4]", "[1", "[]", "[12]"] {
+            assert!(parse_plan_steps(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_prefix_plans_skip_covered_walks() {
+        use crate::map::MapBuilder;
+
+        let map = MapBuilder::new()
+            .room(0, 0)
+            .room(1, 1)
+            .room(2, 2)
+            .connect(0, 0, 1, 0)
+            .connect(1, 1, 2, 1)
+            .connect(0, 1, 2, 0)
+            .connect(1, 2, 1, 2)
+            .connect(2, 2, 0, 2)
+            .build()
+            .unwrap();
+        let targets: Vec<String> = ["0", "01", "012", "01", "1", "10", "", "011", "0R1"]
+            .map(String::from)
+            .to_vec();
+        let prefix = PrefixPlans::new(&targets).unwrap();
+        assert_eq!(prefix.plans, vec!["011", "012", "10", "0R1"]);
+
+        let cost = |plans: &[String]| plans.iter().map(|p| p.len() + 1).sum::<usize>();
+        assert_eq!(cost(&targets), 26);
+        assert_eq!(cost(&prefix.plans), 15);
+        assert_eq!(prefix.redundant_steps(), 17 - 11);
+
+        let results: Vec<Vec<usize>> = prefix.plans.iter().map(|p| map.walk(p).unwrap()).collect();
+        let expected: Vec<Vec<usize>> = targets.iter().map(|t| map.walk(t).unwrap()).collect();
+        let convention = ObservationConvention::IncludingFinal;
+        assert_eq!(prefix.expand(&results, convention).unwrap(), expected);
+
+        // Without the final label, and with only the first plan answered.
+        let excluding: Vec<Vec<usize>> = results
+            .iter()
+            .map(|labels| labels[..labels.len() - 1].to_vec())
+            .collect();
+        let labels = prefix
+            .expand(&excluding[..1], ObservationConvention::ExcludingFinal)
+            .unwrap();
+        assert_eq!(labels, vec![vec![0], vec![0, 1]]);
+        assert!(prefix.expand(&excluding, convention).is_err());
+
+        // A mark is one step and one label, however many characters it takes.
+        let targets: Vec<String> = ["0[3]", "0[3]1", "0[3]12"].map(String::from).to_vec();
+        let prefix = PrefixPlans::new(&targets).unwrap();
+        assert_eq!(prefix.plans, vec!["0[3]12"]);
+        assert_eq!(prefix.redundant_steps(), 2 + 3);
+        let results = vec![map.walk("0[3]12").unwrap()];
+        let expected: Vec<Vec<usize>> = targets.iter().map(|t| map.walk(t).unwrap()).collect();
+        assert_eq!(prefix.expand(&results, convention).unwrap(), expected);
+        assert!(PrefixPlans::new(&["0[9]".to_string()]).is_err());
+    }
+}
@@ -1,5 +1,13 @@
 use serde::{Deserialize, Serialize};
 
+use crate::catalog::ProblemCatalog;
+
+/// Doors every room has, numbered `0` to `DOORS - 1` in plans and maps.
+pub const DOORS: usize = 6;
+
+/// Labels a room can have as explores report them: the lowest two bits.
+pub const LABELS: usize = 4;
+
 #[derive(Debug, Clone, Serialize)]
 pub struct RegisterRequest {
     pub name: String,
@@ -25,6 +33,40 @@ pub struct SelectResponse {
     pub data: serde_json::Value,
 }
 
+/// What is known about the selected problem from its `select` response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProblemInfo {
+    pub name: String,
+    /// Reported as `roomCount`, or else the size of the problem in the [`ProblemCatalog`].
+    pub room_count: Option<usize>,
+    /// Doorways left for the problem, if reported as `remainingBudget`.
+    pub budget: Option<usize>,
+}
+
+impl ProblemInfo {
+    pub fn from_select(problem_name: &str, response: &SelectResponse) -> Self {
+        let field = |name: &str| {
+            response
+                .data
+                .get(name)
+                .and_then(|value| value.as_u64())
+                .map(|value| value as usize)
+        };
+        let name = response
+            .data
+            .get("problemName")
+            .and_then(|value| value.as_str())
+            .unwrap_or(problem_name)
+            .to_string();
+        Self {
+            room_count: field("roomCount")
+                .or_else(|| ProblemCatalog::get(&name).map(|spec| spec.room_count)),
+            budget: field("remainingBudget"),
+            name,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct ExploreRequest {
     pub id: String,
@@ -35,16 +77,64 @@ pub struct ExploreRequest {
 pub struct ExploreResponse {
     pub results: Vec<Vec<usize>>,
     #[serde(rename = "queryCount")]
-    pub query_count: usize,
+    pub query_count: QueryCount,
+    /// Set when the explore was cut short, e.g. by running out of budget: `results` then only
+    /// covers the plans that were walked to the end, which are a prefix of the plans sent.
+    /// Never sent by the API; clients set it when they salvage partial results.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+impl ExploreResponse {
+    pub fn total_query_count(&self) -> usize {
+        self.query_count.total()
+    }
+
+    /// Per-plan counts, if the server reported them.
+    pub fn per_plan(&self) -> Option<&[usize]> {
+        self.query_count.per_plan()
+    }
+}
+
+/// `queryCount` of an explore response. Most tiers send a single number, some send one count
+/// per plan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum QueryCount {
+    Total(usize),
+    PerPlan(Vec<usize>),
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+impl QueryCount {
+    /// The reported count, summing per-plan counts.
+    pub fn total(&self) -> usize {
+        match self {
+            QueryCount::Total(total) => *total,
+            QueryCount::PerPlan(counts) => counts.iter().sum(),
+        }
+    }
+
+    pub fn per_plan(&self) -> Option<&[usize]> {
+        match self {
+            QueryCount::Total(_) => None,
+            QueryCount::PerPlan(counts) => Some(counts),
+        }
+    }
+}
+
+impl From<usize> for QueryCount {
+    fn from(total: usize) -> Self {
+        QueryCount::Total(total)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct MapConnection {
     pub from: RoomDoor,
     pub to: RoomDoor,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize)]
 pub struct RoomDoor {
     pub room: usize,
     pub door: usize,
@@ -68,3 +158,57 @@ pub struct GuessRequest {
 pub struct GuessResponse {
     pub correct: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_problem_info_from_select() {
+        let response = SelectResponse {
+            data: serde_json::json!({ "problemName": "primus", "remainingBudget": 40 }),
+        };
+        let info = ProblemInfo::from_select("primus", &response);
+        assert_eq!(info.name, "primus");
+        assert_eq!(info.room_count, Some(6));
+        assert_eq!(info.budget, Some(40));
+
+        let response = SelectResponse {
+            data: serde_json::json!({ "roomCount": 5 }),
+        };
+        let info = ProblemInfo::from_select("custom", &response);
+        assert_eq!(info.name, "custom");
+        assert_eq!(info.room_count, Some(5));
+        assert_eq!(info.budget, None);
+    }
+
+    #[test]
+    fn test_query_count_scalar() {
+        let response: ExploreResponse =
+            serde_json::from_str(r#"{"results":[[0,1]],"queryCount":3}"#).unwrap();
+        assert_eq!(response.query_count, QueryCount::Total(3));
+        assert_eq!(response.total_query_count(), 3);
+        assert_eq!(response.per_plan(), None);
+        assert!(!response.truncated);
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"results":[[0,1]],"queryCount":3}"#
+        );
+    }
+
+    #[test]
+    fn test_query_count_array() {
+        let response: ExploreResponse =
+            serde_json::from_str(r#"{"results":[[0,1],[0]],"queryCount":[2,1]}"#).unwrap();
+        assert_eq!(response.total_query_count(), 3);
+        assert_eq!(response.per_plan(), Some(&[2, 1][..]));
+        assert_eq!(
+            serde_json::to_string(&response).unwrap(),
+            r#"{"results":[[0,1],[0]],"queryCount":[2,1]}"#
+        );
+
+        assert!(
+            serde_json::from_str::<ExploreResponse>(r#"{"results":[],"queryCount":"2"}"#).is_err()
+        );
+    }
+}
@@ -1,136 +1,331 @@
-use std::{collections::HashSet, env};
+mod progress;
 
-use icfpc2025_client::AedificiumRemoteClient;
-use icfpc2025_common::{AedificiumClient, Map, MapConnection, RoomDoor};
-use rand::Rng;
+use std::{env, sync::Arc, time::Duration};
 
-const N: usize = 3;
-const EDGES: usize = 6;
-const RETRY_COUNT: usize = 10;
+use clap::Parser;
+use icfpc2025_client::ClientBuilder;
+use icfpc2025_common::{
+    AedificiumClient, DOORS, Map, ObservationSet, ProblemInfo, SelectResponse, max_plan_len,
+};
+use icfpc2025_solver::{
+    BatchConfig, BudgetTracker, Reconciliation, ReconstructOptions, Reconstruction,
+    check_consistency, explore_batched, fit_to_budget, hold_out, rank_candidates,
+    reconstruct_pairs, reported_remaining_budget,
+};
+use progress::Progress;
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::IndexedRandom};
+
+const BACKOFF_BASE: Duration = Duration::from_secs(1);
+const BACKOFF_MAX: Duration = Duration::from_secs(10);
+/// Every this many explored plans, one is held out of the reconstruction to rank candidates by.
+const HOLD_OUT_EVERY: usize = 4;
+
+#[derive(Parser)]
+#[command(name = "greedy")]
+struct Args {
+    #[arg(long, default_value = "probatio", help = "Problem to select")]
+    problem: String,
+    #[arg(
+        long,
+        help = "Number of rooms, if the select response doesn't say and the problem isn't known"
+    )]
+    rooms: Option<usize>,
+    #[arg(
+        long,
+        default_value_t = 10,
+        help = "Give up after this many wrong guesses"
+    )]
+    max_attempts: usize,
+    #[arg(long, help = "Seed for plan generation and candidate ordering")]
+    seed: Option<u64>,
+    #[arg(
+        long,
+        help = "Guess the candidate with the smallest canonical hash instead of a random one"
+    )]
+    deterministic: bool,
+    #[arg(
+        long,
+        default_value_t = 1,
+        help = "Number of random plans explored per attempt"
+    )]
+    plan_count: usize,
+    #[arg(long, default_value_t = BatchConfig::default().batch_size, help = "Plans sent per explore call")]
+    explore_batch_size: usize,
+    #[arg(
+        long,
+        help = "Don't send plans that are a prefix of another; read their labels off the longer one"
+    )]
+    share_prefixes: bool,
+    #[arg(
+        long,
+        help = "Show live coverage, budget and candidate counts on stderr (plain lines when not a terminal)"
+    )]
+    pretty_progress: bool,
+}
+
+fn backoff(attempt: usize) -> Duration {
+    BACKOFF_BASE
+        .saturating_mul(1 << attempt.min(8))
+        .min(BACKOFF_MAX)
+}
+
+/// Rooms of the selected problem: as the select response reports them or as known by name,
+/// else `fallback`.
+fn room_count(
+    problem_name: &str,
+    response: &SelectResponse,
+    fallback: Option<usize>,
+) -> anyhow::Result<usize> {
+    ProblemInfo::from_select(problem_name, response)
+        .room_count
+        .or(fallback)
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "The room count of {} is unknown; pass --rooms",
+                problem_name
+            )
+        })
+}
+
+/// Reconstructs candidate maps of `rooms` rooms consistent with the observations.
+fn reconstruct_candidates(
+    observations: &ObservationSet,
+    rooms: usize,
+    rng: &mut StdRng,
+) -> anyhow::Result<Reconstruction> {
+    let pairs: Vec<(String, Vec<usize>)> = observations
+        .iter()
+        .map(|o| (o.plan.clone(), o.labels_usize()))
+        .collect();
+    check_consistency(&pairs)?;
+
+    let mut reconstruction = reconstruct_pairs(&pairs, rooms, &ReconstructOptions::default(), rng)?;
+    reconstruction.retain_connected();
+    println!(
+        "Candidates: {} (nodes expanded: {})",
+        reconstruction.candidates.len(),
+        reconstruction.nodes_expanded
+    );
+
+    Ok(reconstruction)
+}
+
+/// Picks one of the candidates ranked best against the `held_out` observations: the first in rank order if `deterministic`, a
+/// random one otherwise.
+fn choose_guess(
+    candidates: &[Map],
+    held_out: &ObservationSet,
+    rng: &mut StdRng,
+    deterministic: bool,
+) -> Option<Map> {
+    let ranked = rank_candidates(candidates, held_out);
+    let best = ranked.first()?.1;
+    let top: Vec<&Map> = ranked
+        .iter()
+        .take_while(|(_, score)| *score == best)
+        .map(|(map, _)| map)
+        .collect();
+    let map = if deterministic {
+        top[0]
+    } else {
+        top.choose(rng)?
+    };
+    Some(map.clone())
+}
 
 #[tokio::main]
 async fn main() -> Result<(), anyhow::Error> {
     dotenvy::dotenv().ok();
 
+    let args = Args::parse();
+
     let team_id = env::var("ICFPC_TEAM_ID").map_err(|_| {
         anyhow::anyhow!(
             "Team ID is required. Set via ICFPC_TEAM_ID environment variable or .env file"
         )
     })?;
 
-    let mut client = AedificiumRemoteClient::new(team_id);
+    // Sized once the first select tells how many rooms there are.
+    let progress = args.pretty_progress.then(|| Arc::new(Progress::new(0)));
+
+    let mut builder = ClientBuilder::from_env(team_id);
+    if let Some(progress) = &progress {
+        builder = builder.observer(progress.clone());
+    }
+    let mut client = builder.build()?;
+
+    let mut rng = match args.seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_rng(&mut rand::rng()),
+    };
+
+    let mut select_response = client.select(args.problem.clone()).await?;
+    let rooms = room_count(&args.problem, &select_response, args.rooms)?;
+    println!("Rooms: {}", rooms);
 
-    let mut rng = rand::rng();
+    // Doorways this run may spend on the problem across all attempts, counting the starting
+    // room observation of every plan.
+    let total_budget = args.max_attempts * args.plan_count * (max_plan_len(rooms) + 1);
+    if let Some(progress) = &progress {
+        progress.set_budget(total_budget);
+    }
+
+    let mut budget = BudgetTracker::new(total_budget);
+    let mut candidates: Vec<Map> = Vec::new();
+    let mut held_out = ObservationSet::new();
 
-    for _ in 0..RETRY_COUNT {
-        // Select a problem
-        let select_response = client.select("probatio".to_string()).await?;
+    for attempt in 0..args.max_attempts {
+        if attempt > 0 {
+            tokio::time::sleep(backoff(attempt - 1)).await;
+            select_response = client.select(args.problem.clone()).await?;
+        }
+        println!("=== Attempt {}/{} ===", attempt + 1, args.max_attempts);
         println!("Selected problem: {:?}", select_response);
 
-        // Explore with some plans
-        let max_plans = 18 * N;
-
-        // generate random [0~5]{max_plans} string
-        let query = (0..max_plans)
-            .map(|_| rng.random_range(0..=5))
-            .collect::<Vec<usize>>();
-        println!("Query: {:?}", query);
-
-        let explore_response = client
-            .explore(vec![
-                query
-                    .iter()
-                    .map(|x| x.to_string())
-                    .collect::<Vec<String>>()
-                    .join(""),
-            ])
-            .await?;
-        println!("Explore response: {:?}", explore_response);
-
-        let mut graph: Vec<Vec<i32>> = vec![vec![-1; EDGES]; N];
-        let plan_result = explore_response.results[0].clone();
-
-        let mut current_room_id = plan_result[0];
-
-        assert!(plan_result.len() == query.len() + 1);
-
-        for i in 0..query.len() {
-            let door_type = query[i];
-            let next_room_id = plan_result[i + 1];
-            graph[current_room_id][door_type] = next_room_id as i32;
-            current_room_id = next_room_id;
+        if let Reconciliation::Mismatch {
+            local_remaining,
+            reported_remaining,
+        } = budget.reconcile(reported_remaining_budget(&select_response))
+        {
+            eprintln!(
+                "Warning: expected {} doorways left but the API reports {}",
+                local_remaining, reported_remaining
+            );
         }
 
-        // validate if -1 is not in the graph
-        let mut has_minus_one = false;
-        println!("Graph:");
-        for row in graph.iter() {
-            for &value in row.iter() {
-                print!("{} ", value);
-                if value == -1 {
-                    has_minus_one = true;
-                }
+        if budget.is_exhausted() {
+            eprintln!("Budget exhausted, guessing from the last reconstruction");
+        } else {
+            // Explore with some plans
+            let max_plans = max_plan_len(rooms);
+
+            // generate random door strings of max_plans doors
+            let mut plans = (0..args.plan_count)
+                .map(|_| {
+                    (0..max_plans)
+                        .map(|_| rng.random_range(0..DOORS).to_string())
+                        .collect::<String>()
+                })
+                .collect::<Vec<String>>();
+            // Running out of doorways halfway through a batch would throw the batch away.
+            let trimmed = fit_to_budget(&mut plans, budget.remaining())?;
+            if trimmed > 0 {
+                eprintln!(
+                    "Trimmed {} plans to fit the {} doorways left",
+                    trimmed,
+                    budget.remaining()
+                );
             }
-            println!();
-        }
-        if has_minus_one {
-            println!("Graph contains -1");
-            continue;
-        }
+            println!("Plans: {:?}", plans);
 
-        let mut map = Map {
-            rooms: (0..N).collect(),
-            starting_room: plan_result[0],
-            connections: vec![],
-        };
+            let batch_config = BatchConfig {
+                batch_size: args.explore_batch_size,
+                share_prefixes: args.share_prefixes,
+                convention: client.api_version().convention(),
+                ..BatchConfig::default()
+            };
+            let (results, stats) = explore_batched(&mut client, &plans, &batch_config).await?;
+            budget.record(stats.doorways);
+            println!("Explore results: {:?}", results);
+            println!(
+                "HTTP calls: {}, doorways used: {} (query count: {})",
+                stats.http_calls, stats.doorways, stats.query_count
+            );
 
-        let mut used_room_doors: HashSet<RoomDoor> = HashSet::new();
-
-        for i in 0..N {
-            for j in 0..EDGES {
-                if graph[i][j] != -1 {
-                    let from_door = RoomDoor { room: i, door: j };
-                    if used_room_doors.contains(&from_door) {
-                        continue;
-                    }
-                    let next_room_id = graph[i][j] as usize;
-                    let mut reversed_door = None;
-                    for k in 0..EDGES {
-                        let door = RoomDoor {
-                            room: next_room_id,
-                            door: k,
-                        };
-                        if used_room_doors.contains(&door) {
-                            continue;
-                        }
-                        if graph[next_room_id][k] == i as i32 {
-                            reversed_door = Some(door);
-                            break;
-                        }
-                    }
-
-                    assert!(reversed_door.is_some());
-                    let to_door = reversed_door.unwrap();
-                    used_room_doors.insert(from_door.clone());
-                    used_room_doors.insert(to_door.clone());
-                    map.connections.push(MapConnection {
-                        from: from_door,
-                        to: to_door,
-                    });
-                }
+            let observations = ObservationSet::from_results(&plans, &results)?;
+            let kept;
+            (kept, held_out) = hold_out(&observations, HOLD_OUT_EVERY);
+            let reconstruction = reconstruct_candidates(&kept, rooms, &mut rng)?;
+            if let Some(progress) = &progress {
+                progress.reconstructed(
+                    reconstruction.candidates.len(),
+                    reconstruction.partial_maps.first().map(|p| p.coverage()),
+                );
             }
+            candidates = reconstruction.candidates;
         }
 
+        let candidate_count = candidates.len();
+        let Some(map) = choose_guess(&candidates, &held_out, &mut rng, args.deterministic) else {
+            eprintln!("Attempt {}: 0 candidates, skipping guess", attempt + 1);
+            if budget.is_exhausted() {
+                break;
+            }
+            continue;
+        };
+
+        println!("Guess: {}", map);
         let guess_response = client.guess(map).await?;
+        if let Some(progress) = &progress {
+            progress.finish();
+        }
+        eprintln!(
+            "Attempt {}: {} candidates, guess {}",
+            attempt + 1,
+            candidate_count,
+            if guess_response.correct {
+                "correct"
+            } else {
+                "incorrect"
+            }
+        );
         if guess_response.correct {
-            eprintln!("Guess correct");
             return Ok(());
-        } else {
-            eprintln!("Guess incorrect");
         }
+    }
 
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    Err(anyhow::anyhow!(
+        "Giving up after {} attempts",
+        args.max_attempts
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use icfpc2025_simulator::Simulator;
+
+    use super::*;
+
+    #[test]
+    fn test_backoff_is_capped() {
+        assert_eq!(backoff(0), Duration::from_secs(1));
+        assert_eq!(backoff(1), Duration::from_secs(2));
+        assert_eq!(backoff(2), Duration::from_secs(4));
+        assert_eq!(backoff(10), BACKOFF_MAX);
     }
 
-    Ok(())
+    #[tokio::test]
+    async fn test_room_count_from_select() {
+        let mut simulator = Simulator::with_seed(3, 0).unwrap();
+        let response = simulator.select("secundus".to_string()).await.unwrap();
+        assert_eq!(room_count("secundus", &response, Some(3)).unwrap(), 12);
+
+        // Without a reported or known count, only the fallback is left.
+        let response = SelectResponse {
+            data: serde_json::json!({ "problemName": "practice" }),
+        };
+        assert_eq!(room_count("practice", &response, Some(5)).unwrap(), 5);
+        assert!(room_count("practice", &response, None).is_err());
+    }
+
+    #[test]
+    fn test_seeded_runs_produce_identical_guesses() {
+        // Room 2 can lead back to either room 1 or itself, so there are two candidates.
+        let observations =
+            ObservationSet::from_results(&["000".to_string()], &[vec![0, 1, 1, 1]]).unwrap();
+        let guess = |seed: u64, deterministic: bool| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let candidates = reconstruct_candidates(&observations, 3, &mut rng)
+                .unwrap()
+                .candidates;
+            assert_eq!(candidates.len(), 2);
+            let map = choose_guess(&candidates, &observations, &mut rng, deterministic);
+            serde_json::to_string(&map.unwrap()).unwrap()
+        };
+
+        for seed in 0..8 {
+            assert_eq!(guess(seed, false), guess(seed, false));
+            assert_eq!(guess(seed, true), guess(0, true));
+        }
+    }
 }
@@ -0,0 +1,144 @@
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use icfpc2025_client::ClientObserver;
+use icfpc2025_common::ExploreResponse;
+
+/// Live solve progress on stderr, fed by the client's observer hooks and by the solve loop.
+///
+/// On a terminal the status line is redrawn in place; otherwise every update is logged as its
+/// own line.
+pub struct Progress {
+    started: Instant,
+    budget: AtomicUsize,
+    redraw: bool,
+    state: Mutex<ProgressState>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct ProgressState {
+    explore_calls: usize,
+    doorways: usize,
+    candidates: Option<usize>,
+    coverage: Option<f64>,
+}
+
+impl ProgressState {
+    fn render(&self, budget: usize, elapsed: Duration) -> String {
+        let candidates = self
+            .candidates
+            .map_or("-".to_string(), |count| count.to_string());
+        let coverage = self
+            .coverage
+            .map_or("-".to_string(), |c| format!("{:.0}%", c * 100.0));
+        format!(
+            "[{:>6.1}s] coverage {} | doorways {}/{} ({} left) | explore calls {} | candidates {}",
+            elapsed.as_secs_f64(),
+            coverage,
+            self.doorways,
+            budget,
+            budget.saturating_sub(self.doorways),
+            self.explore_calls,
+            candidates
+        )
+    }
+}
+
+impl Progress {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            started: Instant::now(),
+            budget: AtomicUsize::new(budget),
+            redraw: std::io::stderr().is_terminal(),
+            state: Mutex::new(ProgressState::default()),
+        }
+    }
+
+    /// Doorways the run may spend, once the problem's size is known.
+    pub fn set_budget(&self, budget: usize) {
+        self.budget.store(budget, Ordering::Relaxed);
+    }
+
+    /// Records the latest reconstruction.
+    pub fn reconstructed(&self, candidates: usize, coverage: Option<f64>) {
+        self.update(|state| {
+            state.candidates = Some(candidates);
+            state.coverage = coverage;
+        });
+    }
+
+    /// Ends the redrawn status line so later output starts on a fresh line.
+    pub fn finish(&self) {
+        if self.redraw {
+            eprintln!();
+        }
+    }
+
+    fn update(&self, change: impl FnOnce(&mut ProgressState)) {
+        let mut state = self.state.lock().unwrap();
+        change(&mut state);
+        let line = state.render(self.budget.load(Ordering::Relaxed), self.started.elapsed());
+        if self.redraw {
+            let mut stderr = std::io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[2K{}", line);
+            let _ = stderr.flush();
+        } else {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+impl ClientObserver for Progress {
+    fn on_explore(&self, plans: &[String], _response: &ExploreResponse, _elapsed: Duration) {
+        // Every plan also pays for observing the starting room.
+        let doorways: usize = plans.iter().map(|plan| plan.len() + 1).sum();
+        self.update(|state| {
+            state.explore_calls += 1;
+            state.doorways += doorways;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut state = ProgressState::default();
+        assert_eq!(
+            state.render(100, Duration::from_millis(1500)),
+            "[   1.5s] coverage - | doorways 0/100 (100 left) | explore calls 0 | candidates -"
+        );
+
+        state.explore_calls = 2;
+        state.doorways = 40;
+        state.candidates = Some(3);
+        state.coverage = Some(0.5);
+        assert_eq!(
+            state.render(100, Duration::from_secs(12)),
+            "[  12.0s] coverage 50% | doorways 40/100 (60 left) | explore calls 2 | candidates 3"
+        );
+    }
+
+    #[test]
+    fn test_observer_counts_explores() {
+        let progress = Progress::new(100);
+        let response = ExploreResponse {
+            results: vec![vec![0, 1], vec![0, 2, 3]],
+            query_count: 7.into(),
+            truncated: false,
+        };
+        progress.on_explore(
+            &["1".to_string(), "23".to_string()],
+            &response,
+            Duration::ZERO,
+        );
+
+        let state = progress.state.lock().unwrap();
+        assert_eq!(state.explore_calls, 1);
+        assert_eq!(state.doorways, 5);
+    }
+}
@@ -1,16 +1,17 @@
 use anyhow::{bail, Result};
 use async_trait::async_trait;
 use icfpc2025_common::{
-    ExploreResponse, GuessResponse, Map, MapConnection, RoomDoor, SelectResponse,
+    doorway_budget, parse_plan_steps, validate_map, ExploreResponse, GuessResponse, Map,
+    MapConnection, ObservationConvention, ObservationSet, PlanStep, ProblemCatalog, RoomDoor,
+    SelectResponse, DOORS, LABELS,
 };
-use rand::prelude::Rng;
+use rand::{prelude::Rng, rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet, VecDeque};
 
 // Re-export the trait for convenience
 pub use icfpc2025_common::AedificiumClient;
 
-const DOORS: usize = 6;
-
 #[derive(Debug, Clone)]
 pub struct Room {
     pub label: usize,
@@ -32,11 +33,134 @@ impl Room {
     }
 }
 
+/// Doors of `to_room` leading back to `from_room`, in ascending order.
+///
+/// With parallel edges or self-loops there is more than one; [`pair_doors`] decides which
+/// door pairs with which.
+pub fn find_reverse_doors(
+    rooms: &HashMap<usize, Room>,
+    from_room: usize,
+    to_room: usize,
+) -> Vec<usize> {
+    rooms.get(&to_room).map_or_else(Vec::new, |room| {
+        (0..DOORS)
+            .filter(|&door| room.connections[door] == Some(from_room))
+            .collect()
+    })
+}
+
+/// Pairs every connected door with a distinct door leading back, and lists each pair once.
+///
+/// Rooms and doors are visited in ascending order and each door takes the lowest reverse door
+/// that is still free, so the pairing is deterministic and never uses a door twice. A door only
+/// pairs with itself if it loops back into its own room and no other loop of that room is left.
+/// Doors without a free reverse door are left out.
+fn pair_doors(rooms: &HashMap<usize, Room>) -> Vec<MapConnection> {
+    let mut room_ids: Vec<usize> = rooms.keys().cloned().collect();
+    room_ids.sort_unstable();
+
+    let mut used = HashSet::new();
+    let mut connections = Vec::new();
+    for &room in &room_ids {
+        for (door, &target) in rooms[&room].connections.iter().enumerate() {
+            let Some(target) = target else {
+                continue;
+            };
+            if used.contains(&(room, door)) {
+                continue;
+            }
+            let back = find_reverse_doors(rooms, room, target)
+                .into_iter()
+                .find(|&d| (target, d) != (room, door) && !used.contains(&(target, d)));
+            let back = match back {
+                Some(back) => back,
+                None if target == room => door,
+                None => continue,
+            };
+            used.insert((room, door));
+            used.insert((target, back));
+            connections.push(MapConnection {
+                from: RoomDoor { room, door },
+                to: RoomDoor {
+                    room: target,
+                    door: back,
+                },
+            });
+        }
+    }
+    connections
+}
+
+/// Converts a room graph into a [`Map`] listing every connection once.
+pub fn map_from_rooms(rooms: &HashMap<usize, Room>, starting_room: usize) -> Map {
+    let mut room_ids: Vec<usize> = rooms.keys().cloned().collect();
+    room_ids.sort_unstable();
+
+    Map {
+        rooms: room_ids,
+        starting_room,
+        connections: pair_doors(rooms),
+    }
+}
+
+/// Converts a [`Map`] into a room graph. `labels[i]` is the label of room `map.rooms[i]`.
+///
+/// Fails if a connection refers to an unknown room or door, or if a door takes part in more
+/// than one connection, since the graph could then no longer lead back the way it came.
+pub fn rooms_from_map(map: &Map, labels: &[usize]) -> Result<HashMap<usize, Room>> {
+    if labels.len() != map.rooms.len() {
+        bail!("Got {} labels for {} rooms", labels.len(), map.rooms.len());
+    }
+
+    let mut rooms: HashMap<usize, Room> = map
+        .rooms
+        .iter()
+        .zip(labels)
+        .map(|(&id, &label)| (id, Room::new(label)))
+        .collect();
+
+    for conn in &map.connections {
+        for (end, other) in [(&conn.from, &conn.to), (&conn.to, &conn.from)] {
+            if end.door >= DOORS {
+                bail!("Invalid door {} of room {}", end.door, end.room);
+            }
+            let Some(room) = rooms.get_mut(&end.room) else {
+                bail!("Connection refers to unknown room {}", end.room);
+            };
+            match room.connections[end.door] {
+                None => room.connect_door(end.door, other.room),
+                // A door connected to itself is listed as both ends of one connection.
+                Some(_) if end == other => {}
+                Some(_) => bail!(
+                    "Door {} of room {} is connected more than once",
+                    end.door,
+                    end.room
+                ),
+            }
+        }
+    }
+
+    Ok(rooms)
+}
+
+/// How [`Library`] connections are generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GenerationMode {
+    /// A random spanning tree plus a few extra edges. Leaves many doors unconnected, so a
+    /// [`Simulator`] generated this way is lenient about dead ends.
+    Random,
+    /// Every door of every room is connected, like the contest libraries.
+    #[default]
+    Regular,
+}
+
 #[derive(Debug)]
 pub struct Library {
     rooms: HashMap<usize, Room>,
     starting_room: usize,
     room_count: usize,
+    /// `(width, height)` for libraries made by [`Library::generate_grid`].
+    grid: Option<(usize, usize)>,
 }
 
 impl Library {
@@ -49,6 +173,7 @@ impl Library {
             rooms: HashMap::new(),
             starting_room: 0,
             room_count,
+            grid: None,
         };
 
         // Create rooms with
@@ -63,7 +188,134 @@ impl Library {
         Ok(library)
     }
 
+    pub fn generate_with(
+        room_count: usize,
+        mode: GenerationMode,
+        rng: &mut impl Rng,
+    ) -> Result<Self> {
+        match mode {
+            GenerationMode::Random => Self::generate(room_count, rng),
+            GenerationMode::Regular => Self::generate_regular(room_count, rng),
+        }
+    }
+
+    /// Generates a connected library in which all doors of every room are connected.
+    pub fn generate_regular(room_count: usize, rng: &mut impl Rng) -> Result<Self> {
+        if room_count == 0 {
+            bail!("Library must have at least one room");
+        }
+
+        loop {
+            let mut rooms: HashMap<usize, Room> = (0..room_count)
+                .map(|i| (i, Room::new(rng.gen_range(0..room_count))))
+                .collect();
+
+            // Pair up all doors with a random perfect matching. A door may be paired with
+            // another door of the same room, which makes a self-loop.
+            let mut doors: Vec<(usize, usize)> = (0..room_count)
+                .flat_map(|room| (0..DOORS).map(move |door| (room, door)))
+                .collect();
+            doors.shuffle(rng);
+            for pair in doors.chunks(2) {
+                let (room1, door1) = pair[0];
+                let (room2, door2) = pair[1];
+                rooms.get_mut(&room1).unwrap().connect_door(door1, room2);
+                rooms.get_mut(&room2).unwrap().connect_door(door2, room1);
+            }
+
+            let library = Self {
+                rooms,
+                starting_room: 0,
+                room_count,
+                grid: None,
+            };
+            if library.is_connected() {
+                return Ok(library);
+            }
+        }
+    }
+
+    /// Generates a `width` x `height` lattice. Room `y * width + x` uses doors 0/1/2/3 for
+    /// north/east/south/west; those doors loop back into the room at the border. Doors 4 and
+    /// 5 of all rooms are paired randomly, which may also make self-loops.
+    pub fn generate_grid(width: usize, height: usize, rng: &mut impl Rng) -> Result<Self> {
+        let room_count = width * height;
+        if room_count == 0 {
+            bail!("Library must have at least one room");
+        }
+
+        let mut rooms: HashMap<usize, Room> = (0..room_count)
+            .map(|i| (i, Room::new(rng.gen_range(0..room_count))))
+            .collect();
+
+        for y in 0..height {
+            for x in 0..width {
+                let id = y * width + x;
+                let room = rooms.get_mut(&id).unwrap();
+                let north = if y > 0 { id - width } else { id };
+                let east = if x + 1 < width { id + 1 } else { id };
+                let south = if y + 1 < height { id + width } else { id };
+                let west = if x > 0 { id - 1 } else { id };
+                for (door, neighbor) in [north, east, south, west].into_iter().enumerate() {
+                    room.connect_door(door, neighbor);
+                }
+            }
+        }
+
+        let mut extras: Vec<(usize, usize)> = (0..room_count)
+            .flat_map(|room| (4..DOORS).map(move |door| (room, door)))
+            .collect();
+        extras.shuffle(rng);
+        for pair in extras.chunks(2) {
+            let (room1, door1) = pair[0];
+            let (room2, door2) = pair[1];
+            rooms.get_mut(&room1).unwrap().connect_door(door1, room2);
+            rooms.get_mut(&room2).unwrap().connect_door(door2, room1);
+        }
+
+        Ok(Self {
+            rooms,
+            starting_room: 0,
+            room_count,
+            grid: Some((width, height)),
+        })
+    }
+
+    /// `(width, height)` if this library was generated as a grid.
+    pub fn grid_dimensions(&self) -> Option<(usize, usize)> {
+        self.grid
+    }
+
+    /// Whether every door of every room is connected, as in the contest libraries.
+    pub fn is_complete(&self) -> bool {
+        self.rooms
+            .values()
+            .all(|room| room.connections.iter().all(Option::is_some))
+    }
+
+    fn is_connected(&self) -> bool {
+        let mut visited = HashSet::from([self.starting_room]);
+        let mut queue = VecDeque::from([self.starting_room]);
+        while let Some(room) = queue.pop_front() {
+            for next in self.rooms[&room].connections.iter().flatten() {
+                if visited.insert(*next) {
+                    queue.push_back(*next);
+                }
+            }
+        }
+        visited.len() == self.room_count
+    }
+
     fn generate_connections(&mut self, rng: &mut impl Rng) -> Result<()> {
+        // A lone room has nowhere else to lead, so every door loops back into it.
+        if self.room_count == 1 {
+            let room = self.rooms.get_mut(&0).unwrap();
+            for door in 0..DOORS {
+                room.connect_door(door, 0);
+            }
+            return Ok(());
+        }
+
         // Use a modified version of Kruskal's algorithm to create a connected graph
         let mut connected = HashSet::new();
         let mut to_connect = VecDeque::new();
@@ -140,8 +392,232 @@ impl Library {
         Ok(())
     }
 
+    /// Builds a library from a map in the API's format, where `map.rooms[i]` is the label of
+    /// room `i`.
+    pub fn from_map(map: &Map) -> Result<Self> {
+        Self::from_map_with_labels(map, &map.rooms)
+    }
+
+    /// Like [`Library::from_map`], with `labels[i]` as the label of room `i` instead of
+    /// `map.rooms[i]`. Labels may be larger than the ones explores report. Fails on a map
+    /// [`validate_map`] rejects.
+    pub fn from_map_with_labels(map: &Map, labels: &[usize]) -> Result<Self> {
+        validate_map(map)?;
+        let room_count = map.rooms.len();
+        let ids = Map {
+            rooms: (0..room_count).collect(),
+            ..map.clone()
+        };
+        let library = Self {
+            rooms: rooms_from_map(&ids, labels)?,
+            starting_room: map.starting_room,
+            room_count,
+            grid: None,
+        };
+        if !library.is_connected() {
+            bail!("Some rooms are unreachable from the starting room");
+        }
+        Ok(library)
+    }
+
+    /// The library in the API's format, with every door pair listed once.
+    pub fn to_map(&self) -> Map {
+        let connections = pair_doors(&self.rooms);
+        Map {
+            // Explore results only report the lowest two bits of a label.
+            rooms: (0..self.room_count)
+                .map(|room| self.rooms[&room].label % LABELS)
+                .collect(),
+            starting_room: self.starting_room,
+            connections,
+        }
+    }
+
+    /// Whether `map` describes this library up to renumbering of rooms: starting from the
+    /// starting rooms, every door must lead to rooms with the same labels in both.
+    ///
+    /// Labels are compared modulo [`LABELS`], as explores report them, and only the room a door
+    /// leads to is compared, not which door of that room it comes in through. This is what a
+    /// [`Simulator`] guess accepts.
+    pub fn matches(&self, map: &Map) -> bool {
+        self.diagnose(map).is_correct()
+    }
+
+    /// How `map` differs from this library; see [`GuessDiagnosis`].
+    pub fn diagnose(&self, map: &Map) -> GuessDiagnosis {
+        let room_count = map.rooms.len();
+        let mut diagnosis = GuessDiagnosis {
+            guessed_rooms: room_count,
+            actual_rooms: self.room_count,
+            ..GuessDiagnosis::default()
+        };
+        if map.starting_room >= room_count {
+            diagnosis.unmatched_rooms = room_count;
+            return diagnosis;
+        }
+
+        let mut edges = vec![[None; DOORS]; room_count];
+        for conn in &map.connections {
+            let valid = [&conn.from, &conn.to]
+                .iter()
+                .all(|end| end.room < room_count && end.door < DOORS);
+            if !valid {
+                diagnosis.edge_errors += 1;
+                continue;
+            }
+            edges[conn.from.room][conn.from.door] = Some(conn.to.room);
+            edges[conn.to.room][conn.to.door] = Some(conn.from.room);
+        }
+
+        let mut to_actual = vec![None; room_count];
+        let mut taken = HashSet::from([self.starting_room]);
+        to_actual[map.starting_room] = Some(self.starting_room);
+        let mut queue = VecDeque::from([map.starting_room]);
+        while let Some(room) = queue.pop_front() {
+            let actual = &self.rooms[&to_actual[room].unwrap()];
+            if map.rooms[room] != actual.label % LABELS {
+                diagnosis.label_errors += 1;
+            }
+            for (&next, &actual_next) in edges[room].iter().zip(&actual.connections) {
+                match (next, actual_next) {
+                    (None, None) => {}
+                    (Some(next), Some(actual_next)) => match to_actual[next] {
+                        Some(mapped) if mapped == actual_next => {}
+                        Some(_) => diagnosis.edge_errors += 1,
+                        None if !taken.insert(actual_next) => diagnosis.edge_errors += 1,
+                        None => {
+                            to_actual[next] = Some(actual_next);
+                            queue.push_back(next);
+                        }
+                    },
+                    _ => diagnosis.edge_errors += 1,
+                }
+            }
+        }
+
+        diagnosis.unmatched_rooms = to_actual.iter().filter(|mapped| mapped.is_none()).count();
+        diagnosis
+    }
+
     pub fn max_doorways(&self) -> usize {
-        18 * self.room_count
+        doorway_budget(self.room_count)
+    }
+}
+
+/// How a guessed map differs from the library, as found by [`Simulator::guess_diagnose`].
+///
+/// Guessed rooms are matched to library rooms by walking both from their starting rooms. A
+/// guessed door that leads somewhere else than the matching says is an edge error and isn't
+/// walked through, so the rooms behind a wrong edge may stay unmatched.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GuessDiagnosis {
+    pub guessed_rooms: usize,
+    pub actual_rooms: usize,
+    /// Matched rooms labelled differently from their library room.
+    pub label_errors: usize,
+    /// Doors, counted from each side, that lead to the wrong room or are connected on one side
+    /// only, plus connections referring to rooms or doors that don't exist.
+    pub edge_errors: usize,
+    /// Guessed rooms never matched to a library room.
+    pub unmatched_rooms: usize,
+}
+
+impl GuessDiagnosis {
+    pub fn is_correct(&self) -> bool {
+        self.error_count() == 0
+    }
+
+    /// All mistakes together, with every room too many or too few counting as one.
+    pub fn error_count(&self) -> usize {
+        self.guessed_rooms.abs_diff(self.actual_rooms)
+            + self.label_errors
+            + self.edge_errors
+            + self.unmatched_rooms
+    }
+}
+
+/// Where the doorway budget went, as reported by [`Simulator::cost_report`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostReport {
+    pub total_doorways: usize,
+    /// Doorways used by each `explore` call, in call order.
+    pub per_call: Vec<usize>,
+    pub distinct_rooms_discovered: usize,
+    pub avg_doorways_per_room: f64,
+}
+
+/// Exploration state of a [`Simulator`], taken by [`Simulator::snapshot`]. The library itself
+/// isn't part of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimState {
+    doorways_used: usize,
+    doorways_per_call: Vec<usize>,
+    discovered_rooms: HashSet<usize>,
+}
+
+impl SimState {
+    pub fn doorways_used(&self) -> usize {
+        self.doorways_used
+    }
+}
+
+/// Observations captured from a real problem together with the library that produced them, for
+/// replaying in a [`Simulator`] as a regression fixture.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Capture {
+    pub map: Map,
+    /// Label of every room of `map`, as the library has them.
+    pub labels: Vec<usize>,
+    /// What explores reported the labels modulo; `None` if they were reported whole.
+    #[serde(default = "Capture::default_label_modulus")]
+    pub label_modulus: Option<usize>,
+    #[serde(default)]
+    pub convention: ObservationConvention,
+    #[serde(default = "Capture::default_charge_start_observation")]
+    pub charge_start_observation: bool,
+    pub observations: ObservationSet,
+}
+
+impl Capture {
+    fn default_label_modulus() -> Option<usize> {
+        Some(LABELS)
+    }
+
+    fn default_charge_start_observation() -> bool {
+        true
+    }
+
+    /// A simulator of the captured library, set up to report observations the way they were
+    /// captured.
+    pub fn simulator(&self) -> Result<Simulator> {
+        let mut simulator = Simulator::from_map_with_labels(&self.map, &self.labels)?;
+        simulator.set_label_modulus(self.label_modulus);
+        simulator.set_observation_convention(self.convention);
+        simulator.set_charge_start_observation(self.charge_start_observation);
+        Ok(simulator)
+    }
+
+    /// Explores every captured plan in a fresh [`Capture::simulator`] and fails at the first
+    /// observation it doesn't reproduce.
+    pub fn replay(&self) -> Result<()> {
+        let mut simulator = self.simulator()?;
+        for (index, observation) in self.observations.iter().enumerate() {
+            // Captures may span more explores than a single budget allows.
+            simulator.reset_exploration();
+            let response = simulator.walk_plans(vec![observation.plan.clone()])?;
+            let labels = observation.labels_usize();
+            if response.results.first() != Some(&labels) {
+                bail!(
+                    "Observation {} ({:?}): captured {:?}, simulated {:?}",
+                    index,
+                    observation.plan,
+                    labels,
+                    response.results.first()
+                );
+            }
+        }
+        Ok(())
     }
 }
 
@@ -149,45 +625,184 @@ impl Library {
 pub struct Simulator {
     library: Library,
     current_doorways_used: usize,
+    /// Whether observing the starting room at the beginning of each plan costs a doorway.
+    /// Defaults to `true`, so a batch of K plans costs K doorways on top of the doors walked.
+    charge_start_observation: bool,
+    convention: ObservationConvention,
+    /// Explore results report labels modulo this, like the API's two-bit labels. `None`
+    /// reports them whole.
+    label_modulus: Option<usize>,
+    /// Whether an unconnected door fails the explore, since contest libraries have none. When
+    /// `false`, a plan stops at the dead end and the rest of it goes unobserved.
+    strict_complete: bool,
+    doorways_per_call: Vec<usize>,
+    discovered_rooms: HashSet<usize>,
+    /// How `select` generates a fresh library, and the RNG it draws from. `None` for a fixed
+    /// library, which `select` only resets the exploration of.
+    regeneration: Option<(GenerationMode, StdRng)>,
 }
 
 impl Simulator {
     pub fn new(room_count: usize, rng: &mut impl Rng) -> Result<Self> {
-        let library = Library::generate(room_count, rng)?;
-        Ok(Self {
+        Self::with_mode(room_count, GenerationMode::default(), rng)
+    }
+
+    /// Strict about dead ends unless `mode` is [`GenerationMode::Random`].
+    pub fn with_mode(room_count: usize, mode: GenerationMode, rng: &mut impl Rng) -> Result<Self> {
+        let library = Library::generate_with(room_count, mode, rng)?;
+        let mut simulator = Self::from_library(library);
+        simulator.strict_complete = mode != GenerationMode::Random;
+        simulator.regeneration = Some((mode, StdRng::from_rng(rng)?));
+        Ok(simulator)
+    }
+
+    /// Simulates the library described by `map`, e.g. one saved from [`Simulator::get_actual_map`].
+    pub fn from_map(map: &Map) -> Result<Self> {
+        Ok(Self::from_library(Library::from_map(map)?))
+    }
+
+    /// Simulates `map` with explicit room labels; see [`Library::from_map_with_labels`].
+    pub fn from_map_with_labels(map: &Map, labels: &[usize]) -> Result<Self> {
+        Ok(Self::from_library(Library::from_map_with_labels(
+            map, labels,
+        )?))
+    }
+
+    pub fn from_library(library: Library) -> Self {
+        Self {
             library,
             current_doorways_used: 0,
+            charge_start_observation: true,
+            convention: ObservationConvention::default(),
+            label_modulus: Some(LABELS),
+            strict_complete: true,
+            doorways_per_call: Vec::new(),
+            discovered_rooms: HashSet::new(),
+            regeneration: None,
+        }
+    }
+
+    pub fn set_charge_start_observation(&mut self, charge: bool) {
+        self.charge_start_observation = charge;
+    }
+
+    /// Which labels explore results include. Doorways are charged the same either way.
+    pub fn set_observation_convention(&mut self, convention: ObservationConvention) {
+        self.convention = convention;
+    }
+
+    /// Sets what explore results report labels modulo, `Some(LABELS)` by default; `Some(0)`
+    /// counts as `None`. Guesses are still compared modulo [`LABELS`].
+    pub fn set_label_modulus(&mut self, modulus: Option<usize>) {
+        self.label_modulus = modulus.filter(|&modulus| modulus > 0);
+    }
+
+    /// Whether explores fail on an unconnected door, `true` by default. Turn it off to explore
+    /// incomplete libraries, where a plan ends at its first dead end.
+    pub fn set_strict_complete(&mut self, strict: bool) {
+        self.strict_complete = strict;
+    }
+
+    pub fn with_seed(room_count: usize, seed: u64) -> Result<Self> {
+        Self::with_mode_seed(room_count, GenerationMode::default(), seed)
+    }
+
+    pub fn with_mode_seed(room_count: usize, mode: GenerationMode, seed: u64) -> Result<Self> {
+        Self::with_mode(room_count, mode, &mut StdRng::seed_from_u64(seed))
+    }
+
+    /// Generates a fresh library for the problem, sized by the [`ProblemCatalog`] for the contest
+    /// problems and as before for any other name, and reports its size as `roomCount`.
+    fn _select(&mut self, problem_name: String) -> Result<SelectResponse> {
+        if let Some((mode, rng)) = &mut self.regeneration {
+            let room_count = ProblemCatalog::get(&problem_name)
+                .map_or(self.library.room_count, |spec| spec.room_count);
+            self.library = Library::generate_with(room_count, *mode, rng)?;
+        }
+        self.reset_exploration();
+        Ok(SelectResponse {
+            data: serde_json::json!({
+                "problemName": problem_name,
+                "roomCount": self.library.room_count,
+            }),
         })
     }
 
     fn _explore(&mut self, plans: Vec<String>) -> Result<ExploreResponse> {
+        let used_before = self.current_doorways_used;
+        let result = self.walk_plans(plans);
+        self.doorways_per_call
+            .push(self.current_doorways_used - used_before);
+        result
+    }
+
+    /// Walks every plan from the starting room. Running out of doorways ends the walk: the plans
+    /// walked to the end so far are returned as a truncated response, or an error if there are
+    /// none. The doorways spent on the unfinished plan stay spent.
+    ///
+    /// A charcoal mark relabels the current room for the rest of its plan only, and costs no
+    /// doorway.
+    fn walk_plans(&mut self, plans: Vec<String>) -> Result<ExploreResponse> {
         let mut results = Vec::new();
+        let mut truncated = false;
 
-        for plan in plans {
+        'plans: for plan in plans {
+            let steps = parse_plan_steps(&plan)?;
+            let mut marked: HashMap<usize, usize> = HashMap::new();
+            let label = |marked: &HashMap<usize, usize>, room: usize| {
+                marked
+                    .get(&room)
+                    .copied()
+                    .unwrap_or(self.library.rooms[&room].label)
+            };
             let mut current_room = self.library.starting_room;
             let mut room_labels = Vec::new();
 
             // Start with the starting room's label
-            room_labels.push(self.library.rooms[&current_room].label);
-            self.current_doorways_used += 1;
+            room_labels.push(label(&marked, current_room));
+            self.discovered_rooms.insert(current_room);
+            if self.charge_start_observation {
+                self.current_doorways_used += 1;
+            }
 
             // Follow the plan
-            for door_char in plan.chars() {
-                if self.current_doorways_used >= self.library.max_doorways() {
-                    bail!("Maximum doorways exceeded for this library");
-                }
-
-                let door = match door_char.to_digit(10) {
-                    Some(d) if d < DOORS as u32 => d as usize,
-                    _ => bail!("Invalid door number in plan: {}", door_char),
+            for &step in &steps {
+                let door = match step {
+                    PlanStep::Mark(new_label) => {
+                        marked.insert(current_room, new_label);
+                        room_labels.push(new_label);
+                        continue;
+                    }
+                    _ if self.current_doorways_used >= self.library.max_doorways() => {
+                        if results.is_empty() {
+                            bail!("Maximum doorways exceeded for this library");
+                        }
+                        truncated = true;
+                        break 'plans;
+                    }
+                    PlanStep::Reset => {
+                        current_room = self.library.starting_room;
+                        room_labels.push(label(&marked, current_room));
+                        if self.charge_start_observation {
+                            self.current_doorways_used += 1;
+                        }
+                        continue;
+                    }
+                    PlanStep::Door(door) => door,
                 };
 
                 match self.library.rooms[&current_room].connections[door] {
                     Some(next_room) => {
                         current_room = next_room;
-                        room_labels.push(self.library.rooms[&current_room].label);
+                        room_labels.push(label(&marked, current_room));
+                        self.discovered_rooms.insert(current_room);
                         self.current_doorways_used += 1;
                     }
+                    None if self.strict_complete => bail!(
+                        "Door {} of room {} is not connected; every door should be",
+                        door,
+                        current_room
+                    ),
                     None => {
                         // Dead end - exploration stops here
                         break;
@@ -195,108 +810,46 @@ impl Simulator {
                 }
             }
 
+            if self.convention == ObservationConvention::ExcludingFinal {
+                room_labels.truncate(steps.len());
+            }
             results.push(room_labels);
         }
 
         Ok(ExploreResponse {
             results: results
                 .iter()
-                .map(|x| x.iter().map(|y| y % 4).collect())
+                .map(|x| {
+                    x.iter()
+                        .map(|&y| self.label_modulus.map_or(y, |modulus| y % modulus))
+                        .collect()
+                })
                 .collect(),
-            query_count: self.current_doorways_used,
+            query_count: self.current_doorways_used.into(),
+            truncated,
         })
     }
 
+    /// Accepts `map` if it [`Library::matches`] the library, so reconstructed maps with their
+    /// own room numbering can be correct.
     fn _guess(&self, map: Map) -> Result<GuessResponse> {
-        // Verify the map matches the actual library structure
-
-        // Check if starting room matches
-        if map.starting_room != self.library.starting_room {
-            return Ok(GuessResponse { correct: false });
-        }
-
-        // Check if all rooms are present with correct labels
-        let mut expected_rooms: HashMap<usize, usize> = self
-            .library
-            .rooms
-            .iter()
-            .map(|(id, room)| (*id, room.label))
-            .collect();
-
-        for &room_id in &map.rooms {
-            if !expected_rooms.contains_key(&room_id) {
-                return Ok(GuessResponse { correct: false });
-            }
-            expected_rooms.remove(&room_id);
-        }
-
-        if !expected_rooms.is_empty() {
-            return Ok(GuessResponse { correct: false });
-        }
-
-        // Check connections
-        let mut expected_connections = HashSet::new();
-
-        for (room_id, room) in &self.library.rooms {
-            for (door, &connected_room) in room.connections.iter().enumerate() {
-                if let Some(connected_room) = connected_room {
-                    // Add connection in canonical form (smaller room first)
-                    let conn = if *room_id < connected_room {
-                        MapConnection {
-                            from: RoomDoor {
-                                room: *room_id,
-                                door,
-                            },
-                            to: RoomDoor {
-                                room: connected_room,
-                                door: self.find_reverse_door(*room_id, door, connected_room),
-                            },
-                        }
-                    } else {
-                        MapConnection {
-                            from: RoomDoor {
-                                room: connected_room,
-                                door: self.find_reverse_door(*room_id, door, connected_room),
-                            },
-                            to: RoomDoor {
-                                room: *room_id,
-                                door,
-                            },
-                        }
-                    };
-                    expected_connections.insert(conn);
-                }
-            }
-        }
-
-        let mut provided_connections = HashSet::new();
-        for conn in &map.connections {
-            // Normalize connection order
-            let normalized_conn = if conn.from.room < conn.to.room {
-                conn.clone()
-            } else {
-                MapConnection {
-                    from: conn.to.clone(),
-                    to: conn.from.clone(),
-                }
-            };
-            provided_connections.insert(normalized_conn);
-        }
-
         Ok(GuessResponse {
-            correct: expected_connections == provided_connections,
+            correct: self.library.matches(&map),
         })
     }
 
-    fn find_reverse_door(&self, from_room: usize, _from_door: usize, to_room: usize) -> usize {
-        if let Some(to_room_data) = self.library.rooms.get(&to_room) {
-            for (door, &connected) in to_room_data.connections.iter().enumerate() {
-                if connected == Some(from_room) {
-                    return door;
-                }
-            }
-        }
-        0 // Fallback, shouldn't happen in a well-formed library
+    /// What would make a guess of `map` wrong, without making the guess. Only the simulator
+    /// can tell, so this is for tuning offline.
+    pub fn guess_diagnose(&self, map: &Map) -> GuessDiagnosis {
+        self.library.diagnose(map)
+    }
+
+    /// [`Simulator::guess_diagnose`] for each candidate.
+    pub fn diagnose_all(&self, candidates: &[Map]) -> Vec<GuessDiagnosis> {
+        candidates
+            .iter()
+            .map(|map| self.guess_diagnose(map))
+            .collect()
     }
 
     pub fn get_library_info(&self) -> (usize, usize) {
@@ -305,40 +858,68 @@ impl Simulator {
 
     pub fn reset_exploration(&mut self) {
         self.current_doorways_used = 0;
+        self.doorways_per_call.clear();
+        self.discovered_rooms.clear();
     }
 
-    pub fn get_actual_map(&self) -> Map {
-        let mut connections = Vec::new();
-        let rooms: Vec<usize> = self.library.rooms.keys().cloned().collect();
-
-        for (room_id, room) in &self.library.rooms {
-            for (door, &connected_room) in room.connections.iter().enumerate() {
-                if let Some(connected_room) = connected_room {
-                    // Only add each connection once (avoid duplicates)
-                    if *room_id < connected_room {
-                        let reverse_door = self.find_reverse_door(*room_id, door, connected_room);
-                        connections.push(MapConnection {
-                            from: RoomDoor {
-                                room: *room_id,
-                                door,
-                            },
-                            to: RoomDoor {
-                                room: connected_room,
-                                door: reverse_door,
-                            },
-                        });
-                    }
-                }
-            }
+    /// Captures the doorways spent so far, to try out some explores and then
+    /// [`Simulator::restore`] to this point.
+    pub fn snapshot(&self) -> SimState {
+        SimState {
+            doorways_used: self.current_doorways_used,
+            doorways_per_call: self.doorways_per_call.clone(),
+            discovered_rooms: self.discovered_rooms.clone(),
         }
+    }
 
-        Map {
-            rooms,
-            starting_room: self.library.starting_room,
-            connections,
+    /// Rewinds the exploration to `state`. Meant for a snapshot of the same library: a `select`
+    /// that regenerated the library in between is not undone.
+    pub fn restore(&mut self, state: SimState) {
+        self.current_doorways_used = state.doorways_used;
+        self.doorways_per_call = state.doorways_per_call;
+        self.discovered_rooms = state.discovered_rooms;
+    }
+
+    pub fn cost_report(&self) -> CostReport {
+        let distinct_rooms_discovered = self.discovered_rooms.len();
+        CostReport {
+            total_doorways: self.current_doorways_used,
+            per_call: self.doorways_per_call.clone(),
+            distinct_rooms_discovered,
+            avg_doorways_per_room: if distinct_rooms_discovered == 0 {
+                0.0
+            } else {
+                self.current_doorways_used as f64 / distinct_rooms_discovered as f64
+            },
         }
     }
 
+    /// The library as the API would describe it: `rooms[i]` is the observed label of room `i`.
+    pub fn get_actual_map(&self) -> Map {
+        self.library.to_map()
+    }
+
+    /// Where door `door` of room `room` really leads: the room behind it and the door of that
+    /// room leading back. `None` if the door is unconnected or doesn't exist.
+    ///
+    /// Gives away part of the answer, so it only exists for tutorials and demos showing how a
+    /// reconstruction converges, behind the `peek` feature.
+    #[cfg(any(test, feature = "peek"))]
+    pub fn peek_edge(&self, room: usize, door: usize) -> Option<(usize, usize)> {
+        pair_doors(&self.library.rooms)
+            .into_iter()
+            .find_map(|connection| {
+                let (from, to) = (connection.from, connection.to);
+                if (from.room, from.door) == (room, door) {
+                    Some((to.room, to.door))
+                } else if (to.room, to.door) == (room, door) {
+                    Some((from.room, from.door))
+                } else {
+                    None
+                }
+            })
+    }
+
     pub fn remaining_doorways(&self) -> usize {
         self.library
             .max_doorways()
@@ -348,8 +929,8 @@ impl Simulator {
 
 #[async_trait]
 impl AedificiumClient for Simulator {
-    async fn select(&self, _problem_name: String) -> Result<SelectResponse> {
-        unimplemented!()
+    async fn select(&mut self, problem_name: String) -> Result<SelectResponse> {
+        self._select(problem_name)
     }
 
     async fn explore(&mut self, plans: Vec<String>) -> Result<ExploreResponse> {
@@ -363,7 +944,7 @@ impl AedificiumClient for Simulator {
 
 #[cfg(test)]
 mod tests {
-    use rand::{rngs::StdRng, SeedableRng};
+    use icfpc2025_common::{EdgeSet, MapBuilder, ProblemInfo, RESET};
 
     use super::*;
 
@@ -375,6 +956,540 @@ mod tests {
         assert_eq!(library.starting_room, 0);
     }
 
+    #[test]
+    fn test_regular_library_has_no_unconnected_doors() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for room_count in [1, 2, 3, 6, 12, 30] {
+            let library = Library::generate_regular(room_count, &mut rng).unwrap();
+            assert_eq!(library.rooms.len(), room_count);
+            assert!(library.is_connected());
+            for room in library.rooms.values() {
+                assert!(room.connections.iter().all(|c| c.is_some()));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_strict_libraries_have_no_dead_ends() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for room_count in [1, 2, 3, 6, 12] {
+            let mut simulator = Simulator::new(room_count, &mut rng).unwrap();
+            assert!(simulator.library.is_complete());
+
+            let plans: Vec<String> = (0..room_count)
+                .map(|_| {
+                    (0..12)
+                        .map(|_| char::from(b'0' + rng.gen_range(0..DOORS as u8)))
+                        .collect()
+                })
+                .collect();
+            let response = simulator.explore(plans.clone()).await.unwrap();
+            assert!(!response.truncated);
+            for (plan, labels) in plans.iter().zip(&response.results) {
+                assert_eq!(labels.len(), plan.len() + 1);
+            }
+        }
+
+        // Strict exploring fails at the first dead end of an incomplete library instead.
+        let mut simulator = Simulator::from_map(&line_map()).unwrap();
+        assert!(!simulator.library.is_complete());
+        assert!(simulator.explore(vec!["002".to_string()]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_single_room_library() {
+        for mode in [GenerationMode::Random, GenerationMode::Regular] {
+            let mut simulator = Simulator::with_mode_seed(1, mode, 3).unwrap();
+            assert!(simulator.library.is_complete(), "{:?}", mode);
+            assert!(simulator.library.is_connected());
+
+            let response = simulator
+                .explore(vec!["012345".to_string(), "5R0".to_string()])
+                .await
+                .unwrap();
+            let label = response.results[0][0];
+            assert_eq!(response.results, vec![vec![label; 7], vec![label; 4]]);
+
+            let mut map = MapBuilder::new().room(0, label);
+            for door in 0..DOORS {
+                map = map.connect(0, door, 0, door);
+            }
+            let guess = simulator._guess(map.build().unwrap()).unwrap();
+            assert!(guess.correct, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn test_peek_edge_agrees_with_actual_map() {
+        let simulator = Simulator::with_seed(4, 2).unwrap();
+        let map = simulator.get_actual_map();
+        for connection in &map.connections {
+            let (from, to) = (&connection.from, &connection.to);
+            assert_eq!(
+                simulator.peek_edge(from.room, from.door),
+                Some((to.room, to.door))
+            );
+            assert_eq!(
+                simulator.peek_edge(to.room, to.door),
+                Some((from.room, from.door))
+            );
+        }
+        assert_eq!(simulator.peek_edge(0, DOORS), None);
+        assert_eq!(simulator.peek_edge(4, 0), None);
+    }
+
+    #[test]
+    fn test_grid_library() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let library = Library::generate_grid(4, 3, &mut rng).unwrap();
+        assert_eq!(library.grid_dimensions(), Some((4, 3)));
+        assert_eq!(library.rooms.len(), 12);
+        assert!(library.is_connected());
+        for room in library.rooms.values() {
+            assert!(room.connections.iter().all(|c| c.is_some()));
+        }
+
+        // Room 5 is (1, 1): north 1, east 6, south 9, west 4.
+        assert_eq!(
+            library.rooms[&5].connections[..4],
+            [Some(1), Some(6), Some(9), Some(4)]
+        );
+        // Room 3 is (3, 0), on the north and east border.
+        assert_eq!(
+            library.rooms[&3].connections[..4],
+            [Some(3), Some(3), Some(7), Some(2)]
+        );
+
+        assert!(Library::generate_grid(0, 3, &mut rng).is_err());
+        assert_eq!(
+            Library::generate(3, &mut rng).unwrap().grid_dimensions(),
+            None
+        );
+    }
+
+    fn sorted_connections(map: &Map) -> Vec<(usize, usize, usize, usize)> {
+        EdgeSet::from(map)
+            .iter()
+            .map(|c| (c.from.room, c.from.door, c.to.room, c.to.door))
+            .collect()
+    }
+
+    #[test]
+    fn test_rooms_map_round_trip() {
+        let map = Map {
+            rooms: vec![0, 1, 2],
+            starting_room: 0,
+            connections: vec![
+                MapConnection {
+                    from: RoomDoor { room: 0, door: 0 },
+                    to: RoomDoor { room: 1, door: 3 },
+                },
+                MapConnection {
+                    from: RoomDoor { room: 2, door: 5 },
+                    to: RoomDoor { room: 1, door: 1 },
+                },
+                MapConnection {
+                    from: RoomDoor { room: 0, door: 4 },
+                    to: RoomDoor { room: 2, door: 2 },
+                },
+            ],
+        };
+
+        let rooms = rooms_from_map(&map, &[10, 11, 12]).unwrap();
+        assert_eq!(rooms[&0].label, 10);
+        assert_eq!(rooms[&1].connections[1], Some(2));
+        assert_eq!(rooms[&2].connections[5], Some(1));
+
+        let back = map_from_rooms(&rooms, 0);
+        assert_eq!(back.rooms, vec![0, 1, 2]);
+        assert_eq!(sorted_connections(&back), sorted_connections(&map));
+
+        let again = rooms_from_map(&back, &[10, 11, 12]).unwrap();
+        for id in 0..3 {
+            assert_eq!(again[&id].label, rooms[&id].label);
+            assert_eq!(again[&id].connections, rooms[&id].connections);
+        }
+    }
+
+    #[test]
+    fn test_explore_keeps_plans_completed_before_budget_runs_out() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 1).unwrap();
+        assert_eq!(simulator.remaining_doorways(), 54);
+        let long = "0".repeat(40);
+
+        let response = simulator
+            ._explore(vec![long.clone(), long.clone(), "1".to_string()])
+            .unwrap();
+        assert!(response.truncated);
+        assert_eq!(response.results.len(), 1);
+        assert_eq!(response.results[0].len(), 41);
+        assert_eq!(simulator.remaining_doorways(), 0);
+
+        // Once the budget is gone there is nothing to salvage.
+        assert!(simulator._explore(vec!["1".to_string()]).is_err());
+
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 1).unwrap();
+        let response = simulator._explore(vec![long]).unwrap();
+        assert!(!response.truncated);
+    }
+
+    #[test]
+    fn test_parallel_edges_pair_distinct_doors() {
+        // Doors 0 and 3 of room 0 both lead to room 1, whose doors 5 and 2 lead back. Room 1
+        // also loops through doors 1 and 4.
+        let mut rooms = HashMap::from([(0, Room::new(0)), (1, Room::new(1))]);
+        for door in [0, 3] {
+            rooms.get_mut(&0).unwrap().connect_door(door, 1);
+        }
+        for (door, target) in [(5, 0), (2, 0), (1, 1), (4, 1)] {
+            rooms.get_mut(&1).unwrap().connect_door(door, target);
+        }
+        assert_eq!(find_reverse_doors(&rooms, 0, 1), vec![2, 5]);
+        assert_eq!(find_reverse_doors(&rooms, 1, 1), vec![1, 4]);
+
+        let map = map_from_rooms(&rooms, 0);
+        assert_eq!(
+            sorted_connections(&map),
+            vec![(0, 0, 1, 2), (0, 3, 1, 5), (1, 1, 1, 4)]
+        );
+        // Every door is used once, so the map converts back to the same graph.
+        let again = rooms_from_map(&map, &[0, 1]).unwrap();
+        for id in 0..2 {
+            assert_eq!(again[&id].connections, rooms[&id].connections);
+        }
+    }
+
+    #[test]
+    fn test_rooms_from_map_rejects_reused_door() {
+        let map = Map {
+            rooms: vec![0, 1, 2],
+            starting_room: 0,
+            connections: vec![
+                MapConnection {
+                    from: RoomDoor { room: 0, door: 0 },
+                    to: RoomDoor { room: 1, door: 0 },
+                },
+                MapConnection {
+                    from: RoomDoor { room: 0, door: 0 },
+                    to: RoomDoor { room: 2, door: 0 },
+                },
+            ],
+        };
+        assert!(rooms_from_map(&map, &[0, 1, 2]).is_err());
+        assert!(rooms_from_map(&map, &[0, 1]).is_err());
+        let error = Simulator::from_map(&map).unwrap_err().to_string();
+        assert!(error.starts_with("Door 0 of room 0 "), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_actual_map_round_trip_and_guess() {
+        let mut rng = StdRng::seed_from_u64(42);
+        for room_count in [1, 3, 6, 12] {
+            let simulator =
+                Simulator::with_mode(room_count, GenerationMode::Regular, &mut rng).unwrap();
+            let map = simulator.get_actual_map();
+            // Every door of a regular library is listed exactly once.
+            let ends: HashSet<(usize, usize)> = map
+                .connections
+                .iter()
+                .flat_map(|c| [(c.from.room, c.from.door), (c.to.room, c.to.door)])
+                .collect();
+            assert_eq!(ends.len(), room_count * DOORS);
+
+            let reloaded = Simulator::from_map(&map).unwrap();
+            assert!(reloaded.guess(map.clone()).await.unwrap().correct);
+            assert!(simulator.guess(map.clone()).await.unwrap().correct);
+
+            let mut permutation: Vec<usize> = (0..room_count).collect();
+            permutation.shuffle(&mut rng);
+            let renumbered = map.relabel(&permutation).unwrap();
+            assert!(simulator.guess(renumbered).await.unwrap().correct);
+
+            let mut wrong_label = map.clone();
+            wrong_label.rooms[0] = (wrong_label.rooms[0] + 1) % 4;
+            assert!(!simulator.guess(wrong_label).await.unwrap().correct);
+        }
+    }
+
+    #[test]
+    fn test_guess_semantics() {
+        // Room 0 -- room 1 through door 0 of each, every other door a self-loop.
+        let mut map = MapBuilder::new().room(0, 1).room(1, 2).connect(0, 0, 1, 0);
+        for room in 0..2 {
+            for door in 1..DOORS {
+                map = map.connect(room, door, room, door);
+            }
+        }
+        let map = map.build().unwrap();
+        let library = Library::from_map_with_labels(&map, &[1 + LABELS, 2]).unwrap();
+        let simulator = Simulator::from_library(library);
+
+        // Labels are compared as explores report them.
+        assert!(simulator._guess(map.clone()).unwrap().correct);
+        assert!(
+            simulator
+                ._guess(map.relabel(&[1, 0]).unwrap())
+                .unwrap()
+                .correct
+        );
+
+        let mut wrong_start = map.clone();
+        wrong_start.starting_room = 1;
+        assert!(!simulator._guess(wrong_start).unwrap().correct);
+
+        let mut wrong_edge = map.clone();
+        wrong_edge.connections.retain(|c| c.from.room == c.to.room);
+        wrong_edge.connections.push(MapConnection {
+            from: RoomDoor { room: 0, door: 0 },
+            to: RoomDoor { room: 0, door: 0 },
+        });
+        assert!(!simulator._guess(wrong_edge).unwrap().correct);
+    }
+
+    #[test]
+    fn test_diagnose_counts_mistakes() {
+        let simulator = Simulator::with_mode_seed(4, GenerationMode::Regular, 2).unwrap();
+        let map = simulator.get_actual_map();
+        assert_eq!(simulator.guess_diagnose(&map).error_count(), 0);
+
+        let mut wrong_label = map.clone();
+        wrong_label.rooms[1] = (wrong_label.rooms[1] + 1) % 4;
+        let diagnosis = simulator.guess_diagnose(&wrong_label);
+        assert_eq!(diagnosis.label_errors, 1);
+        assert_eq!(diagnosis.edge_errors, 0);
+
+        // Without one of its connections, both doors of it are wrong from the room they're on.
+        let mut missing_edge = map.clone();
+        let removed = missing_edge
+            .connections
+            .iter()
+            .position(|c| c.from.room != c.to.room)
+            .unwrap();
+        missing_edge.connections.remove(removed);
+        let diagnosis = simulator.guess_diagnose(&missing_edge);
+        assert_eq!(diagnosis.label_errors, 0);
+        assert!(diagnosis.edge_errors > 0);
+        assert!(!diagnosis.is_correct());
+
+        let mut too_small = map.clone();
+        too_small.rooms.pop();
+        too_small
+            .connections
+            .retain(|c| c.from.room < 3 && c.to.room < 3);
+        let diagnoses = simulator.diagnose_all(&[map, too_small]);
+        assert!(diagnoses[0].is_correct());
+        assert_eq!(diagnoses[1].guessed_rooms, 3);
+        assert!(diagnoses[1].error_count() > 1);
+    }
+
+    /// The same check as the `walk_matches_explore` fuzz target, over a few generated libraries.
+    #[tokio::test]
+    async fn test_explore_matches_map_walk() {
+        let mut rng = StdRng::seed_from_u64(11);
+        for room_count in [1, 2, 3, 6, 12] {
+            let map = Simulator::with_mode(room_count, GenerationMode::Regular, &mut rng)
+                .unwrap()
+                .get_actual_map();
+            let mut simulator = Simulator::from_map(&map).unwrap();
+            let plans: Vec<String> = (0..4)
+                .map(|_| {
+                    (0..2 * room_count)
+                        .map(|_| match rng.gen_range(0..DOORS + 2) {
+                            DOORS => RESET.to_string(),
+                            door if door > DOORS => format!("[{}]", rng.gen_range(0..LABELS)),
+                            door => door.to_string(),
+                        })
+                        .collect()
+                })
+                .collect();
+            let response = simulator.explore(plans.clone()).await.unwrap();
+            for (plan, labels) in plans.iter().zip(&response.results) {
+                assert_eq!(labels, &map.walk(plan).unwrap(), "plan {:?}", plan);
+            }
+        }
+
+        // Marks last for their own plan only, and cost no doorway: the doorways go to the two
+        // starting room observations and the two resets.
+        let map = Simulator::with_mode_seed(2, GenerationMode::Regular, 0)
+            .unwrap()
+            .get_actual_map();
+        let mut simulator = Simulator::from_map(&map).unwrap();
+        let label = (map.rooms[map.starting_room] + 1) % LABELS;
+        let plans = vec![format!("[{}]R", label), "R".to_string()];
+        let response = simulator.explore(plans).await.unwrap();
+        let start = map.rooms[map.starting_room];
+        assert_eq!(
+            response.results,
+            [vec![start, label, label], vec![start, start]]
+        );
+        assert_eq!(simulator.get_library_info().1, 2 + 2);
+    }
+
+    #[test]
+    fn test_guess_ignores_connection_order_and_direction() {
+        let mut rng = StdRng::seed_from_u64(7);
+        for mode in [GenerationMode::Regular, GenerationMode::Random] {
+            for room_count in [1, 2, 5, 12] {
+                for _ in 0..10 {
+                    let simulator = Simulator::with_mode(room_count, mode, &mut rng).unwrap();
+                    let mut map = simulator.get_actual_map();
+                    map.connections.shuffle(&mut rng);
+                    for conn in map.connections.iter_mut() {
+                        if rng.gen_bool(0.5) {
+                            std::mem::swap(&mut conn.from, &mut conn.to);
+                        }
+                    }
+                    assert!(
+                        simulator._guess(map.clone()).unwrap().correct,
+                        "{:?} library with {} rooms rejected {:?}",
+                        mode,
+                        room_count,
+                        map
+                    );
+
+                    let mut permutation: Vec<usize> = (0..room_count).collect();
+                    permutation.shuffle(&mut rng);
+                    let renumbered = map.relabel(&permutation).unwrap();
+                    assert!(simulator._guess(renumbered).unwrap().correct);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_select_regenerates_library() {
+        let mut simulator = Simulator::with_mode_seed(6, GenerationMode::Regular, 1).unwrap();
+        let before = simulator.get_actual_map();
+        simulator.explore(vec!["012".to_string()]).await.unwrap();
+
+        let response = simulator.select("custom".to_string()).await.unwrap();
+        assert_eq!(response.data["problemName"], "custom");
+        assert_eq!(simulator.get_library_info(), (6, 0));
+        assert!(!simulator.guess(before.clone()).await.unwrap().correct);
+
+        // Contest problems get their own size.
+        let response = simulator.select("secundus".to_string()).await.unwrap();
+        assert_eq!(simulator.get_library_info(), (12, 0));
+        assert_eq!(simulator.get_actual_map().rooms.len(), 12);
+        let info = ProblemInfo::from_select("secundus", &response);
+        assert_eq!(info.name, "secundus");
+        assert_eq!(info.room_count, Some(12));
+        assert_eq!(response.data["roomCount"], 12);
+
+        // A library loaded from a map stays the same.
+        let mut fixed = Simulator::from_map(&before).unwrap();
+        fixed.explore(vec!["012".to_string()]).await.unwrap();
+        let response = fixed.select("probatio".to_string()).await.unwrap();
+        assert_eq!(response.data["roomCount"], 6);
+        assert_eq!(fixed.get_library_info(), (6, 0));
+        assert!(fixed.guess(before).await.unwrap().correct);
+    }
+
+    /// Rooms 0 -> 1 -> 2 in a line with labels 0, 1, 2. Door 0 goes forward, door 1 back;
+    /// room 0's door 1 and room 2's door 0 loop back into the same room, and door 2 of room 2
+    /// is a dead end. Other doors are unconnected.
+    fn line_map() -> Map {
+        let connect = |a: (usize, usize), b: (usize, usize)| MapConnection {
+            from: RoomDoor {
+                room: a.0,
+                door: a.1,
+            },
+            to: RoomDoor {
+                room: b.0,
+                door: b.1,
+            },
+        };
+        Map {
+            rooms: vec![0, 1, 2],
+            starting_room: 0,
+            connections: vec![
+                connect((0, 0), (1, 1)),
+                connect((1, 0), (2, 1)),
+                connect((0, 1), (0, 1)),
+                connect((2, 0), (2, 0)),
+            ],
+        }
+    }
+
+    /// The label of the starting room comes first, then the label of each room arrived at.
+    /// A plan stops at the first unconnected door.
+    #[tokio::test]
+    async fn test_observed_label_sequences() {
+        let cases: &[(&str, &[usize])] = &[
+            ("", &[0]),
+            ("0", &[0, 1]),
+            ("00", &[0, 1, 2]),
+            // There and back again.
+            ("01", &[0, 1, 0]),
+            ("0011", &[0, 1, 2, 1, 0]),
+            // Self-loops observe the same room again.
+            ("1", &[0, 0]),
+            ("000", &[0, 1, 2, 2]),
+            // Dead ends stop the walk without observing anything.
+            ("002", &[0, 1, 2]),
+            ("0020", &[0, 1, 2]),
+            ("5", &[0]),
+        ];
+
+        let mut simulator = Simulator::from_map(&line_map()).unwrap();
+        simulator.set_strict_complete(false);
+        let plans: Vec<String> = cases.iter().map(|(plan, _)| plan.to_string()).collect();
+        let response = simulator.explore(plans).await.unwrap();
+        for ((plan, expected), observed) in cases.iter().zip(&response.results) {
+            assert_eq!(observed, expected, "plan {:?}", plan);
+        }
+    }
+
+    /// Answers every plan with label 0 for each step.
+    struct ZeroClient;
+
+    #[async_trait]
+    impl AedificiumClient for ZeroClient {
+        async fn select(&mut self, problem_name: String) -> Result<SelectResponse> {
+            Ok(SelectResponse {
+                data: serde_json::json!({ "problemName": problem_name }),
+            })
+        }
+
+        async fn explore(&mut self, plans: Vec<String>) -> Result<ExploreResponse> {
+            Ok(ExploreResponse {
+                results: plans.iter().map(|p| vec![0; p.len() + 1]).collect(),
+                query_count: plans.len().into(),
+                truncated: false,
+            })
+        }
+
+        async fn guess(&self, _data: Map) -> Result<GuessResponse> {
+            Ok(GuessResponse { correct: false })
+        }
+    }
+
+    async fn select_and_explore<C: AedificiumClient>(mut client: C) -> Vec<Vec<usize>> {
+        client.select("probatio".to_string()).await.unwrap();
+        client
+            .explore(vec!["".to_string(), "0".to_string()])
+            .await
+            .unwrap()
+            .results
+    }
+
+    #[tokio::test]
+    async fn test_clients_are_interchangeable() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 1).unwrap();
+        assert_eq!(select_and_explore(&mut simulator).await[1].len(), 2);
+        assert_eq!(simulator.get_library_info().1, 3);
+
+        let boxed: Vec<Box<dyn AedificiumClient>> = vec![Box::new(simulator), Box::new(ZeroClient)];
+        for client in boxed {
+            let results = tokio::spawn(select_and_explore(client)).await.unwrap();
+            assert_eq!(results.len(), 2);
+        }
+        assert_eq!(
+            select_and_explore(ZeroClient).await,
+            vec![vec![0], vec![0, 0]]
+        );
+    }
+
     #[test]
     fn test_simulator_creation() {
         let mut rng = StdRng::seed_from_u64(123);
@@ -401,7 +1516,7 @@ mod tests {
             .unwrap();
 
         assert!(!response.results.is_empty());
-        assert!(response.query_count > 0);
+        assert!(response.total_query_count() > 0);
         assert_eq!(response.results.len(), 6);
         for result in response.results {
             for observed_room in result {
@@ -409,4 +1524,93 @@ mod tests {
             }
         }
     }
+
+    #[tokio::test]
+    async fn test_start_observation_charge_modes() {
+        let plans = vec!["0".to_string(), "".to_string(), "0".to_string()];
+
+        let mut simulator =
+            Simulator::with_mode(2, GenerationMode::Regular, &mut StdRng::seed_from_u64(1))
+                .unwrap();
+        let response = simulator.explore(plans.clone()).await.unwrap();
+        // One doorway per door walked, plus one per plan for the starting room.
+        assert_eq!(response.total_query_count(), 2 + 3);
+
+        let mut simulator =
+            Simulator::with_mode(2, GenerationMode::Regular, &mut StdRng::seed_from_u64(1))
+                .unwrap();
+        simulator.set_charge_start_observation(false);
+        let response = simulator.explore(plans).await.unwrap();
+        assert_eq!(response.total_query_count(), 2);
+        assert_eq!(response.results[1].len(), 1);
+    }
+
+    #[test]
+    fn test_capture_replays() {
+        let capture: Capture =
+            serde_json::from_str(include_str!("../fixtures/captured_3_rooms.json")).unwrap();
+        capture.replay().unwrap();
+
+        // Reporting labels whole gives the rooms labelled 5 and 7 away.
+        let mut whole = capture.clone();
+        whole.label_modulus = None;
+        assert!(whole.replay().is_err());
+
+        let mut tampered = capture.clone();
+        tampered.observations.observations[3].labels[4] ^= 1;
+        let error = tampered.replay().unwrap_err();
+        assert!(error.to_string().starts_with("Observation 3"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_restore_rewinds_doorways() {
+        let mut simulator = Simulator::with_mode_seed(4, GenerationMode::Regular, 3).unwrap();
+        simulator.explore(vec!["01".to_string()]).await.unwrap();
+        let snapshot = simulator.snapshot();
+        assert_eq!(snapshot.doorways_used(), 3);
+        let report = simulator.cost_report();
+
+        let branch = simulator.explore(vec!["2345".to_string()]).await.unwrap();
+        assert_eq!(simulator.get_library_info().1, 8);
+
+        simulator.restore(snapshot.clone());
+        assert_eq!(simulator.get_library_info().1, 3);
+        assert_eq!(simulator.cost_report(), report);
+        assert_eq!(simulator.snapshot(), snapshot);
+
+        let again = simulator.explore(vec!["2345".to_string()]).await.unwrap();
+        assert_eq!(again.results, branch.results);
+        assert_eq!(again.query_count, branch.query_count);
+    }
+
+    #[tokio::test]
+    async fn test_cost_report_per_call_sums_to_total() {
+        let mut rng = StdRng::seed_from_u64(789);
+        let mut simulator = Simulator::new(6, &mut rng).unwrap();
+        simulator
+            .explore(vec!["012".to_string(), "345".to_string()])
+            .await
+            .unwrap();
+        simulator.explore(vec!["0".to_string()]).await.unwrap();
+        simulator
+            .explore(vec!["5432".to_string(), "1".to_string(), "".to_string()])
+            .await
+            .unwrap();
+
+        let report = simulator.cost_report();
+        assert_eq!(report.per_call.len(), 3);
+        assert_eq!(report.per_call.iter().sum::<usize>(), report.total_doorways);
+        assert_eq!(report.total_doorways, simulator.get_library_info().1);
+        assert!(report.distinct_rooms_discovered >= 1);
+        assert_eq!(
+            report.avg_doorways_per_room,
+            report.total_doorways as f64 / report.distinct_rooms_discovered as f64
+        );
+
+        simulator.reset_exploration();
+        let report = simulator.cost_report();
+        assert_eq!(report.total_doorways, 0);
+        assert!(report.per_call.is_empty());
+        assert_eq!(report.distinct_rooms_discovered, 0);
+    }
 }
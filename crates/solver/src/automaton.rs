@@ -0,0 +1,294 @@
+use anyhow::{bail, Result};
+use icfpc2025_common::{ObservationConvention, ObservationSet, DOORS};
+use std::collections::HashMap;
+
+use crate::reconstruct::{build_steps, has_marks, Step};
+
+/// The observations as a partial DFA: a state per observed position, a transition per door
+/// walked from it, and the label seen there.
+///
+/// Positions reached by the same doors from the start are the same room, so
+/// [`Automaton::from_observations`] starts out as a tree rooted at the starting room. Merging
+/// states that are the same room closes loops.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Automaton {
+    pub labels: Vec<usize>,
+    pub transitions: Vec<[Option<usize>; DOORS]>,
+    pub start: usize,
+}
+
+impl Automaton {
+    /// `results[i]` are the labels observed while walking `plans[i]`, including the starting
+    /// room. The plans may not mark rooms, which would give states more than one label.
+    pub fn from_observations(plans: &[String], results: &[Vec<usize>]) -> Result<Self> {
+        let steps = build_steps(plans, results, ObservationConvention::default())?;
+        if has_marks(&steps) {
+            bail!("Can't build an automaton from plans that mark rooms");
+        }
+        Ok(Self::from_steps(&steps).0)
+    }
+
+    /// The tree of `steps`, and the state of every step.
+    pub(crate) fn from_steps(steps: &[Step]) -> (Self, Vec<usize>) {
+        let mut automaton = Self {
+            labels: Vec::new(),
+            transitions: Vec::new(),
+            start: 0,
+        };
+        let mut state_of = Vec::with_capacity(steps.len());
+        let mut current = 0;
+        for step in steps {
+            current = match *step {
+                Step::Start { label, .. } => {
+                    if automaton.labels.is_empty() {
+                        automaton.add_state(label);
+                    }
+                    0
+                }
+                Step::Move { door, label } => match automaton.transitions[current][door] {
+                    Some(next) => next,
+                    None => {
+                        let next = automaton.add_state(label);
+                        automaton.transitions[current][door] = Some(next);
+                        next
+                    }
+                },
+                Step::Mark { .. } => current,
+            };
+            state_of.push(current);
+        }
+        (automaton, state_of)
+    }
+
+    fn add_state(&mut self, label: usize) -> usize {
+        self.labels.push(label);
+        self.transitions.push([None; DOORS]);
+        self.labels.len() - 1
+    }
+
+    pub fn state_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Whether some state can be reached again from itself.
+    pub fn is_cyclic(&self) -> bool {
+        // 0: unvisited, 1: on the current path, 2: done.
+        fn visit(automaton: &Automaton, state: usize, marks: &mut [u8]) -> bool {
+            match marks[state] {
+                1 => return true,
+                2 => return false,
+                _ => {}
+            }
+            marks[state] = 1;
+            let cyclic = automaton.transitions[state]
+                .iter()
+                .flatten()
+                .any(|&next| visit(automaton, next, marks));
+            marks[state] = 2;
+            cyclic
+        }
+        let mut marks = vec![0; self.state_count()];
+        (0..self.state_count()).any(|state| visit(self, state, &mut marks))
+    }
+
+    /// Partitions the states by everything observed within `depth` doors of them.
+    ///
+    /// A state is classified if every door sequence of up to `depth` doors was walked from it,
+    /// and two classified states share a class if they saw the same labels along all of those
+    /// sequences: partition refinement cut off at `depth`. States whose behaviour wasn't fully
+    /// observed are `None`.
+    ///
+    /// States in different classes are certainly different rooms. States in the same class may
+    /// not be, e.g. in a library made of two copies of a smaller one, but can't be told apart.
+    pub fn classes(&self, depth: usize) -> Vec<Option<usize>> {
+        let mut ids: HashMap<(usize, Vec<usize>), usize> = HashMap::new();
+        let mut classes: Vec<Option<usize>> = self
+            .labels
+            .iter()
+            .map(|&label| {
+                let next = ids.len();
+                Some(*ids.entry((label, Vec::new())).or_insert(next))
+            })
+            .collect();
+        for _ in 0..depth {
+            ids.clear();
+            classes = (0..self.state_count())
+                .map(|state| {
+                    let beyond = self.transitions[state]
+                        .iter()
+                        .map(|next| classes[(*next)?])
+                        .collect::<Option<Vec<usize>>>()?;
+                    let next = ids.len();
+                    Some(*ids.entry((self.labels[state], beyond)).or_insert(next))
+                })
+                .collect();
+        }
+        classes
+    }
+
+    /// The automaton with states `a` and `b` merged into one, along with every pair of states
+    /// the merge forces together by leaving a state with two transitions for the same door.
+    /// Fails if that merges states of different labels.
+    pub fn merge(&self, a: usize, b: usize) -> Result<Automaton> {
+        if a.max(b) >= self.state_count() {
+            bail!("The automaton has {} states", self.state_count());
+        }
+        let mut merger = Merger::new(self);
+        if !merger.union(a, b) {
+            bail!("States {} and {} can't be the same room", a, b);
+        }
+        Ok(merger.build())
+    }
+
+    /// Greedily merges every state into the first earlier state it can be merged with, so that
+    /// loops in the library become loops in the automaton.
+    ///
+    /// The result agrees with every observation, but merges rooms that were never seen to
+    /// differ, so it may have fewer states than the library has rooms.
+    pub fn minimize(&self) -> Automaton {
+        let mut merger = Merger::new(self);
+        for state in 0..self.state_count() {
+            if merger.find(state) != state {
+                continue;
+            }
+            for earlier in 0..state {
+                if merger.find(earlier) != earlier {
+                    continue;
+                }
+                let saved = merger.clone();
+                if merger.union(earlier, state) {
+                    break;
+                }
+                merger = saved;
+            }
+        }
+        merger.build()
+    }
+}
+
+impl TryFrom<&ObservationSet> for Automaton {
+    type Error = anyhow::Error;
+
+    fn try_from(observations: &ObservationSet) -> Result<Self> {
+        Self::from_observations(&observations.plans(), &observations.results())
+    }
+}
+
+/// Union-find over the states of an automaton, keeping the transitions of every class on its
+/// representative.
+#[derive(Clone)]
+struct Merger<'a> {
+    automaton: &'a Automaton,
+    parent: Vec<usize>,
+    transitions: Vec<[Option<usize>; DOORS]>,
+}
+
+impl<'a> Merger<'a> {
+    fn new(automaton: &'a Automaton) -> Self {
+        Self {
+            automaton,
+            parent: (0..automaton.state_count()).collect(),
+            transitions: automaton.transitions.clone(),
+        }
+    }
+
+    fn find(&mut self, state: usize) -> usize {
+        let mut root = state;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        self.parent[state] = root;
+        root
+    }
+
+    /// Merges `a` and `b` and everything that forces. Returns `false` on a label conflict,
+    /// leaving the merger in an unusable state.
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        let mut pending = vec![(a, b)];
+        while let Some((a, b)) = pending.pop() {
+            let (a, b) = (self.find(a), self.find(b));
+            if a == b {
+                continue;
+            }
+            if self.automaton.labels[a] != self.automaton.labels[b] {
+                return false;
+            }
+            let (keep, gone) = (a.min(b), a.max(b));
+            self.parent[gone] = keep;
+            for door in 0..DOORS {
+                match (self.transitions[keep][door], self.transitions[gone][door]) {
+                    (Some(x), Some(y)) => pending.push((x, y)),
+                    (None, Some(y)) => self.transitions[keep][door] = Some(y),
+                    _ => {}
+                }
+            }
+        }
+        true
+    }
+
+    /// The automaton with one state per class, numbered in order of their representatives.
+    fn build(mut self) -> Automaton {
+        let roots: Vec<usize> = (0..self.parent.len())
+            .filter(|&state| self.find(state) == state)
+            .collect();
+        let index: HashMap<usize, usize> = roots
+            .iter()
+            .enumerate()
+            .map(|(index, &root)| (root, index))
+            .collect();
+        let transitions = roots
+            .iter()
+            .map(|&root| {
+                let mut doors = [None; DOORS];
+                for (door, next) in self.transitions[root].into_iter().enumerate() {
+                    doors[door] = next.map(|next| index[&self.find(next)]);
+                }
+                doors
+            })
+            .collect();
+        let start = index[&self.find(self.automaton.start)];
+        Automaton {
+            labels: roots
+                .iter()
+                .map(|&root| self.automaton.labels[root])
+                .collect(),
+            transitions,
+            start,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use icfpc2025_common::{Observation, ObservationSet};
+
+    use super::*;
+
+    #[test]
+    fn test_loop_becomes_cycle() {
+        // Two rooms labelled 0 and 1 joined by door 0: walking it back and forth is a loop.
+        let plans = vec!["0000".to_string(), "01".to_string()];
+        let results = vec![vec![0, 1, 0, 1, 0], vec![0, 1, 1]];
+        let automaton = Automaton::from_observations(&plans, &results).unwrap();
+        assert_eq!(automaton.state_count(), 6);
+        assert!(!automaton.is_cyclic());
+
+        let minimized = automaton.minimize();
+        assert!(minimized.is_cyclic());
+        assert_eq!(minimized.state_count(), 2);
+        assert_eq!(minimized.labels, vec![0, 1]);
+        assert_eq!(minimized.transitions[0][0], Some(1));
+        assert_eq!(minimized.transitions[1][0], Some(0));
+        assert_eq!(minimized.transitions[1][1], Some(1));
+
+        // The start and the room behind door 0 have different labels.
+        assert!(automaton.merge(0, 1).is_err());
+        let merged = automaton.merge(0, 2).unwrap();
+        assert!(merged.is_cyclic());
+
+        let observations: ObservationSet = [Observation::new("0000", vec![0, 1, 0, 1, 0])]
+            .into_iter()
+            .collect();
+        assert_eq!(Automaton::try_from(&observations).unwrap().state_count(), 5);
+    }
+}
@@ -0,0 +1,119 @@
+use icfpc2025_common::SelectResponse;
+
+/// Field of the `select` response holding the doorways still available for the problem.
+pub const REMAINING_BUDGET_FIELD: &str = "remainingBudget";
+
+pub fn reported_remaining_budget(response: &SelectResponse) -> Option<usize> {
+    response
+        .data
+        .get(REMAINING_BUDGET_FIELD)
+        .and_then(|value| value.as_u64())
+        .map(|value| value as usize)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reconciliation {
+    /// The API didn't report a budget; the local record is kept as is.
+    Unreported,
+    Match,
+    /// The API disagrees with the local record, which has been corrected to the API's view.
+    Mismatch {
+        local_remaining: usize,
+        reported_remaining: usize,
+    },
+}
+
+/// Locally recorded doorway usage for the selected problem.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetTracker {
+    pub budget: usize,
+    pub used: usize,
+}
+
+impl BudgetTracker {
+    pub fn new(budget: usize) -> Self {
+        Self { budget, used: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.budget.saturating_sub(self.used)
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.remaining() == 0
+    }
+
+    pub fn record(&mut self, doorways: usize) {
+        self.used += doorways;
+    }
+
+    /// Reconciles the local record with the remaining budget reported after re-selecting a
+    /// problem. The API is the source of truth, so on mismatch the local record is corrected.
+    pub fn reconcile(&mut self, reported_remaining: Option<usize>) -> Reconciliation {
+        let Some(reported_remaining) = reported_remaining else {
+            return Reconciliation::Unreported;
+        };
+
+        let local_remaining = self.remaining();
+        if local_remaining == reported_remaining {
+            return Reconciliation::Match;
+        }
+
+        self.budget = self.budget.max(reported_remaining);
+        self.used = self.budget - reported_remaining;
+        Reconciliation::Mismatch {
+            local_remaining,
+            reported_remaining,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reported_remaining_budget() {
+        let response = SelectResponse {
+            data: serde_json::json!({ "problemName": "probatio", "remainingBudget": 12 }),
+        };
+        assert_eq!(reported_remaining_budget(&response), Some(12));
+
+        let response = SelectResponse {
+            data: serde_json::json!({ "problemName": "probatio" }),
+        };
+        assert_eq!(reported_remaining_budget(&response), None);
+    }
+
+    #[test]
+    fn test_reconcile_match_and_unreported() {
+        let mut tracker = BudgetTracker::new(54);
+        tracker.record(20);
+        assert_eq!(tracker.reconcile(None), Reconciliation::Unreported);
+        assert_eq!(tracker.reconcile(Some(34)), Reconciliation::Match);
+        assert_eq!(tracker.used, 20);
+    }
+
+    #[test]
+    fn test_reconcile_mismatch_trusts_api() {
+        // We crashed after the API counted 30 doorways but before we recorded them.
+        let mut tracker = BudgetTracker::new(54);
+        tracker.record(20);
+        assert_eq!(
+            tracker.reconcile(Some(4)),
+            Reconciliation::Mismatch {
+                local_remaining: 34,
+                reported_remaining: 4,
+            }
+        );
+        assert_eq!(tracker.used, 50);
+        assert_eq!(tracker.remaining(), 4);
+        assert!(!tracker.is_exhausted());
+
+        assert!(matches!(
+            tracker.reconcile(Some(0)),
+            Reconciliation::Mismatch { .. }
+        ));
+        assert!(tracker.is_exhausted());
+    }
+}
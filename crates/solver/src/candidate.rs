@@ -0,0 +1,491 @@
+use anyhow::{bail, Context, Result};
+use icfpc2025_common::{validate_map, EdgeSet, Map, Observation, ObservationSet, DOORS};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+
+use crate::reconstruct::verify_against_observations;
+use crate::strategy::probe_start_plan;
+
+/// Hash of `map` that doesn't depend on the order of its connections or their direction.
+pub fn canonical_hash(map: &Map) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    map.rooms.hash(&mut hasher);
+    map.starting_room.hash(&mut hasher);
+    EdgeSet::from(map).hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Picks the candidate with the smallest [`canonical_hash`], independent of the input order.
+pub fn pick_deterministic(candidates: &[Map]) -> Option<&Map> {
+    candidates.iter().min_by_key(|map| canonical_hash(map))
+}
+
+/// Fraction of doors that don't loop back into their own room. Contest libraries are
+/// 6-regular with few self-loops, so higher is more plausible.
+pub fn regularity(map: &Map) -> f64 {
+    if map.rooms.is_empty() {
+        return 0.0;
+    }
+    let self_loop_doors: usize = map
+        .connections
+        .iter()
+        .filter(|c| c.from.room == c.to.room)
+        .map(|c| if c.from.door == c.to.door { 1 } else { 2 })
+        .sum();
+    1.0 - self_loop_doors as f64 / (map.rooms.len() * DOORS) as f64
+}
+
+/// Orders candidates from most to least plausible, with their scores.
+///
+/// The score is the fraction of `observations` a candidate reproduces, typically ones
+/// [`hold_out`] kept out of the reconstruction, plus its [`regularity`] scaled to break ties without outweighing a
+/// single observation. Equal scores are ordered by [`canonical_hash`].
+pub fn rank_candidates(candidates: &[Map], observations: &ObservationSet) -> Vec<(Map, f64)> {
+    let results = observations.results();
+    let plans = observations.plans();
+    let total = plans.len();
+    let mut ranked: Vec<(Map, f64)> = candidates
+        .iter()
+        .map(|map| {
+            let agreeing = plans
+                .iter()
+                .zip(&results)
+                .filter(|(plan, labels)| {
+                    verify_against_observations(
+                        map,
+                        std::slice::from_ref(*plan),
+                        std::slice::from_ref(*labels),
+                    )
+                })
+                .count();
+            let agreement = if total == 0 {
+                0.0
+            } else {
+                agreeing as f64 / total as f64
+            };
+            let score = agreement + regularity(map) / (total + 1) as f64;
+            (map.clone(), score)
+        })
+        .collect();
+    ranked.sort_by(|(a, score_a), (b, score_b)| {
+        score_b
+            .partial_cmp(score_a)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| canonical_hash(a).cmp(&canonical_hash(b)))
+    });
+    ranked
+}
+
+/// Splits `observations` into the ones to reconstruct from and every `every`-th one, held out
+/// so that [`rank_candidates`] measures agreement on observations the candidates weren't
+/// reconstructed from. Nothing is held out of fewer than `every` observations.
+pub fn hold_out(observations: &ObservationSet, every: usize) -> (ObservationSet, ObservationSet) {
+    let (held_out, kept): (Vec<_>, Vec<_>) = observations
+        .iter()
+        .cloned()
+        .enumerate()
+        .partition(|(i, _)| every > 1 && i % every == every - 1);
+    let observations = |pairs: Vec<(usize, Observation)>| {
+        pairs
+            .into_iter()
+            .map(|(_, observation)| observation)
+            .collect()
+    };
+    (observations(kept), observations(held_out))
+}
+
+/// Shortest plan observing different labels in `a` and `b`, found by a breadth-first search over
+/// pairs of rooms, one in each map, that the same doors lead to. Only doors connected in both
+/// maps are walked. Among equally short plans, the one with the smallest doors comes first.
+///
+/// `None` if no plan tells the maps apart, e.g. because they are the same library with its
+/// rooms numbered differently.
+pub fn distinguishing_plan(a: &Map, b: &Map) -> Option<String> {
+    let (doors_a, doors_b) = (a.doors(), b.doors());
+    let start = (a.starting_room, b.starting_room);
+    let mut plans = HashMap::from([(start, String::new())]);
+    let mut queue = VecDeque::from([start]);
+    while let Some((room_a, room_b)) = queue.pop_front() {
+        let plan = plans[&(room_a, room_b)].clone();
+        if a.rooms[room_a] != b.rooms[room_b] {
+            return Some(plan);
+        }
+        for door in 0..DOORS {
+            if let (Some(next_a), Some(next_b)) = (doors_a[room_a][door], doors_b[room_b][door]) {
+                if let Entry::Vacant(entry) = plans.entry((next_a, next_b)) {
+                    entry.insert(format!("{}{}", plan, door));
+                    queue.push_back((next_a, next_b));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The labels seen from `room` through every sequence of up to two doors, `None` past an
+/// unconnected door.
+fn neighbourhood(map: &Map, doors: &[[Option<usize>; DOORS]], room: usize) -> Vec<Option<usize>> {
+    let mut seen = vec![Some(map.rooms[room])];
+    for first in doors[room] {
+        seen.push(first.map(|next| map.rooms[next]));
+        let beyond = first.map_or([None; DOORS], |next| doors[next]);
+        seen.extend(beyond.map(|second| second.map(|next| map.rooms[next])));
+    }
+    seen
+}
+
+/// Rough partial credit for guessing `guess` when the library is `truth`: the fraction of
+/// `truth`'s room labels and door targets that `guess` gets right, from 0.0 to 1.0.
+///
+/// Rooms are matched first, starting room to starting room and then greedily by how much of
+/// what is seen within two doors of them agrees, so that a wrong door only disturbs the
+/// matching near it. A room's label is right if its match has the same label, and a guessed
+/// door is right if it leads to the match of the room the same door leads to in `truth`.
+/// Guesses that are `truth` with its rooms numbered differently usually score 1.0, but rooms
+/// that look alike within two doors can be matched the wrong way round and cost some credit.
+///
+/// Fails if either map is one [`validate_map`] rejects.
+pub fn score_estimate(guess: &Map, truth: &Map) -> Result<f64> {
+    validate_map(guess).context("the guess is invalid")?;
+    validate_map(truth).context("the truth is invalid")?;
+    let total = truth.rooms.len() * (DOORS + 1);
+    let (guess_doors, truth_doors) = (guess.doors(), truth.doors());
+    let guess_seen: Vec<_> = (0..guess.rooms.len())
+        .map(|room| neighbourhood(guess, &guess_doors, room))
+        .collect();
+    let truth_seen: Vec<_> = (0..truth.rooms.len())
+        .map(|room| neighbourhood(truth, &truth_doors, room))
+        .collect();
+
+    let mut pairs: Vec<(usize, usize, usize)> = Vec::new();
+    for (room, seen) in guess_seen.iter().enumerate() {
+        for (actual, actual_seen) in truth_seen.iter().enumerate() {
+            let disagreements = seen.iter().zip(actual_seen).filter(|(a, b)| a != b).count();
+            pairs.push((disagreements, room, actual));
+        }
+    }
+    pairs.sort_unstable();
+    let mut to_truth = vec![None; guess.rooms.len()];
+    let mut matched = vec![false; truth.rooms.len()];
+    to_truth[guess.starting_room] = Some(truth.starting_room);
+    matched[truth.starting_room] = true;
+    for (_, room, actual) in pairs {
+        if to_truth[room].is_none() && !matched[actual] {
+            to_truth[room] = Some(actual);
+            matched[actual] = true;
+        }
+    }
+
+    let mut correct = 0;
+    for (room, actual) in to_truth.iter().enumerate() {
+        let Some(actual) = *actual else {
+            continue;
+        };
+        correct += usize::from(guess.rooms[room] == truth.rooms[actual]);
+        for (next, actual_next) in guess_doors[room].iter().zip(&truth_doors[actual]) {
+            let next = next.and_then(|next| to_truth[next]);
+            correct += usize::from(next.is_some() && next == *actual_next);
+        }
+    }
+    Ok(correct as f64 / total as f64)
+}
+
+/// Rooms with a path from the starting room. The real library is always connected, so a
+/// candidate where this misses some room can't be right.
+pub fn reachable_rooms(map: &Map) -> HashSet<usize> {
+    let mut neighbors = vec![Vec::new(); map.rooms.len()];
+    for conn in &map.connections {
+        neighbors[conn.from.room].push(conn.to.room);
+        neighbors[conn.to.room].push(conn.from.room);
+    }
+
+    let mut reached = HashSet::from([map.starting_room]);
+    let mut stack = vec![map.starting_room];
+    while let Some(room) = stack.pop() {
+        for &next in &neighbors[room] {
+            if reached.insert(next) {
+                stack.push(next);
+            }
+        }
+    }
+    reached
+}
+
+pub fn is_connected(map: &Map) -> bool {
+    reachable_rooms(map).len() == map.rooms.len()
+}
+
+/// The results of the [`probe_start_plan`] plans among `plans`, one per door of the starting
+/// room in door order, if every one of them was explored.
+pub fn start_room_probe(plans: &[String], results: &[Vec<usize>]) -> Option<Vec<Vec<usize>>> {
+    probe_start_plan()
+        .iter()
+        .map(|probe| {
+            plans
+                .iter()
+                .position(|plan| plan == probe)
+                .and_then(|i| results.get(i).cloned())
+        })
+        .collect()
+}
+
+/// Fails unless walking each door of `map`'s starting room observes what the
+/// [`start_room_probe`] did. One cheap comparison before a guess that rules out candidates with
+/// a wrong start room or a wrong neighbor of it.
+pub fn verify_start_room(map: &Map, probe_results: &[Vec<usize>]) -> Result<()> {
+    let probes = probe_start_plan();
+    if probe_results.len() != probes.len() {
+        bail!(
+            "Expected a probe result for each of the {} doors, got {}",
+            probes.len(),
+            probe_results.len()
+        );
+    }
+    for (door, (plan, observed)) in probes.iter().zip(probe_results).enumerate() {
+        let predicted = map.walk(plan)?;
+        if predicted != *observed {
+            bail!(
+                "Door {} of the starting room leads to {:?} in the map, but the probe observed {:?}",
+                door,
+                predicted,
+                observed
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use icfpc2025_common::{AedificiumClient, MapConnection, RoomDoor};
+    use icfpc2025_simulator::{GenerationMode, Simulator};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::{reconstruct, ReconstructOptions};
+
+    fn map(connections: Vec<((usize, usize), (usize, usize))>) -> Map {
+        Map {
+            rooms: vec![0, 1],
+            starting_room: 0,
+            connections: connections
+                .into_iter()
+                .map(|((r1, d1), (r2, d2))| MapConnection {
+                    from: RoomDoor { room: r1, door: d1 },
+                    to: RoomDoor { room: r2, door: d2 },
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_score_estimate_of_one_wrong_edge() {
+        let truth = Simulator::with_mode_seed(6, GenerationMode::Regular, 1)
+            .unwrap()
+            .get_actual_map();
+        assert_eq!(score_estimate(&truth, &truth).unwrap(), 1.0);
+        let renumbered = truth.relabel(&[0, 5, 4, 3, 2, 1]).unwrap();
+        assert_eq!(score_estimate(&renumbered, &truth).unwrap(), 1.0);
+
+        // Swapping where two connections between different rooms lead rewires four doors.
+        let mut wrong = truth.clone();
+        let between: Vec<usize> = (0..wrong.connections.len())
+            .filter(|&i| wrong.connections[i].from.room != wrong.connections[i].to.room)
+            .collect();
+        let (i, j) = (between[0], between[1]);
+        let (to_i, to_j) = (
+            wrong.connections[i].to.clone(),
+            wrong.connections[j].to.clone(),
+        );
+        wrong.connections[i].to = to_j;
+        wrong.connections[j].to = to_i;
+        let score = score_estimate(&wrong, &truth).unwrap();
+        assert!((38.0 / 42.0..1.0).contains(&score), "{}", score);
+
+        let mut mislabelled = truth.clone();
+        mislabelled.rooms[0] = (mislabelled.rooms[0] + 1) % 4;
+        assert_eq!(
+            score_estimate(&mislabelled, &truth).unwrap(),
+            1.0 - 1.0 / 42.0
+        );
+
+        let mut out_of_range = truth.clone();
+        out_of_range.starting_room = 6;
+        assert!(score_estimate(&out_of_range, &truth).is_err());
+        let mut bad_door = truth.clone();
+        bad_door.connections[0].to.door = DOORS;
+        assert!(score_estimate(&truth, &bad_door).is_err());
+    }
+
+    #[test]
+    fn test_distinguishing_plan() {
+        // Room 1 behind door 0 in one, behind door 1 in the other; every other door loops.
+        let loops = |skip: usize| {
+            let mut connections = vec![((0, skip), (1, 0))];
+            connections.extend((0..DOORS).filter(|&d| d != skip).map(|d| ((0, d), (0, d))));
+            connections.extend((1..DOORS).map(|d| ((1, d), (1, d))));
+            map(connections)
+        };
+        let (a, b) = (loops(0), loops(1));
+        assert_eq!(distinguishing_plan(&a, &b), Some("0".to_string()));
+        assert_eq!(distinguishing_plan(&b, &a), Some("0".to_string()));
+        assert_eq!(distinguishing_plan(&a, &a), None);
+
+        // The same library with the rooms numbered the other way round.
+        let mut swapped = a.clone();
+        swapped.rooms.reverse();
+        swapped.starting_room = 1;
+        for conn in &mut swapped.connections {
+            conn.from.room = 1 - conn.from.room;
+            conn.to.room = 1 - conn.to.room;
+        }
+        assert_eq!(distinguishing_plan(&a, &swapped), None);
+
+        let mut relabelled = a.clone();
+        relabelled.rooms[0] = 3;
+        assert_eq!(distinguishing_plan(&a, &relabelled), Some(String::new()));
+    }
+
+    #[test]
+    fn test_canonical_hash_ignores_order_and_direction() {
+        let a = map(vec![((0, 0), (1, 0)), ((0, 1), (1, 1))]);
+        let b = map(vec![((1, 1), (0, 1)), ((1, 0), (0, 0))]);
+        let c = map(vec![((0, 0), (1, 1)), ((0, 1), (1, 0))]);
+        assert_eq!(canonical_hash(&a), canonical_hash(&b));
+        assert_ne!(canonical_hash(&a), canonical_hash(&c));
+    }
+
+    #[test]
+    fn test_reachable_rooms_skips_isolated_room() {
+        let mut isolated = map(vec![((0, 0), (0, 1))]);
+        assert_eq!(reachable_rooms(&isolated), HashSet::from([0]));
+        assert!(!is_connected(&isolated));
+
+        isolated.connections.push(MapConnection {
+            from: RoomDoor { room: 1, door: 3 },
+            to: RoomDoor { room: 0, door: 5 },
+        });
+        assert_eq!(reachable_rooms(&isolated), HashSet::from([0, 1]));
+        assert!(is_connected(&isolated));
+    }
+
+    #[test]
+    fn test_rank_prefers_agreement_then_regularity() {
+        // Both send door 0 of room 0 to room 1, but only `agrees` sends door 1 there too.
+        let agrees = map(vec![((0, 0), (1, 0)), ((0, 1), (1, 1))]);
+        let disagrees = map(vec![((0, 0), (1, 0)), ((0, 1), (0, 1))]);
+        let held_out = ObservationSet::from_results(&["1".to_string()], &[vec![0, 1]]).unwrap();
+
+        let ranked = rank_candidates(&[disagrees.clone(), agrees.clone()], &held_out);
+        assert_eq!(canonical_hash(&ranked[0].0), canonical_hash(&agrees));
+        assert!(ranked[0].1 > ranked[1].1);
+
+        // Without observations, the candidate with fewer self-loops wins.
+        let ranked = rank_candidates(&[disagrees, agrees.clone()], &ObservationSet::new());
+        assert_eq!(canonical_hash(&ranked[0].0), canonical_hash(&agrees));
+    }
+
+    #[test]
+    fn test_hold_out_every_nth_observation() {
+        let plans: Vec<String> = (0..7).map(|door| door.to_string()).collect();
+        let results = vec![vec![0, 1]; 7];
+        let observations = ObservationSet::from_results(&plans, &results).unwrap();
+        let (kept, held_out) = hold_out(&observations, 3);
+        assert_eq!(kept.plans(), ["0", "1", "3", "4", "6"]);
+        assert_eq!(held_out.plans(), ["2", "5"]);
+
+        let (kept, held_out) = hold_out(&observations, 8);
+        assert_eq!(kept, observations);
+        assert!(held_out.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_top_ranked_candidate_agrees_with_held_out_plans() {
+        // With the truth among the candidates, the top-ranked one reproduces every held-out
+        // observation, as the truth does. Other candidates may do so too, so the truth itself
+        // is only expected to come out on top more often than not.
+        let (mut trials, mut correct) = (0, 0);
+        for seed in 0..100 {
+            let mut simulator =
+                Simulator::with_mode_seed(3, GenerationMode::Regular, seed).unwrap();
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut random_plan = |len: usize| -> String {
+                (0..len)
+                    .map(|_| char::from(b'0' + rng.random_range(0..DOORS as u8)))
+                    .collect()
+            };
+            let plans = vec![random_plan(30)];
+            let held_out = vec![random_plan(10), random_plan(10)];
+            let results = simulator.explore(plans.clone()).await.unwrap().results;
+            let held_out_results = simulator.explore(held_out.clone()).await.unwrap().results;
+
+            let options = ReconstructOptions {
+                max_candidates: 1000,
+                ..ReconstructOptions::default()
+            };
+            let reconstruction =
+                reconstruct(&plans, &results, 3, &options, &mut StdRng::seed_from_u64(0)).unwrap();
+            let diagnoses = simulator.diagnose_all(&reconstruction.candidates);
+            if !diagnoses.iter().any(|d| d.is_correct()) {
+                continue;
+            }
+
+            let observations = ObservationSet::from_results(&held_out, &held_out_results).unwrap();
+            let ranked: Vec<Map> = rank_candidates(&reconstruction.candidates, &observations)
+                .into_iter()
+                .map(|(map, _)| map)
+                .collect();
+            assert!(
+                verify_against_observations(&ranked[0], &held_out, &held_out_results),
+                "seed {}",
+                seed
+            );
+            trials += 1;
+            if simulator.guess_diagnose(&ranked[0]).is_correct() {
+                correct += 1;
+            }
+        }
+        assert!(trials >= 20, "{}", trials);
+        assert!(correct * 2 >= trials, "{} of {}", correct, trials);
+    }
+
+    #[test]
+    fn test_pick_deterministic_ignores_candidate_order() {
+        let a = map(vec![((0, 0), (1, 0))]);
+        let b = map(vec![((0, 0), (1, 1))]);
+        let forward = [a.clone(), b.clone()];
+        let backward = [b, a];
+        assert_eq!(
+            canonical_hash(pick_deterministic(&forward).unwrap()),
+            canonical_hash(pick_deterministic(&backward).unwrap())
+        );
+        assert!(pick_deterministic(&[]).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_verify_start_room_rejects_wrong_neighbor() {
+        let mut simulator = Simulator::with_mode_seed(4, GenerationMode::Regular, 2).unwrap();
+        let truth = simulator.get_actual_map();
+        let plans = [probe_start_plan(), vec!["0123".to_string()]].concat();
+        let results = simulator.explore(plans.clone()).await.unwrap().results;
+        let probe = start_room_probe(&plans, &results).unwrap();
+        assert!(verify_start_room(&truth, &probe).is_ok());
+        assert!(start_room_probe(&plans[1..], &results[1..]).is_none());
+
+        // A neighbor of the starting room with the wrong label.
+        let start = truth.starting_room;
+        let neighbor = truth.doors()[start]
+            .iter()
+            .flatten()
+            .copied()
+            .find(|&room| room != start)
+            .unwrap();
+        let mut wrong = truth.clone();
+        wrong.rooms[neighbor] = (truth.rooms[neighbor] + 1) % 4;
+        let error = verify_start_room(&wrong, &probe).unwrap_err();
+        assert!(error.to_string().contains("starting room"), "{}", error);
+    }
+}
@@ -0,0 +1,37 @@
+use anyhow::{Context, Result};
+use icfpc2025_common::Map;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// The best candidate of a solve so far, as written to [`ExploreConfig::checkpoint`].
+///
+/// [`ExploreConfig::checkpoint`]: crate::driver::ExploreConfig::checkpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub map: Map,
+    /// `None` until the map has been guessed.
+    pub correct: Option<bool>,
+    pub explores: usize,
+    pub doorways: usize,
+    pub candidate_count: usize,
+}
+
+impl Checkpoint {
+    /// Replaces the file at `path`. The new content is written next to it first, so an
+    /// interrupted write leaves the previous checkpoint intact.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {}", Path::new(&tmp).display()))?;
+        fs::rename(&tmp, path).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&json)
+            .with_context(|| format!("invalid checkpoint {}", path.display()))
+    }
+}
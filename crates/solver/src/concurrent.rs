@@ -0,0 +1,141 @@
+use anyhow::{anyhow, bail, Result};
+use icfpc2025_common::AedificiumClient;
+use rand::rngs::StdRng;
+use std::collections::HashSet;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+use crate::driver::{solve, ExploreConfig, SolveReport};
+use crate::strategy::ExploreStrategy;
+
+/// One problem to solve on a client of its own.
+///
+/// The API keeps a single selected problem per team, so every job needs its own `session`
+/// (typically the team id the client authenticates as).
+pub struct ProblemJob<C> {
+    pub session: String,
+    pub problem: String,
+    pub client: C,
+    pub config: ExploreConfig,
+    pub rng: StdRng,
+}
+
+/// The outcome of one [`ProblemJob`].
+#[derive(Debug)]
+pub struct ProblemOutcome {
+    pub session: String,
+    pub problem: String,
+    pub report: Result<SolveReport>,
+}
+
+/// Selects and solves every job's problem, running at most `concurrency` jobs at a time.
+///
+/// Fails before starting anything if two jobs share a session. Otherwise returns one outcome
+/// per job, in job order; a failing job does not stop the others. Requests are only limited
+/// by the clients themselves, so clients sharing a server-side limit should share a rate
+/// limiter too.
+pub async fn solve_concurrently<C, S>(
+    jobs: Vec<ProblemJob<C>>,
+    concurrency: usize,
+    strategy: impl Fn() -> S,
+) -> Result<Vec<ProblemOutcome>>
+where
+    C: AedificiumClient + 'static,
+    S: ExploreStrategy + Send + 'static,
+{
+    if concurrency == 0 {
+        bail!("concurrency must be positive");
+    }
+    let mut sessions = HashSet::new();
+    for job in &jobs {
+        if !sessions.insert(job.session.as_str()) {
+            bail!(
+                "session {} is used by more than one problem; each concurrent problem needs its own session",
+                job.session
+            );
+        }
+    }
+
+    let permits = Arc::new(Semaphore::new(concurrency));
+    let tasks: Vec<_> = jobs
+        .into_iter()
+        .map(|mut job| {
+            let permits = Arc::clone(&permits);
+            let mut strategy = strategy();
+            let names = (job.session.clone(), job.problem.clone());
+            let handle = tokio::spawn(async move {
+                let _permit = permits.acquire_owned().await?;
+                job.client.select(job.problem.clone()).await?;
+                solve(&mut job.client, &mut strategy, &job.config, &mut job.rng).await
+            });
+            (names, handle)
+        })
+        .collect();
+
+    let mut outcomes = Vec::with_capacity(tasks.len());
+    for ((session, problem), handle) in tasks {
+        let report = handle
+            .await
+            .unwrap_or_else(|e| Err(anyhow!("solve task failed: {}", e)));
+        outcomes.push(ProblemOutcome {
+            session,
+            problem,
+            report,
+        });
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use icfpc2025_simulator::{GenerationMode, Simulator};
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::strategy::Frontier;
+
+    fn frontier() -> Frontier {
+        Frontier {
+            plan_len: 4,
+            plans_per_call: 1,
+        }
+    }
+
+    fn job(session: &str, problem: &str) -> ProblemJob<Simulator> {
+        let map = Simulator::with_mode_seed(3, GenerationMode::Regular, 3)
+            .unwrap()
+            .get_actual_map();
+        let client = Simulator::from_map(&map).unwrap();
+        ProblemJob {
+            session: session.to_string(),
+            problem: problem.to_string(),
+            config: ExploreConfig::new(3, client.remaining_doorways()),
+            client,
+            rng: StdRng::seed_from_u64(0),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_solve_two_problems_concurrently() {
+        let jobs = vec![job("team-a", "probatio"), job("team-b", "primus")];
+        let outcomes = solve_concurrently(jobs, 2, frontier).await.unwrap();
+
+        assert_eq!(outcomes.len(), 2);
+        assert_eq!(outcomes[0].problem, "probatio");
+        assert_eq!(outcomes[1].session, "team-b");
+        for outcome in outcomes {
+            assert!(outcome.report.unwrap().correct);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_solve_concurrently_requires_distinct_sessions() {
+        let jobs = vec![job("team-a", "probatio"), job("team-a", "primus")];
+        assert!(solve_concurrently(jobs, 2, frontier).await.is_err());
+        assert!(
+            solve_concurrently(vec![job("team-a", "probatio")], 0, frontier)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+use std::fmt;
+
+/// Two plans that share a prefix but observed different labels along it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inconsistency {
+    pub first_plan: String,
+    pub second_plan: String,
+    /// Index into the observed labels where the two plans disagree. Index 0 is the starting room.
+    pub index: usize,
+    pub first_label: usize,
+    pub second_label: usize,
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "plans {:?} and {:?} share a prefix but observed labels {} and {} at index {}",
+            self.first_plan, self.second_plan, self.first_label, self.second_label, self.index
+        )
+    }
+}
+
+impl std::error::Error for Inconsistency {}
+
+/// Checks that plans sharing a prefix observed the same labels along that prefix.
+///
+/// The library is deterministic, so any disagreement means the observations were recorded or
+/// attributed wrongly (or the library changed under us). Reports the first disagreement found.
+pub fn check_consistency(observations: &[(String, Vec<usize>)]) -> Result<(), Inconsistency> {
+    // For every prefix seen so far, the observation that first walked it and the label it saw.
+    let mut seen: HashMap<&str, (usize, usize)> = HashMap::new();
+
+    for (index, (plan, labels)) in observations.iter().enumerate() {
+        for (depth, &label) in labels.iter().enumerate().take(plan.len() + 1) {
+            let Some(prefix) = plan.get(..depth) else {
+                break;
+            };
+            match seen.get(prefix) {
+                Some(&(other, other_label)) if other_label != label => {
+                    return Err(Inconsistency {
+                        first_plan: observations[other].0.clone(),
+                        second_plan: plan.clone(),
+                        index: depth,
+                        first_label: other_label,
+                        second_label: label,
+                    });
+                }
+                Some(_) => {}
+                None => {
+                    seen.insert(prefix, (index, label));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observation(plan: &str, labels: &[usize]) -> (String, Vec<usize>) {
+        (plan.to_string(), labels.to_vec())
+    }
+
+    #[test]
+    fn test_consistent_observations() {
+        let observations = vec![
+            observation("012", &[0, 1, 2, 3]),
+            observation("013", &[0, 1, 2, 0]),
+            observation("01", &[0, 1, 2]),
+            observation("5", &[0, 3]),
+        ];
+        assert_eq!(check_consistency(&observations), Ok(()));
+    }
+
+    #[test]
+    fn test_inconsistent_prefix_is_reported() {
+        let observations = vec![
+            observation("012", &[0, 1, 2, 3]),
+            observation("0145", &[0, 1, 3, 0, 0]),
+        ];
+        assert_eq!(
+            check_consistency(&observations),
+            Err(Inconsistency {
+                first_plan: "012".to_string(),
+                second_plan: "0145".to_string(),
+                index: 2,
+                first_label: 2,
+                second_label: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn test_inconsistent_starting_room() {
+        let observations = vec![observation("0", &[0, 1]), observation("1", &[2, 1])];
+        let inconsistency = check_consistency(&observations).unwrap_err();
+        assert_eq!(inconsistency.index, 0);
+    }
+}
@@ -0,0 +1,1547 @@
+use anyhow::{anyhow, bail, Result};
+use icfpc2025_common::{
+    max_plan_len, truncate_plan, AedificiumClient, ExploreResponse, Map, ObservationConvention,
+    ObservationSet, PrefixPlans,
+};
+use rand::Rng;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::candidate::{distinguishing_plan, rank_candidates, start_room_probe, verify_start_room};
+use crate::checkpoint::Checkpoint;
+use crate::exact::{reconstruct_exact, EXACT_MAX_ROOMS};
+use crate::partial::PartialMap;
+use crate::reconstruct::{reconstruct, ReconstructOptions, Reconstruction};
+use crate::recorder::{RunLog, RunRecorder};
+use crate::shutdown::{Interrupted, Shutdown};
+use crate::strategy::ExploreStrategy;
+use crate::tuner::BatchTuner;
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatchConfig {
+    /// Number of plans sent per explore call.
+    pub batch_size: usize,
+    /// Maximum doorways a single explore call may use, if the caller has such a limit.
+    pub max_doorways_per_call: Option<usize>,
+    /// Don't send plans that are a prefix of another plan, and cut their labels out of the
+    /// longer plan's instead; see [`PrefixPlans`].
+    pub share_prefixes: bool,
+    /// Whether the client's results include the room a plan ends in, which decides where
+    /// shared plans' labels are cut.
+    pub convention: ObservationConvention,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 8,
+            max_doorways_per_call: None,
+            share_prefixes: false,
+            convention: ObservationConvention::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchStats {
+    pub http_calls: usize,
+    /// Doorways used by the plans sent, counting the starting room observation of each plan. A
+    /// plan cut short by a truncated call counts in full, though only the part of it walked
+    /// before the budget ran out was charged.
+    pub doorways: usize,
+    /// `queryCount` reported by the last explore response.
+    pub query_count: usize,
+}
+
+/// Doorways used by walking `plan`, including the starting room observation (as charged by the
+/// simulator by default).
+pub fn plan_cost(plan: &str) -> usize {
+    plan.len() + 1
+}
+
+/// Fits `plans` into `remaining` doorways, counted like [`plan_cost`]: plans that fit are kept
+/// in order, the first one that doesn't is cut down to the steps that still fit, and the rest
+/// are dropped. A charcoal mark is never split; see [`truncate_plan`]. Returns how many plans
+/// were cut short or dropped. Fails only if not even a one-door plan fits, so a batch near the
+/// end of the budget is trimmed rather than refused.
+pub fn fit_to_budget(plans: &mut Vec<String>, remaining: usize) -> Result<usize> {
+    if remaining < plan_cost("0") {
+        bail!(
+            "Only {} doorways left, not enough for a single door",
+            remaining
+        );
+    }
+    let mut remaining = remaining;
+    let mut kept = 0;
+    let mut trimmed = 0;
+    for plan in plans.iter_mut() {
+        if remaining < plan_cost("0") {
+            break;
+        }
+        if plan_cost(plan) > remaining {
+            truncate_plan(plan, remaining - plan_cost(""));
+            trimmed += 1;
+        }
+        remaining -= plan_cost(plan);
+        kept += 1;
+    }
+    trimmed += plans.len() - kept;
+    plans.truncate(kept);
+    Ok(trimmed)
+}
+
+/// Explores `plans` in batches of `config.batch_size` plans per call and returns one result per
+/// plan, in order. Every batch is checked against `config.max_doorways_per_call` before anything
+/// is sent.
+///
+/// If a call comes back truncated, no further batches are sent and the results cover only the
+/// plans completed so far. A call answering more plans than it was sent is an error.
+pub async fn explore_batched<C: AedificiumClient + ?Sized>(
+    client: &mut C,
+    plans: &[String],
+    config: &BatchConfig,
+) -> Result<(Vec<Vec<usize>>, BatchStats)> {
+    if config.batch_size == 0 {
+        bail!("Batch size must be at least 1");
+    }
+    let shared = config
+        .share_prefixes
+        .then(|| PrefixPlans::new(plans))
+        .transpose()?;
+    let plans = shared.as_ref().map_or(plans, |shared| &shared.plans[..]);
+
+    let batches: Vec<&[String]> = plans.chunks(config.batch_size).collect();
+    if let Some(limit) = config.max_doorways_per_call {
+        for (index, batch) in batches.iter().enumerate() {
+            let cost: usize = batch.iter().map(|plan| plan_cost(plan)).sum();
+            if cost > limit {
+                bail!(
+                    "Batch {} needs {} doorways, more than the {} allowed per call",
+                    index,
+                    cost,
+                    limit
+                );
+            }
+        }
+    }
+
+    let mut results = Vec::with_capacity(plans.len());
+    let mut stats = BatchStats::default();
+    for batch in batches {
+        let response = client.explore(batch.to_vec()).await?;
+        stats.http_calls += 1;
+        if response.results.len() > batch.len() {
+            bail!(
+                "Sent {} plans but got {} results",
+                batch.len(),
+                response.results.len()
+            );
+        }
+        // The plan a truncated call was cut short in was walked up to there, and those
+        // doorways are spent too.
+        let walked = if response.truncated {
+            (response.results.len() + 1).min(batch.len())
+        } else {
+            response.results.len()
+        };
+        stats.doorways += batch[..walked]
+            .iter()
+            .map(|plan| plan_cost(plan))
+            .sum::<usize>();
+        stats.query_count = response.total_query_count();
+        results.extend(response.results);
+        if response.truncated {
+            break;
+        }
+    }
+
+    if let Some(shared) = shared {
+        results = shared.expand(&results, config.convention)?;
+    }
+    Ok((results, stats))
+}
+
+/// When [`explore_until`] stops exploring, besides running out of budget or time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopCondition {
+    /// Every door of the reconstructed map has been observed.
+    FullCoverage,
+    /// At least this fraction of the doorway budget has been used.
+    BudgetFraction(f64),
+    /// The observations admit exactly one map, and every door of it has been walked through.
+    /// Doors nobody walked through are only guessed when completing a candidate, so a single
+    /// candidate alone doesn't pin down the map.
+    UniqueReconstruction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    ConditionMet,
+    BudgetExhausted,
+    TimeLimit,
+    /// [`ExploreConfig::shutdown`] was requested.
+    Shutdown,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExploreConfig {
+    pub room_count: usize,
+    /// Doorways available, counted like [`plan_cost`].
+    pub budget: usize,
+    /// Plans from the strategy are cut down to this many doors.
+    pub max_plan_len: usize,
+    pub time_limit: Option<Duration>,
+    pub reconstruct: ReconstructOptions,
+    /// File kept up to date with the best-ranked candidate after every reconstruction, and with
+    /// the guess result once [`solve`] has guessed. Whatever happens to the run, the latest
+    /// best candidate is on disk.
+    pub checkpoint: Option<PathBuf>,
+    /// Where [`solve`] writes a [`crate::RunReport`] of the whole run once it is over, whether
+    /// or not it succeeded.
+    pub run_log: Option<RunLog>,
+    /// Let a [`BatchTuner`] decide how many plans [`explore_until`] sends per call, drawing as
+    /// many as it asks for from the strategy, instead of sending one call per
+    /// [`ExploreStrategy::next_plans`].
+    pub auto_batch: bool,
+    /// Once requested, exploring stops after the call in flight, whose doorways are spent
+    /// anyway, and [`solve`] goes straight to guessing.
+    pub shutdown: Shutdown,
+    /// Whether [`solve`] still guesses the best-ranked candidate after a shutdown, rather than
+    /// failing with [`Interrupted`].
+    pub guess_on_shutdown: bool,
+}
+
+impl ExploreConfig {
+    /// No time limit, the API's plan length limit and default reconstruction options.
+    pub fn new(room_count: usize, budget: usize) -> Self {
+        Self {
+            room_count,
+            budget,
+            max_plan_len: max_plan_len(room_count),
+            time_limit: None,
+            reconstruct: ReconstructOptions::default(),
+            checkpoint: None,
+            run_log: None,
+            auto_batch: false,
+            shutdown: Shutdown::new(),
+            guess_on_shutdown: true,
+        }
+    }
+
+    /// Like [`ExploreConfig::new`], with the reconstruction backend picked by
+    /// [`Backend::for_rooms`]: refinement to [`AUTO_REFINE_DEPTH`] doors from
+    /// [`REFINEMENT_MIN_ROOMS`] rooms up.
+    pub fn auto(room_count: usize, budget: usize) -> Self {
+        let mut config = Self::new(room_count, budget);
+        if Backend::for_rooms(room_count) == Backend::Refinement {
+            config.reconstruct.refine_depth = Some(AUTO_REFINE_DEPTH);
+        }
+        config
+    }
+
+    /// The backend [`ExploreOutcome::record`] reconstructs with under this config.
+    pub fn backend(&self) -> Backend {
+        if self.reconstruct.refine_depth.is_some() {
+            Backend::Refinement
+        } else if self.room_count <= EXACT_MAX_ROOMS {
+            Backend::Exact
+        } else {
+            Backend::Search
+        }
+    }
+}
+
+/// Libraries with at least this many rooms are reconstructed by partition refinement under
+/// [`ExploreConfig::auto`]. That covers quartus, quintus and the larger aleph-series
+/// problems; below it the backtracking search still finishes.
+pub const REFINEMENT_MIN_ROOMS: usize = 24;
+
+/// Refinement depth [`ExploreConfig::auto`] uses for large libraries.
+pub const AUTO_REFINE_DEPTH: usize = 3;
+
+/// How observations are turned into candidate maps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// [`reconstruct_exact`], for libraries of up to [`EXACT_MAX_ROOMS`] rooms.
+    Exact,
+    /// The backtracking [`reconstruct`] over raw rooms.
+    Search,
+    /// [`reconstruct`] over classes of positions, with
+    /// [`ReconstructOptions::refine_depth`] set.
+    Refinement,
+}
+
+impl Backend {
+    /// Exact enumeration up to [`EXACT_MAX_ROOMS`] rooms, refinement from
+    /// [`REFINEMENT_MIN_ROOMS`] rooms and the search in between.
+    pub fn for_rooms(room_count: usize) -> Self {
+        if room_count <= EXACT_MAX_ROOMS {
+            Backend::Exact
+        } else if room_count < REFINEMENT_MIN_ROOMS {
+            Backend::Search
+        } else {
+            Backend::Refinement
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ExploreOutcome {
+    pub plans: Vec<String>,
+    pub results: Vec<Vec<usize>>,
+    /// Reconstruction from all observations, if anything was explored.
+    pub reconstruction: Option<Reconstruction>,
+    pub explore_calls: usize,
+    /// Wall time of every explore call, in order.
+    pub explore_latencies: Vec<Duration>,
+    pub doorways: usize,
+    pub stop_reason: StopReason,
+}
+
+impl ExploreOutcome {
+    fn new() -> Self {
+        Self {
+            plans: Vec::new(),
+            results: Vec::new(),
+            reconstruction: None,
+            explore_calls: 0,
+            explore_latencies: Vec::new(),
+            doorways: 0,
+            stop_reason: StopReason::BudgetExhausted,
+        }
+    }
+
+    /// Observed part of the first candidate map.
+    pub fn partial_map(&self) -> Option<&PartialMap> {
+        self.reconstruction.as_ref()?.partial_maps.first()
+    }
+
+    fn candidates(&self) -> &[Map] {
+        self.reconstruction
+            .as_ref()
+            .map_or(&[][..], |r| &r.candidates[..])
+    }
+
+    /// The candidate ranked first by [`rank_candidates`] against everything observed. Nothing
+    /// is held out of the reconstruction here, so the candidates normally all agree with it and
+    /// [`crate::regularity`] decides.
+    pub fn best_candidate(&self) -> Result<Option<Map>> {
+        let observations = ObservationSet::from_results(&self.plans, &self.results)?;
+        Ok(rank_candidates(self.candidates(), &observations)
+            .into_iter()
+            .next()
+            .map(|(map, _)| map))
+    }
+
+    /// The best-ranked candidate that reproduces the start-room probe, if the
+    /// [`crate::probe_start_plan`] plans were explored: a last check before guessing that
+    /// rejects candidates with a wrong starting room or a wrong neighbor of it. Doors the exact
+    /// backend completes on its own aren't backed by any observation, so they can get this wrong.
+    fn guessable_candidate(&self, room_count: usize) -> Result<Map> {
+        let observations = ObservationSet::from_results(&self.plans, &self.results)?;
+        let ranked = rank_candidates(self.candidates(), &observations);
+        if ranked.is_empty() {
+            bail!("No map with {} rooms matches the observations", room_count);
+        }
+        let probe = start_room_probe(&self.plans, &self.results);
+        let total = ranked.len();
+        ranked
+            .into_iter()
+            .map(|(map, _)| map)
+            .find(|map| {
+                probe
+                    .as_ref()
+                    .is_none_or(|probe| verify_start_room(map, probe).is_ok())
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "None of the {} candidates reproduces the start-room probe",
+                    total
+                )
+            })
+    }
+
+    /// Drops the plans `response` has no results for; whatever was cut off is lost. The plan a
+    /// truncated call was cut short in was walked up to there, so its doorways count as spent,
+    /// in full as in [`BatchStats::doorways`].
+    fn keep_answered(&mut self, plans: &mut Vec<String>, response: &ExploreResponse) {
+        let answered = response.results.len().min(plans.len());
+        if response.truncated {
+            if let Some(cut_short) = plans.get(answered) {
+                self.doorways += plan_cost(cut_short);
+            }
+        }
+        plans.truncate(answered);
+    }
+
+    /// Adds an explore call's plans and their results, and reconstructs from everything
+    /// observed so far. The doorways left of `config.budget` go to the
+    /// `aedificium_doorways_remaining` gauge of the `metrics` facade.
+    fn record(
+        &mut self,
+        plans: Vec<String>,
+        results: Vec<Vec<usize>>,
+        elapsed: Duration,
+        config: &ExploreConfig,
+        rng: &mut impl Rng,
+    ) -> Result<()> {
+        self.explore_calls += 1;
+        self.explore_latencies.push(elapsed);
+        self.doorways += plans.iter().map(|plan| plan_cost(plan)).sum::<usize>();
+        metrics::gauge!("aedificium_doorways_remaining")
+            .set(config.budget.saturating_sub(self.doorways) as f64);
+        self.plans.extend(plans);
+        self.results.extend(results);
+        // Tiny libraries are cheap to search exactly, which never mistakes an open door for a
+        // unique answer. Refinement has its own way of completing maps.
+        let mut reconstruction = if config.backend() == Backend::Exact {
+            reconstruct_exact(
+                &self.plans,
+                &self.results,
+                config.room_count,
+                &config.reconstruct,
+                rng,
+            )?
+        } else {
+            reconstruct(
+                &self.plans,
+                &self.results,
+                config.room_count,
+                &config.reconstruct,
+                rng,
+            )?
+        };
+        reconstruction.retain_connected();
+        self.reconstruction = Some(reconstruction);
+        if let Some(path) = &config.checkpoint {
+            if let Some(map) = self.best_candidate()? {
+                self.save_checkpoint(map, None, path);
+            }
+        }
+        Ok(())
+    }
+
+    /// Saves a [`Checkpoint`] to `path`. A checkpoint that can't be written is reported and
+    /// skipped: it's a safety net, not a reason to give up on the run.
+    fn save_checkpoint(&self, map: Map, correct: Option<bool>, path: &Path) {
+        if let Err(e) = self.checkpoint(map, correct).save(path) {
+            eprintln!("Failed to save a checkpoint to {}: {:#}", path.display(), e);
+        }
+    }
+
+    fn checkpoint(&self, map: Map, correct: Option<bool>) -> Checkpoint {
+        Checkpoint {
+            map,
+            correct,
+            explores: self.explore_calls,
+            doorways: self.doorways,
+            candidate_count: self.candidates().len(),
+        }
+    }
+}
+
+fn condition_met(stop: StopCondition, outcome: &ExploreOutcome, budget: usize) -> bool {
+    match stop {
+        StopCondition::FullCoverage => outcome
+            .partial_map()
+            .is_some_and(|partial| partial.coverage() >= 1.0),
+        StopCondition::BudgetFraction(fraction) => {
+            outcome.doorways as f64 >= fraction * budget as f64
+        }
+        StopCondition::UniqueReconstruction => outcome.reconstruction.as_ref().is_some_and(|r| {
+            r.exhaustive
+                && r.candidates.len() == 1
+                && r.partial_maps.first().is_some_and(|p| p.coverage() >= 1.0)
+        }),
+    }
+}
+
+/// Keeps exploring plans from `strategy` and reconstructing from everything observed so far,
+/// until `stop` holds or the budget or time limit runs out.
+pub async fn explore_until<C, S>(
+    client: &mut C,
+    strategy: &mut S,
+    stop: StopCondition,
+    config: &ExploreConfig,
+    rng: &mut impl Rng,
+) -> Result<ExploreOutcome>
+where
+    C: AedificiumClient + ?Sized,
+    S: ExploreStrategy + ?Sized,
+{
+    let mut outcome = ExploreOutcome::new();
+    explore_into(client, strategy, stop, config, rng, &mut outcome).await?;
+    Ok(outcome)
+}
+
+/// [`explore_until`], adding to `outcome` as it goes so that whatever was explored before an
+/// error is kept.
+async fn explore_into<C, S>(
+    client: &mut C,
+    strategy: &mut S,
+    stop: StopCondition,
+    config: &ExploreConfig,
+    rng: &mut impl Rng,
+    outcome: &mut ExploreOutcome,
+) -> Result<()>
+where
+    C: AedificiumClient + ?Sized,
+    S: ExploreStrategy + ?Sized,
+{
+    let started = Instant::now();
+    let mut tuner = config
+        .auto_batch
+        .then(|| BatchTuner::new(config.budget / plan_cost("0")));
+
+    loop {
+        if config
+            .time_limit
+            .is_some_and(|limit| started.elapsed() >= limit)
+        {
+            outcome.stop_reason = StopReason::TimeLimit;
+            return Ok(());
+        }
+        if config.shutdown.is_requested() {
+            outcome.stop_reason = StopReason::Shutdown;
+            return Ok(());
+        }
+
+        let mut plans = strategy.next_plans(outcome.partial_map(), rng);
+        if let Some(tuner) = &tuner {
+            while !plans.is_empty() && plans.len() < tuner.batch_size() {
+                let more = strategy.next_plans(outcome.partial_map(), rng);
+                if more.is_empty() {
+                    break;
+                }
+                plans.extend(more);
+            }
+            plans.truncate(tuner.batch_size());
+        }
+        for plan in &mut plans {
+            truncate_plan(plan, config.max_plan_len);
+        }
+        // Plans that don't fit whole are dropped rather than trimmed: the doorways left over are
+        // better spent by distinguish_candidates, and after that by spend_remaining.
+        let mut remaining = config.budget.saturating_sub(outcome.doorways);
+        plans.retain(|plan| {
+            let fits = plan_cost(plan) <= remaining;
+            if fits {
+                remaining -= plan_cost(plan);
+            }
+            fits
+        });
+        if plans.is_empty() {
+            outcome.stop_reason = StopReason::BudgetExhausted;
+            return Ok(());
+        }
+
+        let sent = Instant::now();
+        let response = client.explore(plans.clone()).await?;
+        let elapsed = sent.elapsed();
+        outcome.keep_answered(&mut plans, &response);
+        if let Some(tuner) = &mut tuner {
+            let doorways = plans.iter().map(|plan| plan_cost(plan)).sum();
+            tuner.record(plans.len(), doorways, elapsed);
+        }
+        outcome.record(plans, response.results, elapsed, config, rng)?;
+
+        if condition_met(stop, outcome, config.budget) {
+            outcome.stop_reason = StopReason::ConditionMet;
+            return Ok(());
+        }
+        if response.truncated {
+            outcome.stop_reason = StopReason::BudgetExhausted;
+            return Ok(());
+        }
+    }
+}
+
+/// While several candidates are left, explores the [`distinguishing_plan`] between the
+/// best-ranked candidate and the next one it can be told apart from, as long as the plan fits
+/// the budget. Each of these explores rules out at least one of the two, which is a better
+/// use of the remaining doorways than guessing between them.
+pub async fn distinguish_candidates<C>(
+    client: &mut C,
+    outcome: &mut ExploreOutcome,
+    config: &ExploreConfig,
+    rng: &mut impl Rng,
+) -> Result<()>
+where
+    C: AedificiumClient + ?Sized,
+{
+    // Candidates may come and go between reconstructions; don't chase them forever.
+    for _ in 0..outcome.candidates().len() {
+        if outcome.candidates().len() < 2 || config.shutdown.is_requested() {
+            break;
+        }
+        let observations = ObservationSet::from_results(&outcome.plans, &outcome.results)?;
+        let ranked = rank_candidates(outcome.candidates(), &observations);
+        let Some(plan) = ranked[1..]
+            .iter()
+            .find_map(|(other, _)| distinguishing_plan(&ranked[0].0, other))
+        else {
+            break;
+        };
+        if plan.len() > config.max_plan_len || outcome.doorways + plan_cost(&plan) > config.budget {
+            break;
+        }
+
+        let sent = Instant::now();
+        let response = client.explore(vec![plan.clone()]).await?;
+        let elapsed = sent.elapsed();
+        if response.results.is_empty() {
+            break;
+        }
+        outcome.record(vec![plan], response.results, elapsed, config, rng)?;
+    }
+    Ok(())
+}
+
+/// While several candidates are left, explores one batch from `strategy` cut down with
+/// [`fit_to_budget`] to the doorways still left, which would otherwise go unused. Meant for
+/// after [`distinguish_candidates`], which only sends plans that fit whole.
+async fn spend_remaining<C, S>(
+    client: &mut C,
+    strategy: &mut S,
+    outcome: &mut ExploreOutcome,
+    config: &ExploreConfig,
+    rng: &mut impl Rng,
+) -> Result<()>
+where
+    C: AedificiumClient + ?Sized,
+    S: ExploreStrategy + ?Sized,
+{
+    let remaining = config.budget.saturating_sub(outcome.doorways);
+    if outcome.candidates().len() < 2
+        || remaining < plan_cost("0")
+        || config.shutdown.is_requested()
+    {
+        return Ok(());
+    }
+    let mut plans = strategy.next_plans(outcome.partial_map(), rng);
+    for plan in &mut plans {
+        truncate_plan(plan, config.max_plan_len);
+    }
+    let trimmed = fit_to_budget(&mut plans, remaining)?;
+    if plans.is_empty() {
+        return Ok(());
+    }
+    if trimmed > 0 {
+        eprintln!(
+            "Trimmed {} plans to fit the {} doorways left",
+            trimmed, remaining
+        );
+    }
+
+    let sent = Instant::now();
+    let response = client.explore(plans.clone()).await?;
+    let elapsed = sent.elapsed();
+    outcome.keep_answered(&mut plans, &response);
+    outcome.record(plans, response.results, elapsed, config, rng)
+}
+
+/// How big a guess is, checked by [`solve`] before submitting it. Should the API ever limit the
+/// size of a guess, this is where to reject or split it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GuessSize {
+    pub rooms: usize,
+    pub edges: usize,
+    /// Length of the map as JSON, without the rest of the request.
+    pub bytes: usize,
+}
+
+impl GuessSize {
+    pub fn of(map: &Map) -> Result<Self> {
+        Ok(Self {
+            rooms: map.rooms.len(),
+            edges: map.edge_count(),
+            bytes: serde_json::to_vec(map)?.len(),
+        })
+    }
+}
+
+/// What [`solve`] did, for archiving alongside the guessed map.
+#[derive(Debug, Clone, Serialize)]
+pub struct SolveReport {
+    pub map: Map,
+    pub correct: bool,
+    pub explores: usize,
+    pub doorways: usize,
+    /// Candidates left by the final reconstruction.
+    pub candidate_count: usize,
+    /// Whether the observations determined the map, as for
+    /// [`StopCondition::UniqueReconstruction`], rather than it being picked among candidates.
+    pub unique: bool,
+    pub guess_size: GuessSize,
+    /// Whether the run was shut down early and guessed with what it had.
+    pub interrupted: bool,
+}
+
+/// Explores until only one map fits the observations (or the budget runs out), spends what is
+/// left of the budget on [`distinguish_candidates`] and guesses the best-ranked candidate.
+///
+/// A requested [`ExploreConfig::shutdown`] cuts the exploring short; the best-ranked candidate
+/// so far is guessed all the same unless [`ExploreConfig::guess_on_shutdown`] is off.
+pub async fn solve<C, S>(
+    client: &mut C,
+    strategy: &mut S,
+    config: &ExploreConfig,
+    rng: &mut impl Rng,
+) -> Result<SolveReport>
+where
+    C: AedificiumClient + ?Sized,
+    S: ExploreStrategy + ?Sized,
+{
+    let Some(log) = &config.run_log else {
+        return solve_recorded(client, strategy, config, rng, None).await;
+    };
+    let mut recorder = RunRecorder::start(log, config.room_count);
+    let result = solve_recorded(client, strategy, config, rng, Some(&mut recorder)).await;
+    if let Err(e) = recorder.finish(result.as_ref().err()) {
+        eprintln!("Failed to write the run report: {:#}", e);
+    }
+    result
+}
+
+async fn solve_recorded<C, S>(
+    client: &mut C,
+    strategy: &mut S,
+    config: &ExploreConfig,
+    rng: &mut impl Rng,
+    mut recorder: Option<&mut RunRecorder>,
+) -> Result<SolveReport>
+where
+    C: AedificiumClient + ?Sized,
+    S: ExploreStrategy + ?Sized,
+{
+    let stop = StopCondition::UniqueReconstruction;
+    let mut outcome = ExploreOutcome::new();
+    let mut explored = explore_into(client, strategy, stop, config, rng, &mut outcome).await;
+    if explored.is_ok() {
+        explored = distinguish_candidates(client, &mut outcome, config, rng).await;
+    }
+    if explored.is_ok() {
+        explored = spend_remaining(client, strategy, &mut outcome, config, rng).await;
+    }
+    // The observations are worth keeping even if exploring failed halfway.
+    if let Some(recorder) = recorder.as_deref_mut() {
+        recorder.explored(&outcome)?;
+    }
+    explored?;
+    let interrupted = config.shutdown.is_requested();
+    if interrupted && !config.guess_on_shutdown {
+        return Err(Interrupted {
+            explores: outcome.explore_calls,
+            doorways: outcome.doorways,
+        }
+        .into());
+    }
+
+    let map = outcome
+        .guessable_candidate(config.room_count)?
+        .canonicalize()?;
+    let guess_size = GuessSize::of(&map)?;
+    let sent = Instant::now();
+    let response = client.guess(map.clone()).await?;
+    if let Some(recorder) = recorder {
+        recorder.guessed(&map, response.correct, sent.elapsed());
+    }
+    if let Some(path) = &config.checkpoint {
+        outcome.save_checkpoint(map.clone(), Some(response.correct), path);
+    }
+
+    Ok(SolveReport {
+        map,
+        correct: response.correct,
+        explores: outcome.explore_calls,
+        doorways: outcome.doorways,
+        candidate_count: outcome.candidates().len(),
+        unique: condition_met(stop, &outcome, config.budget),
+        guess_size,
+        interrupted,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use icfpc2025_simulator::{GenerationMode, Simulator};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::candidate::canonical_hash;
+    use crate::reconstruct::verify_against_observations;
+    use crate::recorder::RunReport;
+    use crate::strategy::{Frontier, ProbeStartFirst};
+    use crate::testing::assert_reconstructs;
+
+    fn plans() -> Vec<String> {
+        ["0", "12", "345", "0", "5", "44", "3"]
+            .iter()
+            .map(|p| p.to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_explore_batched_splits_calls() {
+        let mut simulator = Simulator::with_seed(6, 1).unwrap();
+        let config = BatchConfig {
+            batch_size: 3,
+            ..BatchConfig::default()
+        };
+        let (results, stats) = explore_batched(&mut simulator, &plans(), &config)
+            .await
+            .unwrap();
+
+        assert_eq!(results.len(), 7);
+        assert_eq!(stats.http_calls, 3);
+        assert_eq!(simulator.cost_report().per_call.len(), 3);
+        assert_eq!(stats.query_count, simulator.get_library_info().1);
+    }
+
+    #[tokio::test]
+    async fn test_share_prefixes_saves_doorways() {
+        // Every plan but the longest two is a prefix of another.
+        let plans: Vec<String> = ["0", "01", "012", "0123", "01234", "5", "54", "543", "01"]
+            .map(String::from)
+            .to_vec();
+        let mut separate = Simulator::with_seed(6, 1).unwrap();
+        let (expected, full) = explore_batched(&mut separate, &plans, &BatchConfig::default())
+            .await
+            .unwrap();
+
+        let mut shared = Simulator::with_seed(6, 1).unwrap();
+        let config = BatchConfig {
+            share_prefixes: true,
+            ..BatchConfig::default()
+        };
+        let (results, stats) = explore_batched(&mut shared, &plans, &config).await.unwrap();
+        assert_eq!(results, expected);
+        assert_eq!((full.doorways, stats.doorways), (32, 10));
+        assert_eq!(shared.cost_report().total_doorways, 10);
+    }
+
+    #[tokio::test]
+    async fn test_share_prefixes_follows_the_convention() {
+        let plans: Vec<String> = ["01", "012", "5"].map(String::from).to_vec();
+        let mut separate = Simulator::with_seed(6, 1).unwrap();
+        separate.set_observation_convention(ObservationConvention::ExcludingFinal);
+        let (expected, _) = explore_batched(&mut separate, &plans, &BatchConfig::default())
+            .await
+            .unwrap();
+
+        let mut shared = Simulator::with_seed(6, 1).unwrap();
+        shared.set_observation_convention(ObservationConvention::ExcludingFinal);
+        let config = BatchConfig {
+            share_prefixes: true,
+            convention: ObservationConvention::ExcludingFinal,
+            ..BatchConfig::default()
+        };
+        let (results, _) = explore_batched(&mut shared, &plans, &config).await.unwrap();
+        assert_eq!(results, expected);
+    }
+
+    #[tokio::test]
+    async fn test_explore_batched_counts_the_plan_cut_short() {
+        let mut simulator = Simulator::with_seed(3, 1).unwrap();
+        simulator.explore(vec!["0".repeat(40)]).await.unwrap();
+        assert_eq!(simulator.remaining_doorways(), 13);
+
+        // The first plan takes 8 doorways and the second runs out after 5 of its 8.
+        let plans: Vec<String> = ["0123450", "0123450", "0"].map(String::from).to_vec();
+        let (results, stats) = explore_batched(&mut simulator, &plans, &BatchConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(stats.doorways, 16);
+        assert_eq!(simulator.remaining_doorways(), 0);
+    }
+
+    /// Answers every explore with one result more than plans sent.
+    struct ExtraResult(Simulator);
+
+    #[async_trait::async_trait]
+    impl AedificiumClient for ExtraResult {
+        async fn select(
+            &mut self,
+            problem_name: String,
+        ) -> Result<icfpc2025_common::SelectResponse> {
+            self.0.select(problem_name).await
+        }
+
+        async fn explore(
+            &mut self,
+            plans: Vec<String>,
+        ) -> Result<icfpc2025_common::ExploreResponse> {
+            let mut response = self.0.explore(plans).await?;
+            response.results.push(vec![0]);
+            Ok(response)
+        }
+
+        async fn guess(&self, data: Map) -> Result<icfpc2025_common::GuessResponse> {
+            self.0.guess(data).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_explore_batched_rejects_extra_results() {
+        let mut client = ExtraResult(Simulator::with_seed(6, 1).unwrap());
+        let error = explore_batched(&mut client, &plans(), &BatchConfig::default())
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("got 8 results"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_fit_to_budget_trims_the_batch() {
+        let mut simulator = Simulator::with_seed(3, 1).unwrap();
+        simulator.explore(vec!["0".repeat(40)]).await.unwrap();
+        assert_eq!(simulator.remaining_doorways(), 13);
+
+        // 8 doorways for the first plan, then the second is cut to 4 doors and the third dropped.
+        let mut plans: Vec<String> = ["1234501", "0123450", "0"].map(String::from).to_vec();
+        let trimmed = fit_to_budget(&mut plans, simulator.remaining_doorways()).unwrap();
+        assert_eq!(trimmed, 2);
+        assert_eq!(plans, vec!["1234501", "0123"]);
+
+        let (results, stats) = explore_batched(&mut simulator, &plans, &BatchConfig::default())
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(stats.doorways, 13);
+        assert_eq!(simulator.remaining_doorways(), 0);
+        assert!(fit_to_budget(&mut plans, simulator.remaining_doorways()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_explore_batched_rejects_oversized_batch() {
+        let mut simulator = Simulator::with_seed(6, 1).unwrap();
+        let config = BatchConfig {
+            batch_size: 3,
+            max_doorways_per_call: Some(6),
+            ..BatchConfig::default()
+        };
+        assert!(explore_batched(&mut simulator, &plans(), &config)
+            .await
+            .is_err());
+        // Nothing was sent.
+        assert_eq!(simulator.get_library_info().1, 0);
+    }
+
+    fn explore_config(simulator: &Simulator, room_count: usize) -> ExploreConfig {
+        ExploreConfig::new(room_count, simulator.remaining_doorways())
+    }
+
+    fn frontier() -> Frontier {
+        Frontier {
+            plan_len: 4,
+            plans_per_call: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_solve_reconstructs_small_libraries() {
+        for (seed, rooms) in [(1, 3), (2, 4), (3, 5)] {
+            assert_reconstructs(seed, rooms, &mut frontier()).await;
+            assert_reconstructs(seed, rooms, &mut ProbeStartFirst::new(frontier())).await;
+        }
+    }
+
+    #[test]
+    fn test_auto_config_picks_backend_by_size() {
+        for (rooms, backend) in [
+            (3, Backend::Exact),
+            (12, Backend::Search),
+            (24, Backend::Refinement),
+        ] {
+            let config = ExploreConfig::auto(rooms, 100);
+            assert_eq!(config.backend(), backend, "{} rooms", rooms);
+            assert_eq!(Backend::for_rooms(rooms), backend);
+        }
+        assert_eq!(
+            ExploreConfig::auto(24, 100).reconstruct.refine_depth,
+            Some(AUTO_REFINE_DEPTH)
+        );
+        // Without auto, large libraries keep the search.
+        assert_eq!(ExploreConfig::new(24, 100).backend(), Backend::Search);
+    }
+
+    #[tokio::test]
+    async fn test_tiny_libraries_are_always_solved() {
+        // Walks can't tell rooms with the same label apart when their surroundings look alike
+        // too, so not every library is pinned down within its budget. Whatever the labels, an
+        // exhaustive exact search always keeps the true map among its candidates, and
+        // libraries whose rooms all have labels of their own are always solved.
+        let mut distinct = 0;
+        for seed in 0..200 {
+            let mut simulator =
+                Simulator::with_mode_seed(3, GenerationMode::Regular, seed).unwrap();
+            let config = explore_config(&simulator, 3);
+            let mut rng = StdRng::seed_from_u64(seed);
+            let outcome = explore_until(
+                &mut simulator,
+                &mut frontier(),
+                StopCondition::UniqueReconstruction,
+                &config,
+                &mut rng,
+            )
+            .await
+            .unwrap();
+            let reconstruction = outcome.reconstruction.unwrap();
+            if reconstruction.exhaustive {
+                let found = simulator
+                    .diagnose_all(&reconstruction.candidates)
+                    .iter()
+                    .any(|diagnosis| diagnosis.is_correct());
+                assert!(found, "seed {}: the true map isn't a candidate", seed);
+            }
+
+            let labels: HashSet<usize> = simulator.get_actual_map().rooms.into_iter().collect();
+            if labels.len() == 3 {
+                assert_reconstructs(seed, 3, &mut frontier()).await;
+                distinct += 1;
+            }
+        }
+        assert!(distinct >= 20, "{}", distinct);
+    }
+
+    #[tokio::test]
+    async fn test_explore_until_full_coverage() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let config = explore_config(&simulator, 3);
+        let mut rng = StdRng::seed_from_u64(0);
+        let outcome = explore_until(
+            &mut simulator,
+            &mut frontier(),
+            StopCondition::FullCoverage,
+            &config,
+            &mut rng,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(outcome.stop_reason, StopReason::ConditionMet);
+        assert_eq!(outcome.partial_map().unwrap().coverage(), 1.0);
+        assert!(outcome.partial_map().unwrap().unexplored_doors().is_empty());
+        assert!(outcome.doorways <= config.budget);
+    }
+
+    #[tokio::test]
+    async fn test_explore_until_budget_fraction() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let config = explore_config(&simulator, 3);
+        let mut rng = StdRng::seed_from_u64(0);
+        let outcome = explore_until(
+            &mut simulator,
+            &mut frontier(),
+            StopCondition::BudgetFraction(0.5),
+            &config,
+            &mut rng,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(outcome.stop_reason, StopReason::ConditionMet);
+        assert!(outcome.doorways * 2 >= config.budget);
+        // One call earlier, the fraction had not been reached yet.
+        assert!((outcome.doorways - (frontier().plan_len + 1)) * 2 < config.budget);
+        assert_eq!(outcome.explore_calls, outcome.plans.len());
+    }
+
+    #[tokio::test]
+    async fn test_explore_until_unique_reconstruction() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let config = explore_config(&simulator, 3);
+        let mut rng = StdRng::seed_from_u64(0);
+        let outcome = explore_until(
+            &mut simulator,
+            &mut frontier(),
+            StopCondition::UniqueReconstruction,
+            &config,
+            &mut rng,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(outcome.stop_reason, StopReason::ConditionMet);
+        let reconstruction = outcome.reconstruction.unwrap();
+        assert_eq!(reconstruction.candidates.len(), 1);
+        assert!(reconstruction.exhaustive);
+    }
+
+    #[tokio::test]
+    async fn test_explore_until_runs_out_of_budget() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let mut config = explore_config(&simulator, 3);
+        config.budget = frontier().plan_len + 3;
+        let mut rng = StdRng::seed_from_u64(0);
+        let outcome = explore_until(
+            &mut simulator,
+            &mut frontier(),
+            StopCondition::FullCoverage,
+            &config,
+            &mut rng,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(outcome.stop_reason, StopReason::BudgetExhausted);
+        assert_eq!(outcome.explore_calls, 1);
+    }
+
+    #[tokio::test]
+    async fn test_explore_until_counts_the_plan_cut_short() {
+        let mut simulator = Simulator::with_seed(3, 1).unwrap();
+        simulator.explore(vec!["0".repeat(40)]).await.unwrap();
+        assert_eq!(simulator.remaining_doorways(), 13);
+
+        // The budget claims more than is left: the second plan runs out after 5 of its 8.
+        let config = ExploreConfig::new(3, 54);
+        let mut strategy = Frontier {
+            plan_len: 7,
+            plans_per_call: 2,
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let outcome = explore_until(
+            &mut simulator,
+            &mut strategy,
+            StopCondition::FullCoverage,
+            &config,
+            &mut rng,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome.stop_reason, StopReason::BudgetExhausted);
+        assert_eq!(outcome.plans.len(), 1);
+        assert_eq!(outcome.doorways, 16);
+        assert_eq!(simulator.remaining_doorways(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_spend_remaining_trims_a_plan_to_the_budget_left() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let mut config = explore_config(&simulator, 3);
+        // Two frontier plans fit whole; three doorways are left over.
+        config.budget = 2 * (frontier().plan_len + 1) + 3;
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut outcome = explore_until(
+            &mut simulator,
+            &mut frontier(),
+            StopCondition::FullCoverage,
+            &config,
+            &mut rng,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome.explore_calls, 2);
+        assert!(outcome.candidates().len() > 1);
+
+        spend_remaining(
+            &mut simulator,
+            &mut frontier(),
+            &mut outcome,
+            &config,
+            &mut rng,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome.explore_calls, 3);
+        assert_eq!(outcome.plans[2].len(), 2);
+        assert_eq!(outcome.doorways, config.budget);
+        assert_eq!(simulator.get_library_info().1, config.budget);
+    }
+
+    #[tokio::test]
+    async fn test_solve_report() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let config = explore_config(&simulator, 3);
+        let mut rng = StdRng::seed_from_u64(0);
+        let report = solve(&mut simulator, &mut frontier(), &config, &mut rng)
+            .await
+            .unwrap();
+        assert!(report.correct);
+        assert!(report.unique);
+        assert_eq!(report.candidate_count, 1);
+        assert_eq!(report.doorways, simulator.get_library_info().1);
+        assert_eq!(report.guess_size.rooms, 3);
+        assert_eq!(report.guess_size.edges, 9);
+        assert_eq!(
+            report.guess_size.bytes,
+            serde_json::to_string(&report.map).unwrap().len()
+        );
+
+        // A single short plan leaves several candidates, so the guess is not determined.
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let mut config = explore_config(&simulator, 3);
+        config.budget = frontier().plan_len + 1;
+        let report = solve(&mut simulator, &mut frontier(), &config, &mut rng)
+            .await
+            .unwrap();
+        assert_eq!(report.explores, 1);
+        assert!(report.candidate_count > 1);
+        assert!(!report.unique);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["candidate_count"], report.candidate_count);
+        assert_eq!(json["unique"], false);
+    }
+
+    #[tokio::test]
+    async fn test_distinguish_candidates_rules_out_one_of_the_best() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let mut config = explore_config(&simulator, 3);
+        let budget = config.budget;
+        config.budget = frontier().plan_len + 1;
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut outcome = explore_until(
+            &mut simulator,
+            &mut frontier(),
+            StopCondition::UniqueReconstruction,
+            &config,
+            &mut rng,
+        )
+        .await
+        .unwrap();
+        assert!(outcome.candidates().len() > 1);
+        let observations = ObservationSet::from_results(&outcome.plans, &outcome.results).unwrap();
+        let ranked = rank_candidates(outcome.candidates(), &observations);
+        let best = ranked[0].0.clone();
+        let rival = ranked[1..]
+            .iter()
+            .map(|(map, _)| map.clone())
+            .find(|map| distinguishing_plan(&best, map).is_some())
+            .unwrap();
+
+        config.budget = budget;
+        distinguish_candidates(&mut simulator, &mut outcome, &config, &mut rng)
+            .await
+            .unwrap();
+        assert!(outcome.explore_calls > 1);
+        assert_eq!(outcome.doorways, simulator.get_library_info().1);
+        // The observations now contradict at least one of them.
+        let fits = |map: &Map| verify_against_observations(map, &outcome.plans, &outcome.results);
+        assert!(!(fits(&best) && fits(&rival)));
+    }
+
+    /// Explores through a simulator, taking `delay` longer for every call.
+    struct Delayed(Simulator, Duration);
+
+    #[async_trait::async_trait]
+    impl AedificiumClient for Delayed {
+        async fn select(
+            &mut self,
+            problem_name: String,
+        ) -> Result<icfpc2025_common::SelectResponse> {
+            self.0.select(problem_name).await
+        }
+
+        async fn explore(
+            &mut self,
+            plans: Vec<String>,
+        ) -> Result<icfpc2025_common::ExploreResponse> {
+            tokio::time::sleep(self.1).await;
+            self.0.explore(plans).await
+        }
+
+        async fn guess(&self, data: Map) -> Result<icfpc2025_common::GuessResponse> {
+            self.0.guess(data).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_auto_batch_grows_when_calls_are_slow() {
+        let simulator = Simulator::with_mode_seed(12, GenerationMode::Regular, 1).unwrap();
+        let mut config = explore_config(&simulator, 12);
+        config.auto_batch = true;
+        let mut client = Delayed(simulator, Duration::from_millis(30));
+        let mut rng = StdRng::seed_from_u64(0);
+        let outcome = explore_until(
+            &mut client,
+            &mut frontier(),
+            StopCondition::BudgetFraction(0.5),
+            &config,
+            &mut rng,
+        )
+        .await
+        .unwrap();
+
+        // One four-door plan at first, then twice as many per call.
+        let per_call = client.0.cost_report().per_call;
+        assert_eq!(per_call[..3], [5, 10, 20]);
+        assert!(
+            per_call[3..].iter().all(|&doorways| doorways >= 20),
+            "{:?}",
+            per_call
+        );
+        assert!(outcome.explore_calls < outcome.plans.len());
+    }
+
+    /// Explores through a simulator but can't submit guesses.
+    struct GuessFails(Simulator);
+
+    #[async_trait::async_trait]
+    impl AedificiumClient for GuessFails {
+        async fn select(
+            &mut self,
+            problem_name: String,
+        ) -> Result<icfpc2025_common::SelectResponse> {
+            self.0.select(problem_name).await
+        }
+
+        async fn explore(
+            &mut self,
+            plans: Vec<String>,
+        ) -> Result<icfpc2025_common::ExploreResponse> {
+            self.0.explore(plans).await
+        }
+
+        async fn guess(&self, _data: Map) -> Result<icfpc2025_common::GuessResponse> {
+            bail!("connection reset")
+        }
+    }
+
+    /// Explores through a simulator, requesting a shutdown once the first call is answered as
+    /// if Ctrl-C had been pressed during it, and keeps the guesses instead of submitting them.
+    struct ShutDownAfterOne {
+        simulator: Simulator,
+        shutdown: Shutdown,
+        guesses: std::sync::Mutex<Vec<Map>>,
+    }
+
+    #[async_trait::async_trait]
+    impl AedificiumClient for ShutDownAfterOne {
+        async fn select(
+            &mut self,
+            problem_name: String,
+        ) -> Result<icfpc2025_common::SelectResponse> {
+            self.simulator.select(problem_name).await
+        }
+
+        async fn explore(
+            &mut self,
+            plans: Vec<String>,
+        ) -> Result<icfpc2025_common::ExploreResponse> {
+            self.shutdown.request();
+            self.simulator.explore(plans).await
+        }
+
+        async fn guess(&self, data: Map) -> Result<icfpc2025_common::GuessResponse> {
+            self.guesses.lock().unwrap().push(data);
+            Ok(icfpc2025_common::GuessResponse { correct: false })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_still_guesses() {
+        let client = || {
+            let simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 1).unwrap();
+            ShutDownAfterOne {
+                simulator,
+                shutdown: Shutdown::new(),
+                guesses: Default::default(),
+            }
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let mut interrupted = client();
+        let mut config = explore_config(&interrupted.simulator, 3);
+        config.shutdown = interrupted.shutdown.clone();
+        let report = solve(&mut interrupted, &mut frontier(), &config, &mut rng)
+            .await
+            .unwrap();
+        assert!(report.interrupted);
+        assert_eq!(report.explores, 1);
+        let guesses = interrupted.guesses.into_inner().unwrap();
+        assert_eq!(guesses.len(), 1);
+        assert_eq!(canonical_hash(&guesses[0]), canonical_hash(&report.map));
+
+        // Without guessing on shutdown, nothing is submitted.
+        let mut interrupted = client();
+        config.shutdown = interrupted.shutdown.clone();
+        config.guess_on_shutdown = false;
+        let err = solve(&mut interrupted, &mut frontier(), &config, &mut rng)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<Interrupted>().map(|e| e.explores),
+            Some(1)
+        );
+        assert!(interrupted.guesses.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_checkpoint_survives_failed_solve() {
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.json", std::process::id()));
+        let simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let mut config = explore_config(&simulator, 3);
+        config.checkpoint = Some(path.clone());
+        let mut client = GuessFails(simulator);
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert!(solve(&mut client, &mut frontier(), &config, &mut rng)
+            .await
+            .is_err());
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        assert_eq!(checkpoint.correct, None);
+        assert_eq!(checkpoint.map.rooms.len(), 3);
+        assert!(client.0.guess(checkpoint.map).await.unwrap().correct);
+
+        // A completed guess is recorded too.
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let report = solve(&mut simulator, &mut frontier(), &config, &mut rng)
+            .await
+            .unwrap();
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        assert_eq!(checkpoint.correct, Some(report.correct));
+        assert_eq!(checkpoint.explores, report.explores);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unwritable_checkpoint_does_not_stop_solve() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let mut config = explore_config(&simulator, 3);
+        config.checkpoint = Some(std::env::temp_dir().join("no-such-dir/checkpoint.json"));
+        let mut rng = StdRng::seed_from_u64(0);
+        let report = solve(&mut simulator, &mut frontier(), &config, &mut rng)
+            .await
+            .unwrap();
+        assert!(report.correct);
+    }
+
+    #[tokio::test]
+    async fn test_solve_writes_run_report() {
+        let path = std::env::temp_dir().join(format!("run-report-{}.json", std::process::id()));
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let mut config = explore_config(&simulator, 3);
+        config.run_log = Some(RunLog {
+            path: path.clone(),
+            problem_name: "probatio".to_string(),
+            seed: Some(3),
+        });
+        let mut rng = StdRng::seed_from_u64(0);
+        let report = solve(&mut simulator, &mut frontier(), &config, &mut rng)
+            .await
+            .unwrap();
+
+        let run = RunReport::load(&path).unwrap();
+        assert_eq!(run.problem_name, "probatio");
+        assert_eq!((run.room_count, run.seed), (3, Some(3)));
+        assert_eq!(run.map.unwrap().rooms, report.map.rooms);
+        assert_eq!(run.correct, Some(report.correct));
+        assert_eq!(
+            (run.explores, run.doorways),
+            (report.explores, report.doorways)
+        );
+        assert!(!run.observations.is_empty());
+        for observation in &run.observations {
+            assert_eq!(observation.labels.len(), observation.plan.len() + 1);
+        }
+        assert!(run.started_at_ms > 0 && run.finished_at_ms >= run.started_at_ms);
+        assert!(run.latency.explore_max_ms <= run.latency.explore_total_ms);
+        assert!(run.latency.guess_ms.is_some());
+        assert_eq!(run.error, None);
+
+        // A failed solve still leaves a report saying why.
+        let mut client =
+            GuessFails(Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap());
+        assert!(solve(&mut client, &mut frontier(), &config, &mut rng)
+            .await
+            .is_err());
+        let run = RunReport::load(&path).unwrap();
+        assert!(run.map.is_none());
+        assert_eq!(run.correct, None);
+        assert_eq!(run.latency.guess_ms, None);
+        assert!(!run.observations.is_empty());
+        assert!(run.error.unwrap().contains("connection reset"));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Explores through a simulator until `calls` explores have been answered, then fails.
+    struct ExploreFailsAfter(Simulator, usize);
+
+    #[async_trait::async_trait]
+    impl AedificiumClient for ExploreFailsAfter {
+        async fn select(
+            &mut self,
+            problem_name: String,
+        ) -> Result<icfpc2025_common::SelectResponse> {
+            self.0.select(problem_name).await
+        }
+
+        async fn explore(
+            &mut self,
+            plans: Vec<String>,
+        ) -> Result<icfpc2025_common::ExploreResponse> {
+            if self.1 == 0 {
+                bail!("connection reset");
+            }
+            self.1 -= 1;
+            self.0.explore(plans).await
+        }
+
+        async fn guess(&self, data: Map) -> Result<icfpc2025_common::GuessResponse> {
+            self.0.guess(data).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_report_keeps_observations_of_failed_explore() {
+        let path = std::env::temp_dir().join(format!("run-failed-{}.json", std::process::id()));
+        let simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let mut config = explore_config(&simulator, 3);
+        config.run_log = Some(RunLog {
+            path: path.clone(),
+            problem_name: "probatio".to_string(),
+            seed: Some(3),
+        });
+        let mut client = ExploreFailsAfter(simulator, 2);
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!(solve(&mut client, &mut frontier(), &config, &mut rng)
+            .await
+            .is_err());
+
+        let run = RunReport::load(&path).unwrap();
+        assert_eq!(run.explores, 2);
+        assert_eq!(run.doorways, client.0.get_library_info().1);
+        assert!(!run.observations.is_empty());
+        assert!(run.error.unwrap().contains("connection reset"));
+        std::fs::remove_file(&path).unwrap();
+
+        // Nor does a report that can't be written change how the solve went.
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        config.run_log.as_mut().unwrap().path = std::env::temp_dir().join("no-such-dir/run.json");
+        let report = solve(&mut simulator, &mut frontier(), &config, &mut rng)
+            .await
+            .unwrap();
+        assert_eq!(report.doorways, simulator.get_library_info().1);
+    }
+
+    #[tokio::test]
+    async fn test_explore_until_respects_max_plan_len() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 3).unwrap();
+        let mut config = explore_config(&simulator, 3);
+        config.max_plan_len = 2;
+        config.budget = 9;
+        let mut strategy = Frontier {
+            plan_len: 10,
+            plans_per_call: 1,
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let outcome = explore_until(
+            &mut simulator,
+            &mut strategy,
+            StopCondition::FullCoverage,
+            &config,
+            &mut rng,
+        )
+        .await
+        .unwrap();
+
+        assert!(outcome.plans.iter().all(|plan| plan.len() == 2));
+        assert_eq!(outcome.explore_calls, 3);
+    }
+}
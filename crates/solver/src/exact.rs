@@ -0,0 +1,180 @@
+use anyhow::Result;
+use icfpc2025_common::DOORS;
+use rand::Rng;
+use std::collections::HashSet;
+
+use crate::reconstruct::{complete_map, reconstruct, ReconstructOptions, Reconstruction};
+
+/// Libraries up to this many rooms are small enough for [`reconstruct_exact`]; the driver uses
+/// it for them instead of [`reconstruct`].
+pub const EXACT_MAX_ROOMS: usize = 6;
+
+/// Every map with `room_count` rooms that fits the observations, including every way of
+/// connecting the doors nobody walked through. [`reconstruct`] completes each assignment of the
+/// observed doors one arbitrary way, so a single candidate from it may still leave unobserved
+/// doors open; here a single candidate is the only map that fits.
+///
+/// The assignments of the observed doors come from the backtracking search of [`reconstruct`],
+/// which finds every one of them unless it stops at `options.max_candidates` or
+/// `options.max_nodes`; `rng` only decides the order they come in. Each assignment is then
+/// completed every possible way, which takes at most another `options.max_nodes` steps per
+/// assignment. The result is `exhaustive` unless one of those limits was hit, or more than
+/// `options.max_candidates` ways of connecting the open doors of one assignment fit; such an
+/// assignment is kept with the single completion [`reconstruct`] made, so it never goes
+/// missing. Enumerating completions pays off for small libraries, up to [`EXACT_MAX_ROOMS`]
+/// rooms, where few doors stay open for long.
+pub fn reconstruct_exact(
+    plans: &[String],
+    results: &[Vec<usize>],
+    room_count: usize,
+    options: &ReconstructOptions,
+    rng: &mut impl Rng,
+) -> Result<Reconstruction> {
+    let observed = reconstruct(plans, results, room_count, options, rng)?;
+
+    let mut exact = Reconstruction {
+        candidates: Vec::new(),
+        partial_maps: Vec::new(),
+        positions: Vec::new(),
+        nodes_expanded: observed.nodes_expanded,
+        exhaustive: observed.exhaustive,
+    };
+    let mut seen = HashSet::new();
+    for ((partial, positions), completed) in observed
+        .partial_maps
+        .iter()
+        .zip(&observed.positions)
+        .zip(observed.candidates)
+    {
+        let unknown = partial.unexplored_doors();
+        let mut completions = Vec::new();
+        let mut edges = partial.edges.clone();
+        let mut nodes_left = options.max_nodes;
+        let maps = if complete(
+            &mut edges,
+            &unknown,
+            options.max_candidates,
+            &mut nodes_left,
+            &mut completions,
+        ) {
+            completions
+                .iter()
+                .map(|edges| complete_map(&partial.labels, edges))
+                .collect()
+        } else {
+            // Too many ways to connect the open doors: keep the one completion `reconstruct`
+            // made, so no assignment of the observed doors goes missing.
+            exact.exhaustive = false;
+            vec![completed]
+        };
+        for map in maps {
+            // The search tells apart maps that only number the rooms differently; one of them
+            // is enough.
+            let canonical = map.canonicalize()?;
+            if !seen.insert((canonical.rooms, canonical.connections)) {
+                continue;
+            }
+            exact.candidates.push(map);
+            exact.partial_maps.push(partial.clone());
+            exact.positions.push(positions.clone());
+        }
+    }
+    Ok(exact)
+}
+
+/// Collects every way of pointing the doors `unknown` at rooms such that each connection
+/// between two rooms has a door back. Returns `false` if there are more than `limit`, or if
+/// finding them takes more than `nodes_left` steps.
+fn complete(
+    edges: &mut [[Option<usize>; DOORS]],
+    unknown: &[(usize, usize)],
+    limit: usize,
+    nodes_left: &mut usize,
+    completions: &mut Vec<Vec<[Option<usize>; DOORS]>>,
+) -> bool {
+    if *nodes_left == 0 {
+        return false;
+    }
+    *nodes_left -= 1;
+    let Some((&(room, door), rest)) = unknown.split_first() else {
+        if !is_balanced(edges) {
+            return true;
+        }
+        if completions.len() >= limit {
+            return false;
+        }
+        completions.push(edges.to_vec());
+        return true;
+    };
+    for target in 0..edges.len() {
+        edges[room][door] = Some(target);
+        if can_balance(edges, room)
+            && can_balance(edges, target)
+            && !complete(edges, rest, limit, nodes_left, completions)
+        {
+            edges[room][door] = None;
+            return false;
+        }
+    }
+    edges[room][door] = None;
+    true
+}
+
+fn count_doors(edges: &[[Option<usize>; DOORS]], from: usize, to: usize) -> usize {
+    edges[from]
+        .iter()
+        .filter(|&&target| target == Some(to))
+        .count()
+}
+
+/// Whether the unknown doors of `room` can still make up for the doors into it that have no
+/// door back.
+fn can_balance(edges: &[[Option<usize>; DOORS]], room: usize) -> bool {
+    let unknown = edges[room].iter().filter(|e| e.is_none()).count();
+    let missing: usize = (0..edges.len())
+        .filter(|&other| other != room)
+        .map(|other| {
+            count_doors(edges, other, room).saturating_sub(count_doors(edges, room, other))
+        })
+        .sum();
+    missing <= unknown
+}
+
+fn is_balanced(edges: &[[Option<usize>; DOORS]]) -> bool {
+    (0..edges.len()).all(|room| {
+        (room + 1..edges.len())
+            .all(|other| count_doors(edges, room, other) == count_doors(edges, other, room))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use icfpc2025_common::AedificiumClient;
+    use icfpc2025_simulator::{GenerationMode, Simulator};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_exact_leaves_unobserved_doors_open() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 1).unwrap();
+        let plans: Vec<String> = ["012345", "543210"].map(String::from).to_vec();
+        let results = simulator.explore(plans.clone()).await.unwrap().results;
+        let options = ReconstructOptions {
+            max_candidates: 10_000,
+            ..ReconstructOptions::default()
+        };
+
+        let observed =
+            reconstruct(&plans, &results, 3, &options, &mut StdRng::seed_from_u64(0)).unwrap();
+        let exact = reconstruct_exact(&plans, &results, 3, &options, &mut StdRng::seed_from_u64(0))
+            .unwrap();
+        assert!(exact.exhaustive);
+        assert!(exact.candidates.len() > observed.candidates.len());
+        let mut correct = 0;
+        for map in exact.candidates {
+            correct += usize::from(simulator.guess(map).await.unwrap().correct);
+        }
+        assert_eq!(correct, 1);
+    }
+}
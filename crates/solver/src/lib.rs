@@ -0,0 +1,59 @@
+pub mod automaton;
+pub mod budget;
+pub mod candidate;
+#[cfg(feature = "client")]
+pub mod checkpoint;
+#[cfg(feature = "client")]
+pub mod concurrent;
+pub mod consistency;
+#[cfg(feature = "client")]
+pub mod driver;
+pub mod exact;
+pub mod partial;
+pub mod reconstruct;
+#[cfg(feature = "client")]
+pub mod recorder;
+pub mod repair;
+#[cfg(feature = "client")]
+pub mod shutdown;
+pub mod signature;
+pub mod strategy;
+#[cfg(all(test, feature = "client"))]
+mod testing;
+pub mod tuner;
+
+pub use automaton::Automaton;
+pub use budget::{reported_remaining_budget, BudgetTracker, Reconciliation};
+pub use candidate::{
+    canonical_hash, distinguishing_plan, hold_out, is_connected, pick_deterministic,
+    rank_candidates, reachable_rooms, regularity, score_estimate, start_room_probe,
+    verify_start_room,
+};
+#[cfg(feature = "client")]
+pub use checkpoint::Checkpoint;
+#[cfg(feature = "client")]
+pub use concurrent::{solve_concurrently, ProblemJob, ProblemOutcome};
+pub use consistency::{check_consistency, Inconsistency};
+#[cfg(feature = "client")]
+pub use driver::{
+    distinguish_candidates, explore_batched, explore_until, fit_to_budget, plan_cost, solve,
+    Backend, BatchConfig, BatchStats, ExploreConfig, ExploreOutcome, GuessSize, SolveReport,
+    StopCondition, StopReason, AUTO_REFINE_DEPTH, REFINEMENT_MIN_ROOMS,
+};
+pub use exact::{reconstruct_exact, EXACT_MAX_ROOMS};
+pub use partial::{shortest_plan, PartialMap, PartialSolution};
+pub use reconstruct::{
+    parse_plan, reconstruct, reconstruct_observations, reconstruct_pairs, reconstruct_partial,
+    verify_against_observations, ReconstructOptions, Reconstruction,
+};
+#[cfg(feature = "client")]
+pub use recorder::{LatencyStats, RunLog, RunReport};
+pub use repair::{is_complete, repair_map};
+#[cfg(feature = "client")]
+pub use shutdown::{Interrupted, Shutdown};
+pub use signature::label_signature;
+pub use strategy::{
+    estimate_coverage, probe_start_plan, DoorOrdering, ExploreStrategy, Frontier, PlanGenConfig,
+    ProbeStartFirst, RandomWalk, WithPlanGen,
+};
+pub use tuner::BatchTuner;
@@ -0,0 +1,203 @@
+use icfpc2025_common::DOORS;
+use std::collections::{HashMap, VecDeque};
+
+/// The doors of a reconstructed library that have actually been observed.
+///
+/// Room 0 is the starting room. Unlike a completed [`Map`](icfpc2025_common::Map), doors that no
+/// observation walked through are left as `None`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PartialMap {
+    pub labels: Vec<usize>,
+    pub edges: Vec<[Option<usize>; DOORS]>,
+}
+
+impl PartialMap {
+    pub fn room_count(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn known_doors(&self) -> usize {
+        self.edges.iter().flatten().filter(|e| e.is_some()).count()
+    }
+
+    /// `(room, door)` pairs whose target is still unknown, in ascending order.
+    pub fn unexplored_doors(&self) -> Vec<(usize, usize)> {
+        self.edges
+            .iter()
+            .enumerate()
+            .flat_map(|(room, doors)| {
+                doors
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, target)| target.is_none())
+                    .map(move |(door, _)| (room, door))
+            })
+            .collect()
+    }
+
+    /// Label of the room behind each door of `room`, for the doors observed so far.
+    pub fn neighbor_labels(&self, room: usize) -> [Option<u8>; DOORS] {
+        self.edges[room].map(|target| target.map(|target| self.labels[target] as u8))
+    }
+
+    /// Rooms with at least one unexplored door.
+    pub fn frontier(&self) -> Vec<usize> {
+        (0..self.room_count())
+            .filter(|&room| self.edges[room].iter().any(|target| target.is_none()))
+            .collect()
+    }
+
+    /// Fraction of all doors whose target is known.
+    pub fn coverage(&self) -> f64 {
+        if self.labels.is_empty() {
+            return 0.0;
+        }
+        self.known_doors() as f64 / (self.room_count() * DOORS) as f64
+    }
+}
+
+/// What every candidate of a reconstruction agrees on, door by door.
+///
+/// Rooms are numbered in the order the observations first walk into them, as in
+/// [`crate::Reconstruction::partial_maps`], so room `i` is the same room in every candidate: the
+/// `i`-th distinct room along the observations. Doors that no candidate observed appear in
+/// neither map.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PartialSolution {
+    /// `(room, door)` observed with the same target in every candidate.
+    pub fixed_edges: HashMap<(usize, usize), usize>,
+    /// `(room, door)` the candidates disagree on or leave open, with the targets observed
+    /// among them in ascending order.
+    pub ambiguous: HashMap<(usize, usize), Vec<usize>>,
+    /// `false` if the search behind it gave up, so there may be candidates it doesn't cover.
+    pub exhaustive: bool,
+}
+
+impl PartialSolution {
+    pub fn from_partial_maps(partial_maps: &[PartialMap], exhaustive: bool) -> Self {
+        let mut solution = Self {
+            exhaustive,
+            ..Self::default()
+        };
+        let room_count = partial_maps.iter().map(PartialMap::room_count).max();
+        for room in 0..room_count.unwrap_or(0) {
+            for door in 0..DOORS {
+                let targets: Vec<Option<usize>> = partial_maps
+                    .iter()
+                    .map(|partial| partial.edges.get(room).and_then(|doors| doors[door]))
+                    .collect();
+                let mut seen: Vec<usize> = targets.iter().flatten().copied().collect();
+                seen.sort_unstable();
+                seen.dedup();
+                match seen[..] {
+                    [] => {}
+                    [target] if targets.iter().all(Option::is_some) => {
+                        solution.fixed_edges.insert((room, door), target);
+                    }
+                    _ => {
+                        solution.ambiguous.insert((room, door), seen);
+                    }
+                }
+            }
+        }
+        solution
+    }
+
+    /// Whether every observed door has a single target.
+    pub fn is_determined(&self) -> bool {
+        self.ambiguous.is_empty()
+    }
+}
+
+/// Shortest door sequence from `from` to every room reachable over known doors.
+pub(crate) fn routes_from(partial: &PartialMap, from: usize) -> Vec<Option<String>> {
+    let doors: Vec<u8> = (0..DOORS as u8).collect();
+    routes_over(partial, from, &doors)
+}
+
+/// Like [`routes_from`], going only through `doors`. Among equally short routes, the one whose
+/// doors come first in `doors` wins.
+pub(crate) fn routes_over(partial: &PartialMap, from: usize, doors: &[u8]) -> Vec<Option<String>> {
+    let mut routes = vec![None; partial.room_count()];
+    if from >= routes.len() {
+        return routes;
+    }
+    routes[from] = Some(String::new());
+    let mut queue = VecDeque::from([from]);
+    while let Some(room) = queue.pop_front() {
+        for &door in doors {
+            if let Some(next) = partial.edges[room][door as usize] {
+                if routes[next].is_none() {
+                    routes[next] = Some(format!("{}{}", routes[room].as_ref().unwrap(), door));
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    routes
+}
+
+/// Shortest plan leading from room `from` to room `to` over known doors only.
+///
+/// Returns `None` if `to` cannot be reached with what is known so far, and an empty plan if
+/// `from == to`. Among equally short plans, the one with the smallest doors comes first.
+pub fn shortest_plan(partial: &PartialMap, from: usize, to: usize) -> Option<String> {
+    routes_from(partial, from).into_iter().nth(to).flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_coverage() {
+        let mut partial = PartialMap {
+            labels: vec![0, 1],
+            edges: vec![[None; DOORS]; 2],
+        };
+        assert_eq!(partial.coverage(), 0.0);
+
+        partial.edges[0][0] = Some(1);
+        partial.edges[1][3] = Some(0);
+        partial.edges[1][4] = Some(1);
+        assert_eq!(partial.known_doors(), 3);
+        assert_eq!(partial.coverage(), 0.25);
+        assert_eq!(partial.unexplored_doors().len(), 9);
+        assert_eq!(partial.unexplored_doors()[..2], [(0, 1), (0, 2)]);
+        assert_eq!(partial.frontier(), vec![0, 1]);
+        assert_eq!(
+            partial.neighbor_labels(1),
+            [None, None, None, Some(0), Some(1), None]
+        );
+
+        partial.edges[0] = [Some(0); DOORS];
+        assert_eq!(partial.frontier(), vec![1]);
+        partial.edges[1] = [Some(1); DOORS];
+        assert!(partial.unexplored_doors().is_empty());
+        assert!(partial.frontier().is_empty());
+        assert_eq!(partial.coverage(), 1.0);
+
+        assert_eq!(PartialMap::default().coverage(), 0.0);
+    }
+
+    #[test]
+    fn test_shortest_plan() {
+        // 0 -5-> 1 -0-> 2 -1-> 3, plus a longer detour 0 -0-> 2 that makes "01" beat "501".
+        let mut partial = PartialMap {
+            labels: vec![0, 1, 2, 3],
+            edges: vec![[None; DOORS]; 4],
+        };
+        partial.edges[0][5] = Some(1);
+        partial.edges[1][0] = Some(2);
+        partial.edges[2][1] = Some(3);
+        partial.edges[0][0] = Some(2);
+        partial.edges[3][2] = Some(3);
+
+        assert_eq!(shortest_plan(&partial, 0, 0).as_deref(), Some(""));
+        assert_eq!(shortest_plan(&partial, 0, 1).as_deref(), Some("5"));
+        assert_eq!(shortest_plan(&partial, 0, 3).as_deref(), Some("01"));
+        assert_eq!(shortest_plan(&partial, 1, 3).as_deref(), Some("01"));
+        assert_eq!(shortest_plan(&partial, 3, 0), None);
+        assert_eq!(shortest_plan(&partial, 0, 4), None);
+    }
+}
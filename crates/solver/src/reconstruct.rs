@@ -0,0 +1,1232 @@
+use anyhow::{anyhow, bail, Result};
+use icfpc2025_common::{
+    parse_plan_steps, Map, MapBuilder, ObservationConvention, ObservationSet, PlanStep, DOORS,
+};
+use rand::{seq::SliceRandom, Rng};
+use std::collections::HashMap;
+
+use crate::automaton::Automaton;
+use crate::candidate::is_connected;
+use crate::partial::{PartialMap, PartialSolution};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ReconstructOptions {
+    /// Stop searching once this many candidate maps have been found.
+    pub max_candidates: usize,
+    /// Give up after expanding this many search nodes.
+    pub max_nodes: usize,
+    /// Skip assigning a position to a room whose known neighbour labels contradict the labels
+    /// observed one step beyond that position. Sound, so it only changes `nodes_expanded`.
+    pub fingerprint_pruning: bool,
+    /// Abandon a branch once the door steps left can't bring in the rooms it is still missing.
+    /// Every room of a candidate is reached through the observations from the starting room,
+    /// so a room that isn't in the partial map yet needs a later door step to join it. Sound,
+    /// so it only changes `nodes_expanded`.
+    pub connectivity_pruning: bool,
+    /// How many labels each result has. Results of the wrong length are rejected.
+    pub convention: ObservationConvention,
+    /// Reconstruct over classes of positions that saw the same labels along every door sequence
+    /// of up to this many doors, instead of over raw rooms. Rooms that can't be told apart are
+    /// collapsed: candidates have the fewest rooms, up to `room_count`, that fit the
+    /// observations.
+    pub refine_depth: Option<usize>,
+}
+
+impl Default for ReconstructOptions {
+    fn default() -> Self {
+        Self {
+            max_candidates: 16,
+            max_nodes: 5_000_000,
+            fingerprint_pruning: true,
+            connectivity_pruning: true,
+            convention: ObservationConvention::default(),
+            refine_depth: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Reconstruction {
+    /// Maps consistent with every observation. Room 0 is always the starting room.
+    pub candidates: Vec<Map>,
+    /// `partial_maps[i]` holds only the doors of `candidates[i]` that were actually observed.
+    pub partial_maps: Vec<PartialMap>,
+    /// `positions[i][j][k]` is the room of `candidates[i]` that the walk of plan `j` was in
+    /// when it observed its `k`th label.
+    pub positions: Vec<Vec<Vec<usize>>>,
+    pub nodes_expanded: usize,
+    /// `true` if the search space was fully covered, i.e. `candidates` lists every consistent map.
+    pub exhaustive: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Step {
+    /// Every plan starts over from the starting room, and so does a reset within a plan. Only
+    /// a new plan wipes out the marks made so far.
+    Start {
+        label: usize,
+        reset: bool,
+    },
+    Move {
+        door: usize,
+        label: usize,
+    },
+    /// A charcoal mark, rewriting the label of the current room to `label` until the end of
+    /// the plan.
+    Mark {
+        label: usize,
+    },
+}
+
+pub(crate) fn has_marks(steps: &[Step]) -> bool {
+    steps.iter().any(|step| matches!(step, Step::Mark { .. }))
+}
+
+pub fn parse_plan(plan: &str) -> Result<Vec<usize>> {
+    plan.chars()
+        .map(|c| match c.to_digit(10) {
+            Some(d) if (d as usize) < DOORS => Ok(d as usize),
+            _ => bail!("Invalid door number in plan: {}", c),
+        })
+        .collect()
+}
+
+pub(crate) fn build_steps(
+    plans: &[String],
+    results: &[Vec<usize>],
+    convention: ObservationConvention,
+) -> Result<Vec<Step>> {
+    if plans.len() != results.len() {
+        bail!("Got {} results for {} plans", results.len(), plans.len());
+    }
+
+    let mut steps = Vec::new();
+    for (index, (plan, labels)) in plans.iter().zip(results).enumerate() {
+        let normalized = convention
+            .normalize(plan, labels)
+            .map_err(|e| anyhow!("Plan {}: {}", index, e))?;
+        let Some((plan, labels)) = normalized else {
+            continue;
+        };
+        steps.push(Step::Start {
+            label: labels[0],
+            reset: false,
+        });
+        for (step, &label) in parse_plan_steps(plan)?.into_iter().zip(&labels[1..]) {
+            steps.push(match step {
+                PlanStep::Door(door) => Step::Move { door, label },
+                PlanStep::Reset => Step::Start { label, reset: true },
+                PlanStep::Mark(mark) if mark == label => Step::Mark { label },
+                PlanStep::Mark(mark) => {
+                    bail!(
+                        "Plan {} marks a room {} but observes {}",
+                        index,
+                        mark,
+                        label
+                    )
+                }
+            });
+        }
+    }
+    Ok(steps)
+}
+
+/// For every position, the label seen behind each door taken from it.
+///
+/// A position only walks through one door, except that every plan start is the starting room:
+/// the first steps of all plans are pooled into a single fingerprint for the starting room.
+/// Once a plan has marked a room, the labels it sees may be marks rather than the rooms' own,
+/// so the rest of the plan is left out.
+fn fingerprints(steps: &[Step]) -> Vec<[Option<usize>; DOORS]> {
+    let mut fingerprints = vec![[None; DOORS]; steps.len()];
+    let mut start = [None; DOORS];
+    let mut marked = false;
+    for (pos, pair) in steps.windows(2).enumerate() {
+        match pair[0] {
+            Step::Start { reset: false, .. } => marked = false,
+            Step::Mark { .. } => marked = true,
+            _ => {}
+        }
+        if marked {
+            continue;
+        }
+        if let Step::Move { door, label } = pair[1] {
+            fingerprints[pos][door] = Some(label);
+            if let Step::Start { .. } = pair[0] {
+                start[door].get_or_insert(label);
+            }
+        }
+    }
+    for (pos, step) in steps.iter().enumerate() {
+        if let Step::Start { .. } = step {
+            fingerprints[pos] = start;
+        }
+    }
+    fingerprints
+}
+
+/// For every position and one past the end, how many of the steps from there on go through a
+/// door.
+fn moves_after(steps: &[Step]) -> Vec<usize> {
+    let mut moves_after = vec![0; steps.len() + 1];
+    for (pos, step) in steps.iter().enumerate().rev() {
+        moves_after[pos] = moves_after[pos + 1] + usize::from(matches!(step, Step::Move { .. }));
+    }
+    moves_after
+}
+
+/// [`Automaton::classes`] of every position.
+fn observation_classes(steps: &[Step], depth: usize) -> Vec<Option<usize>> {
+    let (automaton, state_of) = Automaton::from_steps(steps);
+    let classes = automaton.classes(depth);
+    state_of.into_iter().map(|state| classes[state]).collect()
+}
+
+/// Backtracking search assigning a physical room to every observed position.
+///
+/// Rooms are numbered in order of first appearance, so every candidate is found at most once.
+struct Search<'a, R: Rng> {
+    steps: &'a [Step],
+    fingerprints: Vec<[Option<usize>; DOORS]>,
+    /// `moves_after[pos]` is the number of door steps from `pos` on.
+    moves_after: Vec<usize>,
+    /// Class of every position when refining, otherwise empty.
+    classes: Vec<Option<usize>>,
+    room_count: usize,
+    options: &'a ReconstructOptions,
+    rng: &'a mut R,
+    labels: Vec<usize>,
+    /// The label each room has been marked with in the current plan, if any.
+    marks: Vec<Option<usize>>,
+    edges: Vec<[Option<usize>; DOORS]>,
+    /// Labels seen behind the doors of every room, from the fingerprints of the positions
+    /// placed in it so far. Unlike `edges`, this covers doors walked through by observations
+    /// the search hasn't reached yet.
+    neighbor_labels: Vec<[Option<usize>; DOORS]>,
+    /// Room each class has been assigned to, and the other way round.
+    class_rooms: HashMap<usize, usize>,
+    room_classes: HashMap<usize, usize>,
+    /// Room of every step so far.
+    trace: Vec<usize>,
+    candidates: Vec<Map>,
+    partial_maps: Vec<PartialMap>,
+    /// The trace of every candidate.
+    traces: Vec<Vec<usize>>,
+    nodes_expanded: usize,
+    aborted: bool,
+}
+
+impl<R: Rng> Search<'_, R> {
+    fn search(&mut self, pos: usize, current: usize) {
+        // The first step brings in the starting room, whatever it is.
+        if self.options.connectivity_pruning
+            && self.labels.len().max(1) + self.moves_after[pos] < self.room_count
+        {
+            return;
+        }
+        self.nodes_expanded += 1;
+        if self.nodes_expanded > self.options.max_nodes {
+            self.aborted = true;
+            return;
+        }
+
+        let Some(&step) = self.steps.get(pos) else {
+            if self.labels.len() == self.room_count {
+                self.candidates.push(self.build_map());
+                self.partial_maps.push(PartialMap {
+                    labels: self.labels.clone(),
+                    edges: self.edges.clone(),
+                });
+                self.traces.push(self.trace.clone());
+                if self.candidates.len() >= self.options.max_candidates {
+                    self.aborted = true;
+                }
+            }
+            return;
+        };
+
+        match step {
+            Step::Start { label, reset } => {
+                // A new plan finds every room unmarked again.
+                let marks = if reset {
+                    None
+                } else {
+                    Some(std::mem::replace(
+                        &mut self.marks,
+                        vec![None; self.labels.len()],
+                    ))
+                };
+                if self.labels.is_empty() {
+                    self.push_room(label);
+                    let bound = self.bind_class(0, pos);
+                    self.descend(pos, 0);
+                    self.unbind_class(bound);
+                    self.pop_room();
+                } else if self.label(0) == label && self.fits_class(0, pos) {
+                    let bound = self.bind_class(0, pos);
+                    self.descend(pos, 0);
+                    self.unbind_class(bound);
+                }
+                if let Some(marks) = marks {
+                    self.marks = marks;
+                }
+            }
+            Step::Mark { label } => {
+                let unmarked = self.marks[current].replace(label);
+                self.descend(pos, current);
+                self.marks[current] = unmarked;
+            }
+            Step::Move { door, label } => {
+                if let Some(next) = self.edges[current][door] {
+                    if self.label(next) == label && self.fits_class(next, pos) {
+                        let bound = self.bind_class(next, pos);
+                        self.descend(pos, next);
+                        self.unbind_class(bound);
+                    }
+                    return;
+                }
+
+                let mut next_room_candidates: Vec<usize> = (0..self.labels.len())
+                    .filter(|&room| self.label(room) == label)
+                    .collect();
+                if self.labels.len() < self.room_count {
+                    next_room_candidates.push(self.labels.len());
+                }
+                next_room_candidates.shuffle(self.rng);
+
+                for next in next_room_candidates {
+                    if self.options.fingerprint_pruning && !self.fits_fingerprint(next, pos) {
+                        continue;
+                    }
+                    if !self.fits_class(next, pos) {
+                        continue;
+                    }
+                    // A room seen for the first time can't have been marked yet, so it shows
+                    // its own label.
+                    let is_new_room = next == self.labels.len();
+                    if is_new_room {
+                        self.push_room(label);
+                    }
+                    self.edges[current][door] = Some(next);
+                    let bound = self.bind_class(next, pos);
+
+                    if self.can_complete(current) && self.can_complete(next) {
+                        self.descend(pos, next);
+                    }
+
+                    self.unbind_class(bound);
+                    self.edges[current][door] = None;
+                    if is_new_room {
+                        self.pop_room();
+                    }
+                    if self.aborted {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    /// The label `room` shows now: its mark if the current plan marked it, or its own.
+    fn label(&self, room: usize) -> usize {
+        self.marks[room].unwrap_or(self.labels[room])
+    }
+
+    fn push_room(&mut self, label: usize) {
+        self.labels.push(label);
+        self.marks.push(None);
+        self.edges.push([None; DOORS]);
+        self.neighbor_labels.push([None; DOORS]);
+    }
+
+    fn pop_room(&mut self) {
+        self.labels.pop();
+        self.marks.pop();
+        self.edges.pop();
+        self.neighbor_labels.pop();
+    }
+
+    /// Searches on from the step after `pos`, which was in `room`.
+    fn descend(&mut self, pos: usize, room: usize) {
+        let learned = self.learn_neighbors(room, pos);
+        self.trace.push(room);
+        self.search(pos + 1, room);
+        self.trace.pop();
+        self.forget_neighbors(room, learned);
+    }
+
+    /// Adds the labels observed from `pos` to the neighbor labels of `room`. Returns the doors
+    /// newly learned as a bitmask, for [`Search::forget_neighbors`] to undo.
+    fn learn_neighbors(&mut self, room: usize, pos: usize) -> u8 {
+        let mut learned = 0;
+        for (door, observed) in self.fingerprints[pos].iter().enumerate() {
+            let known = &mut self.neighbor_labels[room][door];
+            if known.is_none() && observed.is_some() {
+                *known = *observed;
+                learned |= 1 << door;
+            }
+        }
+        learned
+    }
+
+    fn forget_neighbors(&mut self, room: usize, learned: u8) {
+        for door in (0..DOORS).filter(|door| learned & (1 << door) != 0) {
+            self.neighbor_labels[room][door] = None;
+        }
+    }
+
+    /// Whether `room` shows the labels observed from `pos` behind its doors, both through the
+    /// doors known so far and through those other positions in `room` looked through.
+    fn fits_fingerprint(&self, room: usize, pos: usize) -> bool {
+        let (Some(room_edges), Some(neighbors)) =
+            (self.edges.get(room), self.neighbor_labels.get(room))
+        else {
+            return true;
+        };
+        self.fingerprints[pos]
+            .iter()
+            .zip(room_edges)
+            .zip(neighbors)
+            .all(|((observed, target), seen)| match observed {
+                Some(label) => {
+                    target.is_none_or(|target| self.labels[target] == *label)
+                        && seen.is_none_or(|seen| seen == *label)
+                }
+                None => true,
+            })
+    }
+
+    /// Whether the position `pos` may be `room`: a class stays in one room, and a room holds
+    /// at most one class.
+    fn fits_class(&self, room: usize, pos: usize) -> bool {
+        let Some(class) = self.classes.get(pos).copied().flatten() else {
+            return true;
+        };
+        match self.class_rooms.get(&class) {
+            Some(&bound) => bound == room,
+            None => !self.room_classes.contains_key(&room),
+        }
+    }
+
+    /// Ties the class of `pos` to `room` if it isn't yet. Returns the class newly tied, for
+    /// [`Search::unbind_class`] to undo.
+    fn bind_class(&mut self, room: usize, pos: usize) -> Option<usize> {
+        let class = self.classes.get(pos).copied().flatten()?;
+        if self.class_rooms.contains_key(&class) {
+            return None;
+        }
+        self.class_rooms.insert(class, room);
+        self.room_classes.insert(room, class);
+        Some(class)
+    }
+
+    fn unbind_class(&mut self, class: Option<usize>) {
+        if let Some(room) = class.and_then(|class| self.class_rooms.remove(&class)) {
+            self.room_classes.remove(&room);
+        }
+    }
+
+    fn count_doors(&self, from: usize, to: usize) -> usize {
+        self.edges[from]
+            .iter()
+            .filter(|&&target| target == Some(to))
+            .count()
+    }
+
+    /// Every door leading into `room` needs a door of `room` leading back. Checks that the
+    /// still-unknown doors of `room` are enough to cover the missing return doors.
+    fn can_complete(&self, room: usize) -> bool {
+        let unknown = self.edges[room].iter().filter(|e| e.is_none()).count();
+        let missing: usize = (0..self.labels.len())
+            .filter(|&other| other != room)
+            .map(|other| {
+                self.count_doors(other, room)
+                    .saturating_sub(self.count_doors(room, other))
+            })
+            .sum();
+        missing <= unknown
+    }
+
+    fn build_map(&self) -> Map {
+        complete_map(&self.labels, &self.edges)
+    }
+}
+
+/// The map with the rooms `labels` and the room behind each door in `edges`, with its unknown
+/// doors completed one way that keeps every connection two-way.
+pub(crate) fn complete_map(labels: &[usize], edges: &[[Option<usize>; DOORS]]) -> Map {
+    let room_count = labels.len();
+    let mut edges = edges.to_vec();
+
+    // Point unknown doors back along unmatched incoming doors, the rest become self-loops.
+    for room in 0..room_count {
+        for other in 0..room_count {
+            if other == room {
+                continue;
+            }
+            let incoming = edges[other].iter().filter(|&&t| t == Some(room)).count();
+            let outgoing = edges[room].iter().filter(|&&t| t == Some(other)).count();
+            for _ in outgoing..incoming {
+                let door = edges[room].iter().position(|t| t.is_none()).unwrap();
+                edges[room][door] = Some(other);
+            }
+        }
+        for target in edges[room].iter_mut() {
+            target.get_or_insert(room);
+        }
+    }
+
+    let doors_between = |from: usize, to: usize| -> Vec<usize> {
+        (0..DOORS)
+            .filter(|&door| edges[from][door] == Some(to))
+            .collect()
+    };
+
+    let mut builder = MapBuilder::new().starting(0);
+    for (room, &label) in labels.iter().enumerate() {
+        builder = builder.room(room, label);
+    }
+    for room in 0..room_count {
+        for chunk in doors_between(room, room).chunks(2) {
+            builder = builder.connect(room, chunk[0], room, *chunk.last().unwrap());
+        }
+        for other in room + 1..room_count {
+            for (door, reverse_door) in doors_between(room, other)
+                .into_iter()
+                .zip(doors_between(other, room))
+            {
+                builder = builder.connect(room, door, other, reverse_door);
+            }
+        }
+    }
+
+    builder
+        .build()
+        .expect("completed doors always form a valid map")
+}
+
+impl Reconstruction {
+    /// Drops candidates with rooms unreachable from the starting room, along with their partial
+    /// maps and positions.
+    pub fn retain_connected(&mut self) {
+        let candidates = std::mem::take(&mut self.candidates);
+        let partial_maps = std::mem::take(&mut self.partial_maps);
+        let positions = std::mem::take(&mut self.positions);
+        for ((map, partial), positions) in candidates.into_iter().zip(partial_maps).zip(positions) {
+            if is_connected(&map) {
+                self.candidates.push(map);
+                self.partial_maps.push(partial);
+                self.positions.push(positions);
+            }
+        }
+    }
+}
+
+/// Finds maps with `room_count` rooms consistent with the observed label sequences.
+///
+/// `results[i]` are the labels observed while walking `plans[i]`, including the starting room.
+pub fn reconstruct(
+    plans: &[String],
+    results: &[Vec<usize>],
+    room_count: usize,
+    options: &ReconstructOptions,
+    rng: &mut impl Rng,
+) -> Result<Reconstruction> {
+    let steps = build_steps(plans, results, options.convention)?;
+    let fingerprints = fingerprints(&steps);
+    let moves_after = moves_after(&steps);
+    let (classes, room_counts) = match options.refine_depth {
+        Some(_) if has_marks(&steps) => bail!("Refinement doesn't support plans that mark rooms"),
+        Some(depth) => (observation_classes(&steps, depth), 1..=room_count),
+        None => (Vec::new(), room_count..=room_count),
+    };
+
+    let mut reconstruction = Reconstruction {
+        candidates: Vec::new(),
+        partial_maps: Vec::new(),
+        positions: Vec::new(),
+        nodes_expanded: 0,
+        exhaustive: true,
+    };
+    for room_count in room_counts {
+        let mut search = Search {
+            fingerprints: fingerprints.clone(),
+            moves_after: moves_after.clone(),
+            classes: classes.clone(),
+            steps: &steps,
+            room_count,
+            options,
+            rng: &mut *rng,
+            labels: Vec::new(),
+            marks: Vec::new(),
+            edges: Vec::new(),
+            neighbor_labels: Vec::new(),
+            class_rooms: HashMap::new(),
+            room_classes: HashMap::new(),
+            trace: Vec::with_capacity(steps.len()),
+            candidates: Vec::new(),
+            partial_maps: Vec::new(),
+            traces: Vec::new(),
+            nodes_expanded: 0,
+            aborted: false,
+        };
+        search.search(0, 0);
+
+        reconstruction.exhaustive &= !search.aborted;
+        reconstruction.nodes_expanded += search.nodes_expanded;
+        reconstruction.candidates = search.candidates;
+        reconstruction.partial_maps = search.partial_maps;
+        reconstruction.positions = search
+            .traces
+            .into_iter()
+            .map(|trace| split_trace(trace, results))
+            .collect();
+        if !reconstruction.candidates.is_empty() {
+            break;
+        }
+    }
+    Ok(reconstruction)
+}
+
+/// Splits the rooms of every step into one list per plan. Every plan has one step per label,
+/// whichever labels its convention includes.
+fn split_trace(trace: Vec<usize>, results: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    let mut rooms = trace.into_iter();
+    results
+        .iter()
+        .map(|labels| rooms.by_ref().take(labels.len()).collect())
+        .collect()
+}
+
+/// [`reconstruct`] over `(plan, labels)` pairs, the shape [`crate::check_consistency`] takes.
+///
+/// All plans are searched jointly: each one starts in the same starting room, and plans that
+/// share a prefix walk through the same rooms along it.
+pub fn reconstruct_pairs(
+    observations: &[(String, Vec<usize>)],
+    room_count: usize,
+    options: &ReconstructOptions,
+    rng: &mut impl Rng,
+) -> Result<Reconstruction> {
+    let (plans, results): (Vec<String>, Vec<Vec<usize>>) = observations.iter().cloned().unzip();
+    reconstruct(&plans, &results, room_count, options, rng)
+}
+
+/// [`reconstruct`], reported as which doors the candidates agree on rather than as the
+/// candidates themselves. Fails if no map fits the observations.
+pub fn reconstruct_partial(
+    plans: &[String],
+    results: &[Vec<usize>],
+    room_count: usize,
+    options: &ReconstructOptions,
+    rng: &mut impl Rng,
+) -> Result<PartialSolution> {
+    let reconstruction = reconstruct(plans, results, room_count, options, rng)?;
+    if reconstruction.partial_maps.is_empty() {
+        bail!("No map of {} rooms fits the observations", room_count);
+    }
+    Ok(PartialSolution::from_partial_maps(
+        &reconstruction.partial_maps,
+        reconstruction.exhaustive,
+    ))
+}
+
+/// [`reconstruct`] over a recorded [`ObservationSet`].
+pub fn reconstruct_observations(
+    observations: &ObservationSet,
+    room_count: usize,
+    options: &ReconstructOptions,
+    rng: &mut impl Rng,
+) -> Result<Reconstruction> {
+    reconstruct(
+        &observations.plans(),
+        &observations.results(),
+        room_count,
+        options,
+        rng,
+    )
+}
+
+/// Checks that walking every plan over `map` yields exactly the observed labels.
+pub fn verify_against_observations(map: &Map, plans: &[String], results: &[Vec<usize>]) -> bool {
+    plans.len() == results.len()
+        && plans
+            .iter()
+            .zip(results)
+            .all(|(plan, labels)| map.walk(plan).is_ok_and(|observed| &observed == labels))
+}
+
+#[cfg(test)]
+mod tests {
+    use icfpc2025_common::{AedificiumClient, ObservationConvention};
+    use icfpc2025_simulator::{GenerationMode, Simulator};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    /// Two rooms joined by doors 0, all other doors are self-loops.
+    fn two_room_map() -> Map {
+        let mut builder = MapBuilder::new().room(0, 0).room(1, 1).connect(0, 0, 1, 0);
+        for room in 0..2 {
+            for door in 1..DOORS {
+                builder = builder.connect(room, door, room, door);
+            }
+        }
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn test_reconstruct_two_rooms() {
+        let plans = vec!["0123450".to_string(), "012345".to_string()];
+        let results = vec![vec![0, 1, 1, 1, 1, 1, 1, 0], vec![0, 1, 1, 1, 1, 1, 1]];
+        assert!(verify_against_observations(
+            &two_room_map(),
+            &plans,
+            &results
+        ));
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let reconstruction = reconstruct(
+            &plans,
+            &results,
+            2,
+            &ReconstructOptions::default(),
+            &mut rng,
+        )
+        .unwrap();
+
+        assert!(reconstruction.exhaustive);
+        assert!(!reconstruction.candidates.is_empty());
+        for candidate in &reconstruction.candidates {
+            assert_eq!(candidate.rooms, vec![0, 1]);
+            assert!(verify_against_observations(candidate, &plans, &results));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reset_branches_from_start() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 4).unwrap();
+        let branched = vec!["01R23R4".to_string()];
+        let separate: Vec<String> = ["01", "23", "4"].map(String::from).to_vec();
+        let joined = simulator.explore(branched.clone()).await.unwrap();
+        let cost = simulator.get_library_info().1;
+        simulator.reset_exploration();
+        let apart = simulator.explore(separate.clone()).await.unwrap();
+
+        assert_eq!(joined.results[0], apart.results.concat());
+        assert_eq!(cost, simulator.get_library_info().1);
+
+        let options = ReconstructOptions {
+            max_candidates: usize::MAX,
+            ..ReconstructOptions::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let from_branched = reconstruct(&branched, &joined.results, 3, &options, &mut rng).unwrap();
+        let from_separate = reconstruct(&separate, &apart.results, 3, &options, &mut rng).unwrap();
+        assert!(from_branched.exhaustive && from_separate.exhaustive);
+        assert_eq!(
+            from_branched.candidates.len(),
+            from_separate.candidates.len()
+        );
+        for map in &from_branched.candidates {
+            assert!(verify_against_observations(map, &branched, &joined.results));
+            assert!(verify_against_observations(map, &separate, &apart.results));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_positions_follow_candidate_edges() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 2).unwrap();
+        let plans = vec!["0123".to_string(), "54R32".to_string(), "".to_string()];
+        let results = simulator.explore(plans.clone()).await.unwrap().results;
+        let options = ReconstructOptions {
+            max_candidates: usize::MAX,
+            ..ReconstructOptions::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        let reconstruction = reconstruct(&plans, &results, 3, &options, &mut rng).unwrap();
+        assert!(!reconstruction.candidates.is_empty());
+        assert_eq!(
+            reconstruction.positions.len(),
+            reconstruction.candidates.len()
+        );
+
+        for (map, positions) in reconstruction
+            .candidates
+            .iter()
+            .zip(&reconstruction.positions)
+        {
+            let doors = map.doors();
+            for ((plan, labels), rooms) in plans.iter().zip(&results).zip(positions) {
+                assert_eq!(rooms.len(), labels.len());
+                assert_eq!(rooms[0], map.starting_room);
+                for (k, (step, pair)) in parse_plan_steps(plan)
+                    .unwrap()
+                    .into_iter()
+                    .zip(rooms.windows(2))
+                    .enumerate()
+                {
+                    let expected = match step {
+                        PlanStep::Door(door) => doors[pair[0]][door],
+                        PlanStep::Reset => Some(map.starting_room),
+                        PlanStep::Mark(_) => Some(pair[0]),
+                    };
+                    assert_eq!(Some(pair[1]), expected, "{:?} step {}", plan, k);
+                }
+                for (&room, &label) in rooms.iter().zip(labels) {
+                    assert_eq!(map.rooms[room], label);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_partial_maps_show_observed_neighbor_labels() {
+        let mut simulator = Simulator::with_mode_seed(6, GenerationMode::Regular, 3).unwrap();
+        let mut rng = StdRng::seed_from_u64(3);
+        let plans: Vec<String> = (0..4)
+            .map(|_| {
+                (0..18)
+                    .map(|_| rng.random_range(0..DOORS).to_string())
+                    .collect()
+            })
+            .collect();
+        let results = simulator.explore(plans.clone()).await.unwrap().results;
+        let reconstruction = reconstruct(
+            &plans,
+            &results,
+            6,
+            &ReconstructOptions::default(),
+            &mut rng,
+        )
+        .unwrap();
+        assert!(!reconstruction.partial_maps.is_empty());
+
+        for (partial, positions) in reconstruction
+            .partial_maps
+            .iter()
+            .zip(&reconstruction.positions)
+        {
+            for ((plan, labels), rooms) in plans.iter().zip(&results).zip(positions) {
+                for (k, door) in plan.bytes().map(|b| (b - b'0') as usize).enumerate() {
+                    let seen = partial.neighbor_labels(rooms[k])[door];
+                    assert_eq!(seen, Some(labels[k + 1] as u8), "{:?} step {}", plan, k);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_two_plans_determine_what_neither_does_alone() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 0).unwrap();
+        let plans = vec!["434124045".to_string(), "140104211".to_string()];
+        let results = simulator.explore(plans.clone()).await.unwrap().results;
+        let pairs: Vec<(String, Vec<usize>)> = plans.into_iter().zip(results).collect();
+
+        let options = ReconstructOptions {
+            max_candidates: usize::MAX,
+            ..ReconstructOptions::default()
+        };
+        let mut rng = StdRng::seed_from_u64(0);
+        for alone in pairs.chunks(1) {
+            let reconstruction = reconstruct_pairs(alone, 3, &options, &mut rng).unwrap();
+            assert!(reconstruction.candidates.len() > 1);
+        }
+        let joint = reconstruct_pairs(&pairs, 3, &options, &mut rng).unwrap();
+        assert!(joint.exhaustive);
+        assert_eq!(joint.candidates.len(), 1);
+        assert!(
+            simulator
+                .guess(joint.candidates[0].clone())
+                .await
+                .unwrap()
+                .correct
+        );
+    }
+
+    #[tokio::test]
+    async fn test_single_room() {
+        let mut simulator = Simulator::with_mode_seed(1, GenerationMode::Random, 0).unwrap();
+        let plans = vec!["012345".to_string(), "".to_string()];
+        let results = simulator.explore(plans.clone()).await.unwrap().results;
+        assert_eq!(results, vec![vec![0; 7], vec![0]]);
+
+        let reconstruction = reconstruct(
+            &plans,
+            &results,
+            1,
+            &ReconstructOptions::default(),
+            &mut StdRng::seed_from_u64(0),
+        )
+        .unwrap();
+        assert!(reconstruction.exhaustive);
+        let [map] = reconstruction.candidates.as_slice() else {
+            panic!("{:?}", reconstruction.candidates);
+        };
+        assert_eq!(map.doors(), vec![[Some(0); DOORS]]);
+        assert!(simulator.guess(map.clone()).await.unwrap().correct);
+
+        // A second label can't be a single room.
+        let reconstruction = reconstruct(
+            &["0".to_string()],
+            &[vec![0, 1]],
+            1,
+            &ReconstructOptions::default(),
+            &mut StdRng::seed_from_u64(0),
+        )
+        .unwrap();
+        assert!(reconstruction.candidates.is_empty());
+    }
+
+    #[test]
+    fn test_partial_solution_leaves_one_door_open() {
+        // Two rooms labelled 1. Door 1 of the start must lead to the other room, since that is
+        // the only way to bring it in, but door 1 back from there may be a self-loop or lead
+        // back to the start.
+        let plans = vec!["11".to_string()];
+        let results = vec![vec![1, 1, 1]];
+        let solution = reconstruct_partial(
+            &plans,
+            &results,
+            2,
+            &ReconstructOptions::default(),
+            &mut StdRng::seed_from_u64(0),
+        )
+        .unwrap();
+
+        assert!(solution.exhaustive);
+        assert_eq!(solution.fixed_edges, HashMap::from([((0, 1), 1)]));
+        assert_eq!(solution.ambiguous, HashMap::from([((1, 1), vec![0, 1])]));
+        assert!(!solution.is_determined());
+
+        assert!(reconstruct_partial(
+            &plans,
+            &[vec![1, 1, 0]],
+            1,
+            &ReconstructOptions::default(),
+            &mut StdRng::seed_from_u64(0),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_reconstruct_rejects_mismatched_lengths() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let options = ReconstructOptions::default();
+        let plans = vec!["01".to_string()];
+        assert!(reconstruct(&plans, &[vec![0, 1]], 2, &options, &mut rng).is_err());
+        assert!(reconstruct(&plans, &[], 2, &options, &mut rng).is_err());
+        assert!(reconstruct(&["7".to_string()], &[vec![0, 1]], 2, &options, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_reconstruct_respects_reciprocity() {
+        // From room 0, doors 0 and 1 both lead to a room labelled 1. With two rooms, room 1
+        // must then spend two of its doors leading back to room 0.
+        let plans = vec!["0".to_string(), "1".to_string()];
+        let results = vec![vec![0, 1], vec![0, 1]];
+        let mut rng = StdRng::seed_from_u64(0);
+        let reconstruction = reconstruct(
+            &plans,
+            &results,
+            2,
+            &ReconstructOptions::default(),
+            &mut rng,
+        )
+        .unwrap();
+
+        assert_eq!(reconstruction.candidates.len(), 1);
+        let map = &reconstruction.candidates[0];
+        let between = map
+            .connections
+            .iter()
+            .filter(|c| c.from.room != c.to.room)
+            .count();
+        assert_eq!(between, 2);
+    }
+
+    #[test]
+    fn test_marks_tell_rooms_with_the_same_label_apart() {
+        // Two rooms labelled 0, joined by their doors 0; every other door loops back.
+        let mut builder = MapBuilder::new().room(0, 0).room(1, 0).connect(0, 0, 1, 0);
+        for room in 0..2 {
+            for door in 1..DOORS {
+                builder = builder.connect(room, door, room, door);
+            }
+        }
+        let truth = builder.build().unwrap();
+        let reconstruct_walks = |plan: &str| {
+            let plans = vec![plan.to_string()];
+            let results = vec![truth.walk(plan).unwrap()];
+            let mut rng = StdRng::seed_from_u64(0);
+            reconstruct(
+                &plans,
+                &results,
+                2,
+                &ReconstructOptions::default(),
+                &mut rng,
+            )
+            .unwrap()
+        };
+
+        // Unmarked, every door shows a 0 wherever it leads.
+        let unmarked = reconstruct_walks("123450123450");
+        assert!(unmarked.candidates.len() > 1);
+
+        // Marking the starting room shows which doors leave it, both on the way out and back.
+        let marked = reconstruct_walks("[1]123450123450");
+        assert!(marked.exhaustive);
+        assert_eq!(marked.candidates.len(), 1);
+        assert_eq!(marked.candidates[0].rooms, truth.rooms);
+        assert_eq!(marked.candidates[0].doors(), truth.doors());
+        assert_eq!(
+            marked.positions[0][0],
+            vec![0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fingerprint_pruning_expands_fewer_nodes() {
+        let mut simulator = Simulator::with_mode_seed(4, GenerationMode::Regular, 5).unwrap();
+        let mut rng = StdRng::seed_from_u64(5);
+        let plans: Vec<String> = (0..3)
+            .map(|_| {
+                (0..12)
+                    .map(|_| rng.random_range(0..DOORS).to_string())
+                    .collect()
+            })
+            .collect();
+        let results = simulator.explore(plans.clone()).await.unwrap().results;
+
+        let run = |fingerprint_pruning| {
+            let options = ReconstructOptions {
+                max_candidates: usize::MAX,
+                fingerprint_pruning,
+                ..ReconstructOptions::default()
+            };
+            let mut rng = StdRng::seed_from_u64(0);
+            reconstruct(&plans, &results, 4, &options, &mut rng).unwrap()
+        };
+        let pruned = run(true);
+        let full = run(false);
+
+        assert!(pruned.exhaustive && full.exhaustive);
+        assert_eq!(pruned.candidates.len(), full.candidates.len());
+        assert!(!pruned.candidates.is_empty());
+        assert!(
+            pruned.nodes_expanded < full.nodes_expanded,
+            "{} vs {}",
+            pruned.nodes_expanded,
+            full.nodes_expanded
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connectivity_pruning_expands_fewer_nodes() {
+        // Short plans through many rooms: most branches reuse rooms until too few doors are left.
+        let mut simulator = Simulator::with_mode_seed(6, GenerationMode::Regular, 2).unwrap();
+        let mut rng = StdRng::seed_from_u64(2);
+        let plans: Vec<String> = (0..2)
+            .map(|_| {
+                (0..5)
+                    .map(|_| rng.random_range(0..DOORS).to_string())
+                    .collect()
+            })
+            .collect();
+        let results = simulator.explore(plans.clone()).await.unwrap().results;
+
+        let run = |connectivity_pruning| {
+            let options = ReconstructOptions {
+                max_candidates: usize::MAX,
+                connectivity_pruning,
+                ..ReconstructOptions::default()
+            };
+            let mut rng = StdRng::seed_from_u64(0);
+            reconstruct(&plans, &results, 6, &options, &mut rng).unwrap()
+        };
+        let pruned = run(true);
+        let full = run(false);
+
+        assert!(pruned.exhaustive && full.exhaustive);
+        assert_eq!(pruned.candidates.len(), full.candidates.len());
+        assert!(!pruned.candidates.is_empty());
+        assert!(
+            pruned.nodes_expanded < full.nodes_expanded,
+            "{} vs {}",
+            pruned.nodes_expanded,
+            full.nodes_expanded
+        );
+
+        // Exactly enough doors: the starting room and one through door 0.
+        let mut rng = StdRng::seed_from_u64(0);
+        let tight = reconstruct(
+            &["0".to_string()],
+            &[vec![0, 1]],
+            2,
+            &ReconstructOptions::default(),
+            &mut rng,
+        )
+        .unwrap();
+        assert!(!tight.candidates.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_observation_conventions_end_to_end() {
+        let plans: Vec<String> = ["0123", "5401", "", "3322"].map(String::from).to_vec();
+        for convention in [
+            ObservationConvention::IncludingFinal,
+            ObservationConvention::ExcludingFinal,
+        ] {
+            let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 1).unwrap();
+            simulator.set_observation_convention(convention);
+            let results = simulator.explore(plans.clone()).await.unwrap().results;
+            for (plan, labels) in plans.iter().zip(&results) {
+                assert_eq!(labels.len(), convention.expected_len(plan.len()));
+            }
+
+            let options = ReconstructOptions {
+                convention,
+                max_candidates: usize::MAX,
+                ..ReconstructOptions::default()
+            };
+            let mut rng = StdRng::seed_from_u64(0);
+            let reconstruction = reconstruct(&plans, &results, 3, &options, &mut rng).unwrap();
+            assert!(reconstruction.exhaustive);
+            assert!(!reconstruction.candidates.is_empty());
+            let (walked, observed): (Vec<String>, Vec<Vec<usize>>) = plans
+                .iter()
+                .zip(&results)
+                .filter_map(|(plan, labels)| convention.normalize(plan, labels).unwrap())
+                .map(|(plan, labels)| (plan.to_string(), labels.to_vec()))
+                .unzip();
+            for map in &reconstruction.candidates {
+                assert!(verify_against_observations(map, &walked, &observed));
+            }
+
+            // Reading the results under the other convention is an error, not a bad map.
+            let other = match convention {
+                ObservationConvention::IncludingFinal => ObservationConvention::ExcludingFinal,
+                ObservationConvention::ExcludingFinal => ObservationConvention::IncludingFinal,
+            };
+            let options = ReconstructOptions {
+                convention: other,
+                ..options
+            };
+            let error = reconstruct(&plans, &results, 3, &options, &mut rng).unwrap_err();
+            assert!(error.to_string().contains("labels under"), "{}", error);
+        }
+    }
+
+    /// Two copies of `base`, with every other connection crossing over between the copies.
+    /// Room `r` of the copy `c` becomes room `2 * r + c`.
+    fn doubled(base: &Map) -> Map {
+        let mut builder = MapBuilder::new().starting(2 * base.starting_room);
+        for (room, &label) in base.rooms.iter().enumerate() {
+            builder = builder.room(2 * room, label).room(2 * room + 1, label);
+        }
+        for (i, conn) in base.connections.iter().enumerate() {
+            let crossed = i % 2;
+            for copy in 0..2 {
+                builder = builder.connect(
+                    2 * conn.from.room + copy,
+                    conn.from.door,
+                    2 * conn.to.room + (copy ^ crossed),
+                    conn.to.door,
+                );
+            }
+        }
+        builder.build().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_refinement_collapses_doubled_rooms() {
+        let mut base = Simulator::with_mode_seed(3, GenerationMode::Regular, 1)
+            .unwrap()
+            .get_actual_map();
+        base.rooms = vec![0, 1, 2];
+        let library = doubled(&base);
+        let mut simulator = Simulator::from_map(&library).unwrap();
+        simulator.set_charge_start_observation(false);
+        let plans: Vec<String> = (0..DOORS * DOORS)
+            .map(|i| format!("{}{}", i / DOORS, i % DOORS))
+            .collect();
+        let results = simulator.explore(plans.clone()).await.unwrap().results;
+
+        // The true room of every position, in the order of the search steps.
+        let steps = build_steps(&plans, &results, ObservationConvention::default()).unwrap();
+        let mut edges = vec![[0; DOORS]; library.rooms.len()];
+        for conn in &library.connections {
+            edges[conn.from.room][conn.from.door] = conn.to.room;
+            edges[conn.to.room][conn.to.door] = conn.from.room;
+        }
+        let mut rooms = Vec::new();
+        for plan in &plans {
+            let mut current = library.starting_room;
+            rooms.push(current);
+            for door in parse_plan(plan).unwrap() {
+                current = edges[current][door];
+                rooms.push(current);
+            }
+        }
+
+        let classes = observation_classes(&steps, 1);
+        let classified: Vec<(usize, usize)> = classes
+            .iter()
+            .zip(&rooms)
+            .filter_map(|(class, &room)| class.map(|class| (class, room)))
+            .collect();
+        for &(class, room) in &classified {
+            for &(other_class, other_room) in &classified {
+                assert_eq!(class == other_class, room / 2 == other_room / 2);
+            }
+        }
+        let mut copies: Vec<usize> = classified.iter().map(|&(_, room)| room).collect();
+        copies.sort();
+        copies.dedup();
+        assert!(copies.len() > 3, "both copies of some room are classified");
+
+        let run = |refine_depth| {
+            let options = ReconstructOptions {
+                refine_depth,
+                max_candidates: 1,
+                ..ReconstructOptions::default()
+            };
+            reconstruct(&plans, &results, 6, &options, &mut StdRng::seed_from_u64(0)).unwrap()
+        };
+        let refined = run(Some(1));
+        let map = &refined.candidates[0];
+        assert_eq!(map.rooms.len(), 3);
+        assert!(verify_against_observations(map, &plans, &results));
+        // Without refinement the search sticks to the room count it was given.
+        assert_eq!(run(None).candidates[0].rooms.len(), 6);
+    }
+
+    #[test]
+    fn test_retain_connected_drops_isolated_room() {
+        let connected = two_room_map();
+        let mut isolated = connected.clone();
+        isolated
+            .connections
+            .retain(|conn| conn.from.room == conn.to.room);
+        let partial = |map: &Map| PartialMap {
+            labels: map.rooms.clone(),
+            edges: vec![[None; DOORS]; map.rooms.len()],
+        };
+
+        let mut reconstruction = Reconstruction {
+            partial_maps: vec![partial(&isolated), partial(&connected)],
+            candidates: vec![isolated, connected.clone()],
+            positions: vec![Vec::new(), vec![vec![0]]],
+            nodes_expanded: 0,
+            exhaustive: true,
+        };
+        reconstruction.retain_connected();
+        assert_eq!(reconstruction.candidates.len(), 1);
+        assert_eq!(reconstruction.partial_maps.len(), 1);
+        assert_eq!(reconstruction.positions, vec![vec![vec![0]]]);
+        assert_eq!(
+            reconstruction.candidates[0].connections,
+            connected.connections
+        );
+    }
+}
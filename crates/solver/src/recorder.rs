@@ -0,0 +1,143 @@
+use anyhow::{Context, Result};
+use icfpc2025_common::{Map, Observation, ObservationSet};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::driver::ExploreOutcome;
+
+/// Where and under what name [`crate::driver::solve`] writes a [`RunReport`], as set in
+/// [`ExploreConfig::run_log`].
+///
+/// [`ExploreConfig::run_log`]: crate::driver::ExploreConfig::run_log
+#[derive(Debug, Clone)]
+pub struct RunLog {
+    pub path: PathBuf,
+    pub problem_name: String,
+    /// Seed of the offline library, if the run was offline.
+    pub seed: Option<u64>,
+}
+
+/// Everything one solve did, for archiving and diffing between runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunReport {
+    pub problem_name: String,
+    pub room_count: usize,
+    pub seed: Option<u64>,
+    /// Every explored plan with its labels, in exploration order.
+    pub observations: Vec<Observation>,
+    /// `None` if the solve failed before guessing.
+    pub map: Option<Map>,
+    pub correct: Option<bool>,
+    pub explores: usize,
+    pub doorways: usize,
+    #[serde(default)]
+    pub latency: LatencyStats,
+    /// Milliseconds since the Unix epoch.
+    pub started_at_ms: u64,
+    pub finished_at_ms: u64,
+    /// Why the solve failed, if it did.
+    pub error: Option<String>,
+}
+
+impl RunReport {
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&json)
+            .with_context(|| format!("invalid run report {}", path.display()))
+    }
+}
+
+/// How long the solve's requests took, in milliseconds of wall time.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencyStats {
+    pub explore_total_ms: u64,
+    pub explore_mean_ms: f64,
+    pub explore_max_ms: u64,
+    /// `None` if the solve failed before guessing.
+    pub guess_ms: Option<u64>,
+}
+
+impl LatencyStats {
+    fn record_explores(&mut self, latencies: &[Duration]) {
+        let total: Duration = latencies.iter().sum();
+        self.explore_total_ms = total.as_millis() as u64;
+        self.explore_mean_ms = if latencies.is_empty() {
+            0.0
+        } else {
+            total.as_secs_f64() * 1000.0 / latencies.len() as f64
+        };
+        self.explore_max_ms = latencies
+            .iter()
+            .max()
+            .map_or(0, |max| max.as_millis() as u64);
+    }
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// Accumulates a [`RunReport`] while a solve runs and writes it once the solve is over.
+#[derive(Debug)]
+pub(crate) struct RunRecorder {
+    path: PathBuf,
+    report: RunReport,
+}
+
+impl RunRecorder {
+    pub(crate) fn start(log: &RunLog, room_count: usize) -> Self {
+        let started_at_ms = now_ms();
+        Self {
+            path: log.path.clone(),
+            report: RunReport {
+                problem_name: log.problem_name.clone(),
+                room_count,
+                seed: log.seed,
+                observations: Vec::new(),
+                map: None,
+                correct: None,
+                explores: 0,
+                doorways: 0,
+                latency: LatencyStats::default(),
+                started_at_ms,
+                finished_at_ms: started_at_ms,
+                error: None,
+            },
+        }
+    }
+
+    pub(crate) fn explored(&mut self, outcome: &ExploreOutcome) -> Result<()> {
+        self.report.observations =
+            ObservationSet::from_results(&outcome.plans, &outcome.results)?.observations;
+        self.report.explores = outcome.explore_calls;
+        self.report.doorways = outcome.doorways;
+        self.report
+            .latency
+            .record_explores(&outcome.explore_latencies);
+        Ok(())
+    }
+
+    pub(crate) fn guessed(&mut self, map: &Map, correct: bool, elapsed: Duration) {
+        self.report.map = Some(map.clone());
+        self.report.correct = Some(correct);
+        self.report.latency.guess_ms = Some(elapsed.as_millis() as u64);
+    }
+
+    /// Writes the report, noting `error` if the solve failed.
+    pub(crate) fn finish(mut self, error: Option<&anyhow::Error>) -> Result<RunReport> {
+        self.report.finished_at_ms = now_ms();
+        self.report.error = error.map(|e| format!("{:#}", e));
+        self.report.save(&self.path)?;
+        Ok(self.report)
+    }
+}
@@ -0,0 +1,116 @@
+use crate::reconstruct::verify_against_observations;
+use icfpc2025_common::{Map, ObservationSet, RoomDoor, DOORS};
+use std::collections::HashMap;
+
+/// How many connection ends use each door. A connection from a door to itself uses it once.
+fn door_uses(map: &Map) -> HashMap<RoomDoor, usize> {
+    let mut uses = HashMap::new();
+    for conn in &map.connections {
+        *uses.entry(conn.from.clone()).or_insert(0) += 1;
+        if conn.to != conn.from {
+            *uses.entry(conn.to.clone()).or_insert(0) += 1;
+        }
+    }
+    uses
+}
+
+/// Whether every door of every room is connected exactly once, as in the contest's libraries.
+pub fn is_complete(map: &Map) -> bool {
+    let uses = door_uses(map);
+    let doors = map.rooms.len() * DOORS;
+    uses.len() == doors
+        && uses
+            .iter()
+            .all(|(end, &count)| count == 1 && end.room < map.rooms.len() && end.door < DOORS)
+}
+
+/// Turns a map with a door connected twice into a complete one by moving a single connection
+/// end to a free door of the same room, keeping only repairs that still reproduce every
+/// observation.
+///
+/// Returns `map` itself if it is complete and reproduces the observations already, and `None`
+/// if no single such edit makes it so.
+pub fn repair_map(map: &Map, observations: &ObservationSet) -> Option<Map> {
+    let (plans, results) = (observations.plans(), observations.results());
+    let fits = |map: &Map| is_complete(map) && verify_against_observations(map, &plans, &results);
+    if fits(map) {
+        return Some(map.clone());
+    }
+
+    let uses = door_uses(map);
+    let free = |room: usize| {
+        (0..DOORS)
+            .map(move |door| RoomDoor { room, door })
+            .filter(|end| !uses.contains_key(end))
+    };
+    for (i, conn) in map.connections.iter().enumerate() {
+        for (end, is_from) in [(&conn.from, true), (&conn.to, false)] {
+            if uses.get(end).is_none_or(|&count| count < 2) || end.room >= map.rooms.len() {
+                continue;
+            }
+            for replacement in free(end.room) {
+                let mut repaired = map.clone();
+                let moved = &mut repaired.connections[i];
+                if is_from {
+                    moved.from = replacement;
+                } else {
+                    moved.to = replacement;
+                }
+                if fits(&repaired) {
+                    return Some(repaired);
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use icfpc2025_common::{AedificiumClient, EdgeSet};
+    use icfpc2025_simulator::{GenerationMode, Simulator};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_repair_misassigned_reverse_door() {
+        let mut simulator = Simulator::with_mode_seed(3, GenerationMode::Regular, 5).unwrap();
+        let truth = simulator.get_actual_map();
+        let plans: Vec<String> = ["0123450123", "5432105432", "1350241523", "4040515123"]
+            .map(String::from)
+            .to_vec();
+        let response = simulator.explore(plans.clone()).await.unwrap();
+        let observations = ObservationSet::from_explore(&plans, &response).unwrap();
+        assert!(is_complete(&truth));
+        assert_eq!(
+            repair_map(&truth, &observations).map(|map| EdgeSet::from(&map)),
+            Some(EdgeSet::from(&truth))
+        );
+
+        // Point the reverse end of a connection at a door of the same room that is already
+        // taken, leaving the door it should use free.
+        let i = truth
+            .connections
+            .iter()
+            .position(|c| c.from.room != c.to.room)
+            .unwrap();
+        let mut broken = truth.clone();
+        let to = broken.connections[i].to.clone();
+        broken.connections[i].to.door = (to.door + 1) % DOORS;
+        assert!(!is_complete(&broken));
+
+        let repaired = repair_map(&broken, &observations).unwrap();
+        assert!(is_complete(&repaired));
+        assert!(verify_against_observations(
+            &repaired,
+            &plans,
+            &response.results
+        ));
+        assert_eq!(repaired.connections[i].to, to);
+
+        // A wrong label can't be fixed by moving a door.
+        let mut mislabelled = broken.clone();
+        mislabelled.rooms[truth.starting_room] = (truth.rooms[truth.starting_room] + 1) % 4;
+        assert!(repair_map(&mislabelled, &observations).is_none());
+    }
+}
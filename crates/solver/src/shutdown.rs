@@ -0,0 +1,64 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Exit status after a second Ctrl-C, as a shell reports a process killed by SIGINT.
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Flag asking [`crate::solve`] to stop exploring and wrap up with what it has observed. Clones
+/// share the flag, so one can be handed to the config and another to whatever sets it.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown(Arc<AtomicBool>);
+
+impl Shutdown {
+    /// A flag nobody has set yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A flag set by the first SIGINT. Once it is set, Ctrl-C no longer kills the process, so
+    /// a second SIGINT exits right away for when wrapping up takes too long. Must be called
+    /// from within a tokio runtime.
+    pub fn on_ctrl_c() -> Self {
+        let shutdown = Self::new();
+        let flag = shutdown.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            flag.request();
+            if tokio::signal::ctrl_c().await.is_ok() {
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+        });
+        shutdown
+    }
+
+    pub fn request(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// [`crate::solve`] was shut down before guessing, with
+/// [`crate::ExploreConfig::guess_on_shutdown`] off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Interrupted {
+    pub explores: usize,
+    pub doorways: usize,
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "interrupted after {} explores and {} doorways, without guessing",
+            self.explores, self.doorways
+        )
+    }
+}
+
+impl std::error::Error for Interrupted {}
@@ -0,0 +1,54 @@
+use icfpc2025_common::ObservationSet;
+use std::collections::HashMap;
+
+/// How often each run of `window` consecutive labels was observed, over every position of
+/// every observation.
+///
+/// Rooms that share a label can only be told apart by what lies behind their doors, so windows
+/// that are rare (or missing) point at the walks that still carry information, while a
+/// histogram dominated by a few windows means the library looks alike from many positions.
+/// Observations shorter than `window` contribute nothing.
+pub fn label_signature(observations: &ObservationSet, window: usize) -> HashMap<Vec<u8>, usize> {
+    let mut histogram = HashMap::new();
+    if window == 0 {
+        return histogram;
+    }
+    for observation in observations.iter() {
+        for labels in observation.labels.windows(window) {
+            *histogram.entry(labels.to_vec()).or_insert(0) += 1;
+        }
+    }
+    histogram
+}
+
+#[cfg(test)]
+mod tests {
+    use icfpc2025_common::Observation;
+
+    use super::*;
+
+    #[test]
+    fn test_label_signature_counts_windows() {
+        // Two rooms labelled 0 and 1 joined by door 0, every other door a self-loop.
+        let observations: ObservationSet = [
+            Observation::new("000", vec![0, 1, 0, 1]),
+            Observation::new("01", vec![0, 1, 1]),
+            Observation::new("", vec![0]),
+        ]
+        .into_iter()
+        .collect();
+
+        let pairs = label_signature(&observations, 2);
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(pairs[&vec![0, 1]], 3);
+        assert_eq!(pairs[&vec![1, 0]], 1);
+        assert_eq!(pairs[&vec![1, 1]], 1);
+
+        let singles = label_signature(&observations, 1);
+        assert_eq!(singles[&vec![0]], 4);
+        assert_eq!(singles[&vec![1]], 4);
+
+        assert!(label_signature(&observations, 5).is_empty());
+        assert!(label_signature(&observations, 0).is_empty());
+    }
+}
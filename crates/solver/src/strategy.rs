@@ -0,0 +1,383 @@
+use anyhow::{bail, Result};
+use icfpc2025_common::DOORS;
+use rand::{seq::SliceRandom, Rng, RngCore};
+
+use crate::partial::{routes_over, PartialMap};
+
+/// Decides which plans to explore next, based on what is known about the library so far.
+pub trait ExploreStrategy {
+    /// Plans for the next explore call. `partial` is `None` until a map has been reconstructed.
+    fn next_plans(&mut self, partial: Option<&PartialMap>, rng: &mut dyn RngCore) -> Vec<String>;
+}
+
+/// Order in which plan generators take doors from [`PlanGenConfig::allowed_doors`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DoorOrdering {
+    /// Every step picks one of the allowed doors uniformly at random.
+    #[default]
+    Random,
+    /// Steps cycle through the allowed doors in the order given. [`Frontier`] takes unexplored
+    /// doors room by room in that order instead of shuffling them, and routes to them prefer
+    /// earlier doors, which makes for a systematic traversal trying doors in a fixed order.
+    Fixed,
+}
+
+/// Which doors [`RandomWalk`] and [`Frontier`] walk through, and in what order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlanGenConfig {
+    allowed_doors: Vec<u8>,
+    ordering: DoorOrdering,
+}
+
+impl Default for PlanGenConfig {
+    /// Every door, at random.
+    fn default() -> Self {
+        Self {
+            allowed_doors: (0..DOORS as u8).collect(),
+            ordering: DoorOrdering::Random,
+        }
+    }
+}
+
+impl PlanGenConfig {
+    /// Plans use only `allowed_doors`, not even on the way to an unexplored door; with none
+    /// allowed, plans are empty. Fails if a door isn't below [`DOORS`].
+    pub fn new(allowed_doors: Vec<u8>, ordering: DoorOrdering) -> Result<Self> {
+        if let Some(door) = allowed_doors.iter().find(|&&door| door as usize >= DOORS) {
+            bail!("Door {} does not exist; doors go up to {}", door, DOORS - 1);
+        }
+        Ok(Self {
+            allowed_doors,
+            ordering,
+        })
+    }
+
+    pub fn allowed_doors(&self) -> &[u8] {
+        &self.allowed_doors
+    }
+
+    pub fn ordering(&self) -> DoorOrdering {
+        self.ordering
+    }
+
+    /// A walk of `len` allowed doors.
+    fn doors(&self, len: usize, rng: &mut dyn RngCore) -> String {
+        let allowed = &self.allowed_doors;
+        if allowed.is_empty() {
+            return String::new();
+        }
+        (0..len)
+            .map(|step| match self.ordering {
+                DoorOrdering::Random => allowed[rng.random_range(0..allowed.len())],
+                DoorOrdering::Fixed => allowed[step % allowed.len()],
+            })
+            .map(|door| door.to_string())
+            .collect()
+    }
+}
+
+/// A [`RandomWalk`] or [`Frontier`] generating plans as `plan_gen` says, made by their
+/// `with_plan_gen`.
+#[derive(Debug, Clone)]
+pub struct WithPlanGen<S> {
+    pub inner: S,
+    pub plan_gen: PlanGenConfig,
+}
+
+/// Expected fraction of the `n * doors` door-transitions of an `n`-room library that `plan`
+/// walks through, without knowing the library.
+///
+/// Models every step as arriving in a uniformly random room, so the `k` uses of a door are
+/// expected to cover `n * (1 - (1 - 1/n)^k)` distinct rooms. Longer plans score higher, and so
+/// do plans spreading their steps over more doors. Characters that aren't a door below `doors`
+/// are ignored.
+pub fn estimate_coverage(plan: &str, n: usize, doors: usize) -> f64 {
+    if n == 0 || doors == 0 {
+        return 0.0;
+    }
+    let mut uses = vec![0; doors];
+    for door in plan.chars().filter_map(|c| c.to_digit(10)) {
+        if let Some(count) = uses.get_mut(door as usize) {
+            *count += 1;
+        }
+    }
+    let miss = 1.0 - 1.0 / n as f64;
+    let covered: f64 = uses.iter().map(|&k| n as f64 * (1.0 - miss.powi(k))).sum();
+    covered / (n * doors) as f64
+}
+
+/// One single-door plan per door of the starting room, `"0"` to `"5"`.
+///
+/// Two doorways each reveal the label behind every door of the starting room, which pins down
+/// the starting room's edges in the reconstruction before any longer walk.
+pub fn probe_start_plan() -> Vec<String> {
+    (0..DOORS).map(|door| door.to_string()).collect()
+}
+
+/// Runs [`probe_start_plan`] as the first call, then hands over to `inner`.
+#[derive(Debug, Clone)]
+pub struct ProbeStartFirst<S> {
+    pub inner: S,
+    probed: bool,
+}
+
+impl<S> ProbeStartFirst<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            probed: false,
+        }
+    }
+}
+
+impl<S: ExploreStrategy> ExploreStrategy for ProbeStartFirst<S> {
+    fn next_plans(&mut self, partial: Option<&PartialMap>, rng: &mut dyn RngCore) -> Vec<String> {
+        if !self.probed {
+            self.probed = true;
+            return probe_start_plan();
+        }
+        self.inner.next_plans(partial, rng)
+    }
+}
+
+/// Independent walks, uniformly random unless [`RandomWalk::with_plan_gen`] says otherwise.
+#[derive(Debug, Clone)]
+pub struct RandomWalk {
+    pub plan_len: usize,
+    pub plans_per_call: usize,
+}
+
+impl RandomWalk {
+    /// The same walks, through the doors `plan_gen` allows in its order.
+    pub fn with_plan_gen(self, plan_gen: PlanGenConfig) -> WithPlanGen<Self> {
+        WithPlanGen {
+            inner: self,
+            plan_gen,
+        }
+    }
+
+    fn plans(&self, plan_gen: &PlanGenConfig, rng: &mut dyn RngCore) -> Vec<String> {
+        (0..self.plans_per_call)
+            .map(|_| plan_gen.doors(self.plan_len, rng))
+            .collect()
+    }
+}
+
+impl ExploreStrategy for RandomWalk {
+    fn next_plans(&mut self, _partial: Option<&PartialMap>, rng: &mut dyn RngCore) -> Vec<String> {
+        self.plans(&PlanGenConfig::default(), rng)
+    }
+}
+
+impl ExploreStrategy for WithPlanGen<RandomWalk> {
+    fn next_plans(&mut self, _partial: Option<&PartialMap>, rng: &mut dyn RngCore) -> Vec<String> {
+        self.inner.plans(&self.plan_gen, rng)
+    }
+}
+
+/// Walks over known doors to a room with an unexplored door, takes that door, and continues
+/// randomly. Falls back to random walks while nothing is known or everything is explored.
+#[derive(Debug, Clone)]
+pub struct Frontier {
+    pub plan_len: usize,
+    pub plans_per_call: usize,
+}
+
+impl Frontier {
+    /// The same walks, through the doors `plan_gen` allows in its order.
+    pub fn with_plan_gen(self, plan_gen: PlanGenConfig) -> WithPlanGen<Self> {
+        WithPlanGen {
+            inner: self,
+            plan_gen,
+        }
+    }
+
+    fn plans(
+        &self,
+        partial: Option<&PartialMap>,
+        plan_gen: &PlanGenConfig,
+        rng: &mut dyn RngCore,
+    ) -> Vec<String> {
+        let Some(partial) = partial.filter(|p| p.room_count() > 0) else {
+            return RandomWalk {
+                plan_len: self.plan_len,
+                plans_per_call: self.plans_per_call,
+            }
+            .plans(plan_gen, rng);
+        };
+
+        let allowed = &plan_gen.allowed_doors;
+        let routes = routes_over(partial, 0, allowed);
+        let mut targets: Vec<String> = (0..partial.room_count())
+            .flat_map(|room| {
+                allowed
+                    .iter()
+                    .filter(move |&&door| partial.edges[room][door as usize].is_none())
+                    .map(move |&door| (room, door))
+            })
+            .filter_map(|(room, door)| {
+                routes[room]
+                    .as_ref()
+                    .map(|route| format!("{}{}", route, door))
+            })
+            .filter(|plan| plan.len() <= self.plan_len)
+            .collect();
+        if plan_gen.ordering == DoorOrdering::Random {
+            targets.shuffle(rng);
+        }
+
+        (0..self.plans_per_call)
+            .map(|i| {
+                let prefix = targets.get(i).cloned().unwrap_or_default();
+                let tail = self.plan_len - prefix.len();
+                prefix + &plan_gen.doors(tail, rng)
+            })
+            .collect()
+    }
+}
+
+impl ExploreStrategy for Frontier {
+    fn next_plans(&mut self, partial: Option<&PartialMap>, rng: &mut dyn RngCore) -> Vec<String> {
+        self.plans(partial, &PlanGenConfig::default(), rng)
+    }
+}
+
+impl ExploreStrategy for WithPlanGen<Frontier> {
+    fn next_plans(&mut self, partial: Option<&PartialMap>, rng: &mut dyn RngCore) -> Vec<String> {
+        self.inner.plans(partial, &self.plan_gen, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use icfpc2025_common::AedificiumClient;
+    use icfpc2025_simulator::{GenerationMode, Simulator};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::reconstruct::{reconstruct, ReconstructOptions};
+
+    #[test]
+    fn test_random_walk_shape() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let plans = RandomWalk {
+            plan_len: 7,
+            plans_per_call: 3,
+        }
+        .next_plans(None, &mut rng);
+        assert_eq!(plans.len(), 3);
+        assert!(plans.iter().all(|p| p.len() == 7));
+    }
+
+    #[test]
+    fn test_estimate_coverage() {
+        let repetitive = estimate_coverage("000000", 6, DOORS);
+        let varied = estimate_coverage("012345", 6, DOORS);
+        let long = estimate_coverage(&"012345".repeat(6), 6, DOORS);
+        assert!(repetitive < varied);
+        assert!(varied < long);
+        assert!(long < 1.0);
+        assert!(estimate_coverage("0", 6, DOORS) < estimate_coverage("01", 6, DOORS));
+        assert_eq!(estimate_coverage("", 6, DOORS), 0.0);
+        // A single room has one transition per door.
+        assert_eq!(estimate_coverage("012345", 1, DOORS), 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_start_probes_determine_start_room() {
+        let mut simulator = Simulator::with_mode_seed(4, GenerationMode::Regular, 2).unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut strategy = ProbeStartFirst::new(RandomWalk {
+            plan_len: 3,
+            plans_per_call: 1,
+        });
+
+        let plans = strategy.next_plans(None, &mut rng);
+        assert_eq!(plans, ["0", "1", "2", "3", "4", "5"]);
+        let results = simulator.explore(plans.clone()).await.unwrap().results;
+        let reconstruction = reconstruct(
+            &plans,
+            &results,
+            4,
+            &ReconstructOptions::default(),
+            &mut rng,
+        )
+        .unwrap();
+
+        assert!(!reconstruction.partial_maps.is_empty());
+        for partial in &reconstruction.partial_maps {
+            for (door, target) in partial.edges[0].iter().enumerate() {
+                let target = target.expect("every start door was walked");
+                assert_eq!(partial.labels[target], results[door][1]);
+            }
+        }
+
+        // Later calls come from the wrapped strategy.
+        assert_eq!(strategy.next_plans(None, &mut rng)[0].len(), 3);
+    }
+
+    #[test]
+    fn test_frontier_targets_unexplored_doors() {
+        // Every door is known except door 2 of room 1, reachable from the start via door 4.
+        let mut partial = PartialMap {
+            labels: vec![0, 1],
+            edges: vec![[Some(0); DOORS], [Some(1); DOORS]],
+        };
+        partial.edges[0][4] = Some(1);
+        partial.edges[1][0] = Some(0);
+        partial.edges[1][2] = None;
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let plans = Frontier {
+            plan_len: 5,
+            plans_per_call: 2,
+        }
+        .next_plans(Some(&partial), &mut rng);
+        assert_eq!(plans.len(), 2);
+        assert!(plans[0].starts_with("42"));
+        assert!(plans.iter().all(|p| p.len() == 5));
+    }
+
+    #[test]
+    fn test_plans_stick_to_allowed_doors() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut walk = RandomWalk {
+            plan_len: 30,
+            plans_per_call: 4,
+        }
+        .with_plan_gen(PlanGenConfig::new(vec![1, 4], DoorOrdering::Random).unwrap());
+        let plans = walk.next_plans(None, &mut rng);
+        assert!(plans
+            .iter()
+            .all(|p| p.chars().all(|c| c == '1' || c == '4')));
+        assert!(plans.iter().any(|p| p.contains('1') && p.contains('4')));
+
+        walk.plan_gen = PlanGenConfig::new(vec![3, 0, 5], DoorOrdering::Fixed).unwrap();
+        walk.inner.plan_len = 7;
+        assert!(walk
+            .next_plans(None, &mut rng)
+            .iter()
+            .all(|p| p == "3053053"));
+
+        // Room 1 is behind doors 1 and 2 of the start. Routes would take door 1, the smaller,
+        // but it isn't allowed. Doors 5 and 0 of room 1 are open, and so is door 0 of the start.
+        let mut partial = PartialMap {
+            labels: vec![0, 1],
+            edges: vec![[Some(0); DOORS], [Some(1); DOORS]],
+        };
+        partial.edges[0][1] = Some(1);
+        partial.edges[0][2] = Some(1);
+        partial.edges[0][0] = None;
+        partial.edges[1][5] = None;
+        partial.edges[1][0] = None;
+        let mut frontier = Frontier {
+            plan_len: 5,
+            plans_per_call: 3,
+        }
+        .with_plan_gen(PlanGenConfig::new(vec![5, 2, 0], DoorOrdering::Fixed).unwrap());
+        let plans = frontier.next_plans(Some(&partial), &mut rng);
+        assert_eq!(plans, ["05205", "25520", "20520"]);
+
+        assert!(PlanGenConfig::new(vec![0, DOORS as u8], DoorOrdering::Fixed).is_err());
+    }
+}
@@ -0,0 +1,39 @@
+use icfpc2025_common::AedificiumClient;
+use icfpc2025_simulator::{GenerationMode, Simulator};
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::driver::{solve, ExploreConfig, SolveReport};
+use crate::strategy::ExploreStrategy;
+
+/// Solves the regular `rooms`-room library generated from `seed` offline with `strategy`, and
+/// panics unless the simulator accepts the guess as the same library up to renumbering. The
+/// panic message shows the true map and the guess as DOT.
+pub async fn assert_reconstructs<S>(seed: u64, rooms: usize, strategy: &mut S) -> SolveReport
+where
+    S: ExploreStrategy + ?Sized,
+{
+    let mut simulator = Simulator::with_mode_seed(rooms, GenerationMode::Regular, seed).unwrap();
+    let truth = simulator.get_actual_map();
+    let config = ExploreConfig::new(rooms, simulator.remaining_doorways());
+    let mut rng = StdRng::seed_from_u64(seed);
+    let report = match solve(&mut simulator, strategy, &config, &mut rng).await {
+        Ok(report) => report,
+        Err(e) => panic!(
+            "seed {}, {} rooms: solve failed: {:#}\ntruth:\n{}",
+            seed,
+            rooms,
+            e,
+            truth.to_dot()
+        ),
+    };
+    assert!(
+        simulator.guess(report.map.clone()).await.unwrap().correct,
+        "seed {}, {} rooms: wrong guess after {} explores\ntruth:\n{}guess:\n{}",
+        seed,
+        rooms,
+        report.explores,
+        truth.to_dot(),
+        report.map.to_dot()
+    );
+    report
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+/// Calls spent trying out batch sizes before settling on one.
+const PROBE_CALLS: usize = 4;
+
+/// How much faster a doubled batch has to explore to be worth keeping.
+const MIN_SPEEDUP: f64 = 1.2;
+
+/// Finds how many plans to send per explore call. Starts with one plan, doubles the batch after
+/// every call as long as that explores noticeably more doorways per second, and settles on the
+/// best size found after a few calls or as soon as doubling stops paying off.
+///
+/// When each call costs mostly a fixed round trip, bigger batches get through the budget in
+/// fewer round trips and the tuner grows them. When the time goes into the plans themselves,
+/// batching gains nothing and the tuner stays small, so the strategy gets to see the results
+/// of every call before choosing the next plans.
+#[derive(Debug, Clone)]
+pub struct BatchTuner {
+    size: usize,
+    max_size: usize,
+    probes_left: usize,
+    /// Batch size and doorways per second of the fastest call so far.
+    best: Option<(usize, f64)>,
+    settled: bool,
+}
+
+impl BatchTuner {
+    /// A tuner that never sends more than `max_size` plans per call.
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            size: 1,
+            max_size: max_size.max(1),
+            probes_left: PROBE_CALLS,
+            best: None,
+            settled: false,
+        }
+    }
+
+    /// Plans to send in the next call.
+    pub fn batch_size(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the tuner is done trying out sizes.
+    pub fn is_settled(&self) -> bool {
+        self.settled
+    }
+
+    /// Records an explore call of `plans` plans that walked `doorways` doorways in `elapsed`.
+    pub fn record(&mut self, plans: usize, doorways: usize, elapsed: Duration) {
+        if self.settled || plans == 0 {
+            return;
+        }
+        self.probes_left = self.probes_left.saturating_sub(1);
+        let rate = doorways as f64 / elapsed.as_secs_f64().max(1e-6);
+        match self.best {
+            Some((_, best_rate)) if rate < best_rate * MIN_SPEEDUP => self.settle(),
+            _ => {
+                self.best = Some((plans, rate));
+                if self.probes_left == 0 || plans < self.size || self.size >= self.max_size {
+                    self.settle();
+                } else {
+                    self.size = (self.size * 2).min(self.max_size);
+                }
+            }
+        }
+    }
+
+    fn settle(&mut self) {
+        if let Some((size, _)) = self.best {
+            self.size = size;
+        }
+        self.settled = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds the tuner calls that take `per_call` plus `per_plan` for every plan, each plan
+    /// walking five doorways, until it settles.
+    fn tune(per_call: Duration, per_plan: Duration) -> BatchTuner {
+        let mut tuner = BatchTuner::new(64);
+        for _ in 0..10 {
+            let plans = tuner.batch_size();
+            tuner.record(plans, 5 * plans, per_call + per_plan * plans as u32);
+        }
+        assert!(tuner.is_settled());
+        tuner
+    }
+
+    #[test]
+    fn test_tuner_grows_batches_when_latency_dominates() {
+        let tuner = tune(Duration::from_millis(200), Duration::from_millis(1));
+        assert_eq!(tuner.batch_size(), 1 << (PROBE_CALLS - 1));
+
+        let tuner = tune(Duration::ZERO, Duration::from_millis(50));
+        assert_eq!(tuner.batch_size(), 1);
+
+        // Capped, however slow the round trip.
+        let mut tuner = BatchTuner::new(3);
+        for _ in 0..PROBE_CALLS {
+            let plans = tuner.batch_size();
+            tuner.record(plans, 5 * plans, Duration::from_secs(1));
+        }
+        assert_eq!(tuner.batch_size(), 3);
+    }
+}
@@ -0,0 +1,70 @@
+//! Map reconstruction for the browser.
+//!
+//! [`reconstruct_json`] is exported through `wasm-bindgen` when building for `wasm32`; on other
+//! targets it is a plain function, so the crate builds and tests like the rest of the workspace.
+//! See `examples/index.html`.
+
+use icfpc2025_solver::{pick_deterministic, reconstruct as solve, ReconstructOptions};
+use rand::{rngs::StdRng, SeedableRng};
+use serde::Deserialize;
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// Observations as printed by `aedificium explore`, plus the plans that produced them.
+#[derive(Deserialize)]
+struct Observations {
+    plans: Vec<String>,
+    results: Vec<Vec<usize>>,
+}
+
+fn error_json(message: impl ToString) -> String {
+    serde_json::json!({ "error": message.to_string() }).to_string()
+}
+
+/// Reconstructs a map from `{"plans": [...], "results": [...]}` and returns it as JSON, or
+/// `{"error": "..."}` if the input is invalid or no map matches.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen)]
+pub fn reconstruct_json(observations_json: &str, rooms: usize) -> String {
+    let observations: Observations = match serde_json::from_str(observations_json) {
+        Ok(observations) => observations,
+        Err(e) => return error_json(format!("Invalid observations: {}", e)),
+    };
+
+    // Fixed seed, so the page shows the same map for the same input.
+    let mut rng = StdRng::seed_from_u64(0);
+    let reconstruction = match solve(
+        &observations.plans,
+        &observations.results,
+        rooms,
+        &ReconstructOptions::default(),
+        &mut rng,
+    ) {
+        Ok(reconstruction) => reconstruction,
+        Err(e) => return error_json(e),
+    };
+
+    match pick_deterministic(&reconstruction.candidates) {
+        Some(map) => serde_json::to_string(map).unwrap_or_else(error_json),
+        None => error_json(format!(
+            "No map with {} rooms matches the observations",
+            rooms
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconstruct_json() {
+        let input = r#"{"plans": ["0", "1"], "results": [[0, 1], [0, 1]]}"#;
+        let map: serde_json::Value = serde_json::from_str(&reconstruct_json(input, 2)).unwrap();
+        assert_eq!(map["rooms"], serde_json::json!([0, 1]));
+        assert_eq!(map["startingRoom"], 0);
+
+        let error: serde_json::Value =
+            serde_json::from_str(&reconstruct_json("not json", 2)).unwrap();
+        assert!(error["error"].is_string());
+    }
+}
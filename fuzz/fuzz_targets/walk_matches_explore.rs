@@ -0,0 +1,98 @@
+//! Explores a random plan through a random library with the simulator and checks the labels
+//! against [`Map::walk`]. The two walk the library separately, so any difference is a bug in
+//! one of them.
+
+#![no_main]
+
+use arbitrary::Arbitrary;
+use icfpc2025_common::{AedificiumClient, Map, MapBuilder, RESET};
+use icfpc2025_simulator::Simulator;
+use libfuzzer_sys::fuzz_target;
+
+const DOORS: usize = 6;
+const MAX_ROOMS: usize = 8;
+
+#[derive(Debug, Arbitrary)]
+struct Input {
+    /// A label per room, up to [`MAX_ROOMS`]. There is always at least one room.
+    labels: Vec<u8>,
+    /// Each pair connects two of the doors still unconnected, picked by index. A second index
+    /// past the end connects the first door to itself.
+    pairs: Vec<(u8, u8)>,
+    /// A door per step, [`RESET`] for the value past the last door, or a mark for the one after
+    /// that, with a label from the rest of the value.
+    plan: Vec<u8>,
+}
+
+impl Input {
+    fn map(&self) -> Map {
+        let room_count = self.labels.len().clamp(1, MAX_ROOMS);
+        let mut builder = MapBuilder::new();
+        for room in 0..room_count {
+            let label = self.labels.get(room).map_or(0, |&label| label as usize % 4);
+            builder = builder.room(room, label);
+        }
+
+        let mut free: Vec<(usize, usize)> = (0..room_count)
+            .flat_map(|room| (0..DOORS).map(move |door| (room, door)))
+            .collect();
+        for &(a, b) in &self.pairs {
+            if free.is_empty() {
+                break;
+            }
+            let (room_a, door_a) = free.swap_remove(a as usize % free.len());
+            let b = b as usize % (free.len() + 1);
+            let (room_b, door_b) = if b == free.len() {
+                (room_a, door_a)
+            } else {
+                free.swap_remove(b)
+            };
+            builder = builder.connect(room_a, door_a, room_b, door_b);
+        }
+        builder.build().expect("only free doors are connected")
+    }
+
+    /// The plan, cut to fit the doorway budget of `room_count` rooms after the starting room's
+    /// observation.
+    fn plan(&self, room_count: usize) -> String {
+        self.plan
+            .iter()
+            .take(18 * room_count - 1)
+            .map(|&step| match step as usize % (DOORS + 2) {
+                DOORS => RESET.to_string(),
+                door if door > DOORS => format!("[{}]", step as usize / (DOORS + 2) % 4),
+                door => door.to_string(),
+            })
+            .collect()
+    }
+}
+
+fuzz_target!(|input: Input| {
+    let map = input.map();
+    let plan = input.plan(map.rooms.len());
+    // Libraries with rooms unreachable from the start aren't simulated.
+    let Ok(mut simulator) = Simulator::from_map(&map) else {
+        return;
+    };
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let explored = runtime.block_on(simulator.explore(vec![plan.clone()]));
+    match (explored, map.walk(&plan)) {
+        (Ok(response), Ok(labels)) => {
+            assert_eq!(
+                response.results,
+                vec![labels],
+                "plan {:?} over {:?}",
+                plan,
+                map
+            )
+        }
+        // Both stop at an unconnected door.
+        (Err(_), Err(_)) => {}
+        (explored, walked) => panic!(
+            "explore gave {:?} but walk gave {:?} for plan {:?} over {:?}",
+            explored, walked, plan, map
+        ),
+    }
+});